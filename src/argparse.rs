@@ -1,11 +1,28 @@
 use clap::{Arg, App};
 
+/// How `--tokens` prints the token stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenFormat {
+    Text,
+    Json,
+}
+
+/// How errors are reported on stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Human, // rustc-style, with source snippets
+    Json,  // one JSON object per line, for editors and other tools
+}
+
 pub struct Arguments {
     pub input_file: String,     // input file
     pub output_file: String,    // output file
     pub print_ast: bool,        // flag to show ast.
+    pub token_format: Option<TokenFormat>, // set if the token stream should be dumped.
+    pub error_format: ErrorFormat,
 }
 
+#[allow(clippy::new_without_default)]
 impl Arguments{
     pub fn new() -> Arguments{
         let default_output_file = "a.s";
@@ -19,29 +36,58 @@ impl Arguments{
                         .long("input")
                         .required(true)
                         .takes_value(true)
-                        .help("Input file path"))
+                        .help("Input file path (- to read from stdin)"))
                 .arg(Arg::with_name("output")
                         .short("o")
                         .long("output")
                         .required(false)
                         .takes_value(true)
-                        .help("Output file path"))
+                        .help("Output assembly file path (defaults to a.s)"))
                 .arg(Arg::with_name("ast")
                         .short("a")
                         .long("ast")
                         .required(false)
                         .takes_value(false)
                         .help("Flag to print the AST"))
+                .arg(Arg::with_name("tokens")
+                        .long("tokens")
+                        .required(false)
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .possible_values(&["text", "json"])
+                        .help("Print every token with its position and exit (--tokens=json for JSON lines)"))
+                .arg(Arg::with_name("error-format")
+                        .long("error-format")
+                        .required(false)
+                        .takes_value(true)
+                        .require_equals(true)
+                        .possible_values(&["human", "json"])
+                        .help("How to print errors (--error-format=json for one JSON object per error)"))
                 .get_matches();
         
         let input_file = matches.value_of("input").unwrap();
         let output_file = matches.value_of("output").unwrap_or(default_output_file);
         let print_ast = matches.is_present("ast");
+        let token_format = if matches.is_present("tokens") {
+            match matches.value_of("tokens") {
+                Some("json") => Some(TokenFormat::Json),
+                _ => Some(TokenFormat::Text),
+            }
+        } else {
+            None
+        };
+        let error_format = match matches.value_of("error-format") {
+            Some("json") => ErrorFormat::Json,
+            _ => ErrorFormat::Human,
+        };
 
         Arguments { 
             input_file: input_file.to_string(), 
             output_file: output_file.to_string(), 
-            print_ast: print_ast 
+            print_ast,
+            token_format,
+            error_format,
         }
     }
-}
\ No newline at end of file
+}
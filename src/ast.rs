@@ -0,0 +1,400 @@
+use std::fmt;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use crate::token::Span;
+
+/// An identifier, along with where it was written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+/// program = "package" identifier ';' { ImportDecl ';' } { TopLevelDecl ';' } .
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub package: Ident,
+    pub imports: Vec<ImportSpec>,
+    pub decls: Vec<TopLevelDecl>,
+    pub span: Span,
+}
+
+/// A single imported package, e.g. `"fmt"` or `f "fmt"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportSpec {
+    pub alias: Option<Ident>,
+    pub path: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum TopLevelDecl {
+    Decl(Decl),
+    Func(FuncDecl),
+    Bad(Span), // a declaration with a syntax error, skipped by the parser
+}
+
+impl TopLevelDecl {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Decl(decl) => decl.span(),
+            Self::Func(func) => func.span,
+            Self::Bad(span) => *span,
+        }
+    }
+}
+
+/// A function or method declaration. Methods have a receiver.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuncDecl {
+    pub receiver: Option<Param>,
+    pub name: Ident,
+    pub type_params: Vec<TypeParam>,
+    pub signature: Signature,
+    pub body: Option<Block>,
+    pub span: Span,
+}
+
+/// A const, type or var declaration. Grouped declarations hold more than one spec.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decl {
+    Const(Vec<ConstSpec>, Span),
+    Type(Vec<TypeSpec>, Span),
+    Var(Vec<VarSpec>, Span),
+}
+
+impl Decl {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Const(_, span) | Self::Type(_, span) | Self::Var(_, span) => *span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstSpec {
+    pub names: Vec<Ident>,
+    pub ty: Option<Type>,
+    pub values: Vec<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeSpec {
+    pub name: Ident,
+    pub type_params: Vec<TypeParam>,
+    pub alias: bool, // `type A = B`
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarSpec {
+    pub names: Vec<Ident>,
+    pub ty: Option<Type>,
+    pub values: Vec<Expr>,
+    pub span: Span,
+}
+
+/// TypeParamDecl = IdentifierList TypeConstraint .
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeParam {
+    pub names: Vec<Ident>,
+    pub constraint: Vec<TypeTerm>,
+    pub span: Span,
+}
+
+/// TypeTerm = Type | "~" Type .
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeTerm {
+    pub underlying: bool,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Param>,
+    pub results: Vec<Param>,
+    pub span: Span,
+}
+
+/// A single parameter or result. `a, b int` is two params sharing the same type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Option<Ident>,
+    pub ty: Type,
+    pub variadic: bool,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Type {
+    pub kind: TypeKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeKind {
+    Name(Ident, Vec<Type>),             // TypeName [ TypeArgs ]
+    Array(Option<Box<Expr>>, Box<Type>), // length is None for `[...]T` in composite literals
+    Slice(Box<Type>),
+    Struct(Vec<FieldDecl>),
+    Pointer(Box<Type>),
+    Function(Signature),
+    Interface(Vec<InterfaceElem>),
+    Map(Box<Type>, Box<Type>),
+    Channel(ChanDir, Box<Type>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChanDir {
+    Both, // chan T
+    Send, // chan<- T
+    Recv, // <-chan T
+}
+
+/// A struct field. Embedded fields have no names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDecl {
+    pub names: Vec<Ident>,
+    pub ty: Type,
+    pub embedded: bool,
+    pub tag: Option<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterfaceElem {
+    Method(Ident, Signature),
+    Union(Vec<TypeTerm>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum StmtKind {
+    Decl(Decl),
+    Labeled(Ident, Box<Stmt>),
+    Expr(Expr),
+    Send(Expr, Expr),                             // channel <- value
+    IncDec(Expr, bool),                           // true for ++
+    Assign(Vec<Expr>, Option<BinaryOp>, Vec<Expr>), // the op is set for compound assignments like +=
+    ShortVarDecl(Vec<Ident>, Vec<Expr>),
+    Go(Expr),
+    Defer(Expr),
+    Return(Vec<Expr>),
+    Break(Option<Ident>),
+    Continue(Option<Ident>),
+    Goto(Ident),
+    Fallthrough,
+    Block(Block),
+    If(IfStmt),
+    Switch(SwitchStmt),
+    TypeSwitch(TypeSwitchStmt),
+    Select(Vec<CommClause>),
+    For(ForStmt),
+    Range(RangeStmt),
+    Empty,
+    Bad, // a statement with a syntax error, skipped by the parser
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfStmt {
+    pub init: Option<Box<Stmt>>,
+    pub cond: Expr,
+    pub then: Block,
+    pub otherwise: Option<Box<Stmt>>, // either another If or a Block
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwitchStmt {
+    pub init: Option<Box<Stmt>>,
+    pub tag: Option<Expr>,
+    pub clauses: Vec<CaseClause>,
+}
+
+/// A case of an expression switch. `default` has no expressions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseClause {
+    pub exprs: Vec<Expr>,
+    pub is_default: bool,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+/// switch [ init; ] [ binding := ] expr.(type) { ... }
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeSwitchStmt {
+    pub init: Option<Box<Stmt>>,
+    pub binding: Option<Ident>,
+    pub expr: Expr,
+    pub clauses: Vec<TypeCaseClause>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeCaseClause {
+    pub types: Vec<Type>,
+    pub is_default: bool,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+/// A case of a select statement. `default` has no communication.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommClause {
+    pub comm: Option<Box<Stmt>>,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+/// for [ init ]; [ cond ]; [ post ] { ... }, which also covers `for cond { ... }` and `for { ... }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForStmt {
+    pub init: Option<Box<Stmt>>,
+    pub cond: Option<Expr>,
+    pub post: Option<Box<Stmt>>,
+    pub body: Block,
+}
+
+/// for [ key [, value ] ( = | := ) ] range expr { ... }
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeStmt {
+    pub key: Option<Expr>,
+    pub value: Option<Expr>,
+    pub define: bool, // true for :=
+    pub expr: Expr,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Ident(String),
+    IntLit(String, BigInt), // the literal as written, along with its exact value
+    FloatLit(String, BigRational),
+    CharLit(String, char), // the literal as written, along with its value
+    StringLit(String, Vec<u8>),
+    CompositeLit(Option<Box<Type>>, Vec<KeyedElement>), // the type is elided for nested literal values
+    FuncLit(Signature, Block),
+    Paren(Box<Expr>),
+    Selector(Box<Expr>, Ident),
+    Index(Box<Expr>, Box<Expr>),
+    Instantiation(Box<Expr>, Vec<Expr>), // f[int, string], when there's more than one type argument
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>, Option<Box<Expr>>),
+    TypeAssert(Box<Expr>, Option<Box<Type>>), // None for `.(type)` in a type switch
+    Call(Box<Expr>, Vec<Expr>, bool),          // true if the last argument is followed by `...`
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Type(Box<Type>), // a type used as an expression, e.g. in `make([]int, 3)` or `[]byte(s)`
+}
+
+/// KeyedElement = [ Key ":" ] Element .
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyedElement {
+    pub key: Option<Expr>,
+    pub value: Expr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Plus,    // +
+    Neg,     // -
+    Not,     // !
+    BitNot,  // ^
+    Deref,   // *
+    AddrOf,  // &
+    Receive, // <-
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    LogicOr,   // ||
+    LogicAnd,  // &&
+    Eq,        // ==
+    NotEq,     // !=
+    Less,      // <
+    LessEq,    // <=
+    Greater,   // >
+    GreaterEq, // >=
+    Add,       // +
+    Sub,       // -
+    BitOr,     // |
+    BitXor,    // ^
+    Mul,       // *
+    Div,       // /
+    Mod,       // %
+    LShift,    // <<
+    RShift,    // >>
+    BitAnd,    // &
+    BitClear,  // &^
+}
+
+impl BinaryOp {
+    /// Binding strength of the operator, from 1 (||) to 5 (mul_op).
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Self::LogicOr => 1,
+            Self::LogicAnd => 2,
+            Self::Eq | Self::NotEq | Self::Less | Self::LessEq | Self::Greater | Self::GreaterEq => 3,
+            Self::Add | Self::Sub | Self::BitOr | Self::BitXor => 4,
+            Self::Mul | Self::Div | Self::Mod | Self::LShift | Self::RShift | Self::BitAnd | Self::BitClear => 5,
+        }
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Plus => "+",
+            Self::Neg => "-",
+            Self::Not => "!",
+            Self::BitNot => "^",
+            Self::Deref => "*",
+            Self::AddrOf => "&",
+            Self::Receive => "<-",
+        })
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::LogicOr => "||",
+            Self::LogicAnd => "&&",
+            Self::Eq => "==",
+            Self::NotEq => "!=",
+            Self::Less => "<",
+            Self::LessEq => "<=",
+            Self::Greater => ">",
+            Self::GreaterEq => ">=",
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::BitOr => "|",
+            Self::BitXor => "^",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+            Self::LShift => "<<",
+            Self::RShift => ">>",
+            Self::BitAnd => "&",
+            Self::BitClear => "&^",
+        })
+    }
+}
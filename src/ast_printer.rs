@@ -0,0 +1,468 @@
+use crate::ast::*;
+use crate::token::Span;
+
+/// Renders the AST as an indented tree, one node per line, each followed by its span.
+pub fn print_program(program: &Program) -> String {
+    let mut printer = AstPrinter {
+        output: String::new(),
+        indent: 0,
+    };
+    printer.program(program);
+    printer.output
+}
+
+struct AstPrinter {
+    output: String,
+    indent: usize,
+}
+
+impl AstPrinter {
+    fn line(&mut self, text: &str, span: Span) {
+        self.output.push_str(&"  ".repeat(self.indent));
+        self.output.push_str(&format!("{} [{}]\n", text, span));
+    }
+
+    /// Prints a line for a node, then its children one level deeper.
+    fn node(&mut self, text: &str, span: Span, children: impl FnOnce(&mut Self)) {
+        self.line(text, span);
+        self.indent += 1;
+        children(self);
+        self.indent -= 1;
+    }
+
+    /// A grouping line (like "Params" or "Else") that has no span of its own.
+    fn group(&mut self, label: &str, children: impl FnOnce(&mut Self)) {
+        self.output.push_str(&"  ".repeat(self.indent));
+        self.output.push_str(label);
+        self.output.push('\n');
+        self.indent += 1;
+        children(self);
+        self.indent -= 1;
+    }
+
+    fn program(&mut self, program: &Program) {
+        self.node(&format!("Program {}", program.package.name), program.span, |p| {
+            for import in &program.imports {
+                let text = match &import.alias {
+                    Some(alias) => format!("Import {} \"{}\"", alias.name, import.path),
+                    None => format!("Import \"{}\"", import.path),
+                };
+                p.line(&text, import.span);
+            }
+            for decl in &program.decls {
+                match decl {
+                    TopLevelDecl::Decl(decl) => p.decl(decl),
+                    TopLevelDecl::Func(func) => p.func_decl(func),
+                    TopLevelDecl::Bad(span) => p.line("BadDecl", *span),
+                }
+            }
+        });
+    }
+
+    fn func_decl(&mut self, func: &FuncDecl) {
+        let kind = if func.receiver.is_some() { "MethodDecl" } else { "FuncDecl" };
+        self.node(&format!("{} {}", kind, func.name.name), func.span, |p| {
+            if let Some(receiver) = &func.receiver {
+                p.group("Receiver", |p| p.param(receiver));
+            }
+            p.type_params(&func.type_params);
+            p.signature(&func.signature);
+            if let Some(body) = &func.body {
+                p.block(body);
+            }
+        });
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::Const(specs, span) => self.node("ConstDecl", *span, |p| {
+                for spec in specs {
+                    p.node(&format!("ConstSpec {}", names(&spec.names)), spec.span, |p| {
+                        if let Some(ty) = &spec.ty {
+                            p.ty(ty);
+                        }
+                        p.exprs(&spec.values);
+                    });
+                }
+            }),
+            Decl::Type(specs, span) => self.node("TypeDecl", *span, |p| {
+                for spec in specs {
+                    let kind = if spec.alias { "AliasSpec" } else { "TypeSpec" };
+                    p.node(&format!("{} {}", kind, spec.name.name), spec.span, |p| {
+                        p.type_params(&spec.type_params);
+                        p.ty(&spec.ty);
+                    });
+                }
+            }),
+            Decl::Var(specs, span) => self.node("VarDecl", *span, |p| {
+                for spec in specs {
+                    p.node(&format!("VarSpec {}", names(&spec.names)), spec.span, |p| {
+                        if let Some(ty) = &spec.ty {
+                            p.ty(ty);
+                        }
+                        p.exprs(&spec.values);
+                    });
+                }
+            }),
+        }
+    }
+
+    fn type_params(&mut self, params: &[TypeParam]) {
+        for param in params {
+            self.node(&format!("TypeParam {}", names(&param.names)), param.span, |p| p.type_terms(&param.constraint));
+        }
+    }
+
+    fn type_terms(&mut self, terms: &[TypeTerm]) {
+        for term in terms {
+            if term.underlying {
+                self.group("Underlying", |p| p.ty(&term.ty));
+            } else {
+                self.ty(&term.ty);
+            }
+        }
+    }
+
+    fn signature(&mut self, signature: &Signature) {
+        self.node("Signature", signature.span, |p| {
+            if !signature.params.is_empty() {
+                p.group("Params", |p| signature.params.iter().for_each(|param| p.param(param)));
+            }
+            if !signature.results.is_empty() {
+                p.group("Results", |p| signature.results.iter().for_each(|param| p.param(param)));
+            }
+        });
+    }
+
+    fn param(&mut self, param: &Param) {
+        let mut text = "Param".to_string();
+        if let Some(name) = &param.name {
+            text.push(' ');
+            text.push_str(&name.name);
+        }
+        if param.variadic {
+            text.push_str(" ...");
+        }
+        self.node(&text, param.span, |p| p.ty(&param.ty));
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        match &ty.kind {
+            TypeKind::Name(name, args) => self.node(&format!("TypeName {}", name.name), ty.span, |p| {
+                args.iter().for_each(|arg| p.ty(arg));
+            }),
+            TypeKind::Array(len, elem) => self.node("ArrayType", ty.span, |p| {
+                match len {
+                    Some(len) => p.expr(len),
+                    None => p.group("...", |_| {}),
+                }
+                p.ty(elem);
+            }),
+            TypeKind::Slice(elem) => self.node("SliceType", ty.span, |p| p.ty(elem)),
+            TypeKind::Struct(fields) => self.node("StructType", ty.span, |p| {
+                for field in fields {
+                    let mut text = if field.embedded {
+                        "EmbeddedField".to_string()
+                    } else {
+                        format!("Field {}", names(&field.names))
+                    };
+                    if let Some(tag) = &field.tag {
+                        text.push_str(&format!(" \"{}\"", tag));
+                    }
+                    p.node(&text, field.span, |p| p.ty(&field.ty));
+                }
+            }),
+            TypeKind::Pointer(elem) => self.node("PointerType", ty.span, |p| p.ty(elem)),
+            TypeKind::Function(signature) => self.node("FuncType", ty.span, |p| p.signature(signature)),
+            TypeKind::Interface(elems) => self.node("InterfaceType", ty.span, |p| {
+                for elem in elems {
+                    match elem {
+                        InterfaceElem::Method(name, signature) => {
+                            p.node(&format!("Method {}", name.name), name.span, |p| p.signature(signature));
+                        }
+                        InterfaceElem::Union(terms) => p.group("Union", |p| p.type_terms(terms)),
+                    }
+                }
+            }),
+            TypeKind::Map(key, value) => self.node("MapType", ty.span, |p| {
+                p.ty(key);
+                p.ty(value);
+            }),
+            TypeKind::Channel(dir, elem) => {
+                let text = match dir {
+                    ChanDir::Both => "ChanType",
+                    ChanDir::Send => "ChanType chan<-",
+                    ChanDir::Recv => "ChanType <-chan",
+                };
+                self.node(text, ty.span, |p| p.ty(elem));
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.node("Block", block.span, |p| p.stmts(&block.stmts));
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn optional_stmt(&mut self, label: &str, stmt: &Option<Box<Stmt>>) {
+        if let Some(stmt) = stmt {
+            self.group(label, |p| p.stmt(stmt));
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let span = stmt.span;
+        match &stmt.kind {
+            StmtKind::Decl(decl) => self.decl(decl),
+            StmtKind::Labeled(label, inner) => self.node(&format!("Labeled {}", label.name), span, |p| p.stmt(inner)),
+            StmtKind::Expr(expr) => self.node("ExprStmt", span, |p| p.expr(expr)),
+            StmtKind::Send(channel, value) => self.node("Send", span, |p| {
+                p.expr(channel);
+                p.expr(value);
+            }),
+            StmtKind::IncDec(expr, increment) => {
+                let text = if *increment { "IncDec ++" } else { "IncDec --" };
+                self.node(text, span, |p| p.expr(expr));
+            }
+            StmtKind::Assign(lhs, op, rhs) => {
+                let text = match op {
+                    Some(op) => format!("Assign {}=", op),
+                    None => "Assign =".to_string(),
+                };
+                self.node(&text, span, |p| {
+                    p.group("Lhs", |p| p.exprs(lhs));
+                    p.group("Rhs", |p| p.exprs(rhs));
+                });
+            }
+            StmtKind::ShortVarDecl(names_list, values) => {
+                self.node(&format!("ShortVarDecl {}", names(names_list)), span, |p| p.exprs(values));
+            }
+            StmtKind::Go(expr) => self.node("Go", span, |p| p.expr(expr)),
+            StmtKind::Defer(expr) => self.node("Defer", span, |p| p.expr(expr)),
+            StmtKind::Return(values) => self.node("Return", span, |p| p.exprs(values)),
+            StmtKind::Break(label) => self.line(&with_label("Break", label), span),
+            StmtKind::Continue(label) => self.line(&with_label("Continue", label), span),
+            StmtKind::Goto(label) => self.line(&format!("Goto {}", label.name), span),
+            StmtKind::Fallthrough => self.line("Fallthrough", span),
+            StmtKind::Block(block) => self.block(block),
+            StmtKind::If(if_stmt) => self.node("If", span, |p| {
+                p.optional_stmt("Init", &if_stmt.init);
+                p.group("Cond", |p| p.expr(&if_stmt.cond));
+                p.block(&if_stmt.then);
+                p.optional_stmt("Else", &if_stmt.otherwise);
+            }),
+            StmtKind::Switch(switch) => self.node("Switch", span, |p| {
+                p.optional_stmt("Init", &switch.init);
+                if let Some(tag) = &switch.tag {
+                    p.group("Tag", |p| p.expr(tag));
+                }
+                for clause in &switch.clauses {
+                    let text = if clause.is_default { "Default" } else { "Case" };
+                    p.node(text, clause.span, |p| {
+                        p.exprs(&clause.exprs);
+                        p.stmts(&clause.body);
+                    });
+                }
+            }),
+            StmtKind::TypeSwitch(switch) => {
+                let text = match &switch.binding {
+                    Some(binding) => format!("TypeSwitch {}", binding.name),
+                    None => "TypeSwitch".to_string(),
+                };
+                self.node(&text, span, |p| {
+                    p.optional_stmt("Init", &switch.init);
+                    p.group("Guard", |p| p.expr(&switch.expr));
+                    for clause in &switch.clauses {
+                        let text = if clause.is_default { "Default" } else { "Case" };
+                        p.node(text, clause.span, |p| {
+                            clause.types.iter().for_each(|ty| p.ty(ty));
+                            p.stmts(&clause.body);
+                        });
+                    }
+                });
+            }
+            StmtKind::Select(clauses) => self.node("Select", span, |p| {
+                for clause in clauses {
+                    match &clause.comm {
+                        Some(comm) => p.node("Case", clause.span, |p| {
+                            p.stmt(comm);
+                            p.stmts(&clause.body);
+                        }),
+                        None => p.node("Default", clause.span, |p| p.stmts(&clause.body)),
+                    }
+                }
+            }),
+            StmtKind::For(for_stmt) => self.node("For", span, |p| {
+                p.optional_stmt("Init", &for_stmt.init);
+                if let Some(cond) = &for_stmt.cond {
+                    p.group("Cond", |p| p.expr(cond));
+                }
+                p.optional_stmt("Post", &for_stmt.post);
+                p.block(&for_stmt.body);
+            }),
+            StmtKind::Range(range) => {
+                let text = if range.define { "Range :=" } else { "Range =" };
+                self.node(text, span, |p| {
+                    if let Some(key) = &range.key {
+                        p.group("Key", |p| p.expr(key));
+                    }
+                    if let Some(value) = &range.value {
+                        p.group("Value", |p| p.expr(value));
+                    }
+                    p.group("Expr", |p| p.expr(&range.expr));
+                    p.block(&range.body);
+                });
+            }
+            StmtKind::Empty => self.line("Empty", span),
+            StmtKind::Bad => self.line("Bad", span),
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn optional_expr(&mut self, label: &str, expr: &Option<Box<Expr>>) {
+        if let Some(expr) = expr {
+            self.group(label, |p| p.expr(expr));
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Ident(name) => self.line(&format!("Ident {}", name), span),
+            ExprKind::IntLit(text, _) => self.line(&format!("IntLit {}", text), span),
+            ExprKind::FloatLit(text, _) => self.line(&format!("FloatLit {}", text), span),
+            ExprKind::CharLit(raw, _) => self.line(&format!("CharLit {}", raw), span),
+            ExprKind::StringLit(raw, _) => self.line(&format!("StringLit {}", raw), span),
+            ExprKind::CompositeLit(ty, elements) => self.node("CompositeLit", span, |p| {
+                if let Some(ty) = ty {
+                    p.ty(ty);
+                }
+                for element in elements {
+                    match &element.key {
+                        Some(key) => p.group("KeyedElement", |p| {
+                            p.expr(key);
+                            p.expr(&element.value);
+                        }),
+                        None => p.expr(&element.value),
+                    }
+                }
+            }),
+            ExprKind::FuncLit(signature, body) => self.node("FuncLit", span, |p| {
+                p.signature(signature);
+                p.block(body);
+            }),
+            ExprKind::Paren(inner) => self.node("Paren", span, |p| p.expr(inner)),
+            ExprKind::Selector(base, field) => self.node(&format!("Selector .{}", field.name), span, |p| p.expr(base)),
+            ExprKind::Index(base, index) => self.node("Index", span, |p| {
+                p.expr(base);
+                p.expr(index);
+            }),
+            ExprKind::Instantiation(base, args) => self.node("Instantiation", span, |p| {
+                p.expr(base);
+                p.exprs(args);
+            }),
+            ExprKind::Slice(base, low, high, max) => self.node("SliceExpr", span, |p| {
+                p.expr(base);
+                p.optional_expr("Low", low);
+                p.optional_expr("High", high);
+                p.optional_expr("Max", max);
+            }),
+            ExprKind::TypeAssert(base, ty) => self.node("TypeAssert", span, |p| {
+                p.expr(base);
+                match ty {
+                    Some(ty) => p.ty(ty),
+                    None => p.group("type", |_| {}),
+                }
+            }),
+            ExprKind::Call(callee, args, spread) => {
+                let text = if *spread { "Call ..." } else { "Call" };
+                self.node(text, span, |p| {
+                    p.expr(callee);
+                    p.exprs(args);
+                });
+            }
+            ExprKind::Unary(op, operand) => self.node(&format!("Unary {}", op), span, |p| p.expr(operand)),
+            ExprKind::Binary(op, lhs, rhs) => self.node(&format!("Binary {}", op), span, |p| {
+                p.expr(lhs);
+                p.expr(rhs);
+            }),
+            ExprKind::Type(ty) => self.ty(ty),
+        }
+    }
+}
+
+fn names(idents: &[Ident]) -> String {
+    idents.iter().map(|ident| ident.name.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+fn with_label(text: &str, label: &Option<Ident>) -> String {
+    match label {
+        Some(label) => format!("{} {}", text, label.name),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+pub mod ast_printer_test {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn print_file(file_name: &str) -> String {
+        let program = Parser::new(Lexer::new(file_name.to_string()).unwrap()).parse().unwrap();
+        super::print_program(&program)
+    }
+
+    #[test]
+    pub fn given_function_should_print_indented_tree_with_spans() {
+        let expected = "\
+Program main [1:1-5:2]
+  FuncDecl add [3:1-5:2]
+    Signature [3:9-3:23]
+      Params
+        Param a [3:10-3:11]
+          TypeName int [3:15-3:18]
+        Param b [3:13-3:18]
+          TypeName int [3:15-3:18]
+      Results
+        Param [3:20-3:23]
+          TypeName int [3:20-3:23]
+    Block [3:24-5:2]
+      Return [4:5-4:19]
+        Binary + [4:12-4:19]
+          Ident a [4:12-4:13]
+          Binary * [4:16-4:19]
+            Ident b [4:16-4:17]
+            IntLit 2 [4:18-4:19]
+";
+        assert_eq!(print_file("testfiles/print_ast.test"), expected);
+    }
+
+    #[test]
+    pub fn given_full_program_should_print_every_statement_kind() {
+        let output = print_file("testfiles/valid_program.test");
+        for expected in [
+            "MethodDecl Move [42:1-45:2]",
+            "TypeParam T [47:10-47:18]",
+            "Range := [49:5-51:6]",
+            "Labeled outer [",
+            "Break outer [",
+            "TypeSwitch v [",
+            "Select [",
+            "Assign += [43:5-43:14]",
+            "Goto end [",
+            "SliceExpr [",
+        ] {
+            assert!(output.contains(expected), "missing {}", expected);
+        }
+    }
+}
@@ -0,0 +1,1681 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write;
+use num_traits::ToPrimitive;
+use crate::ast::*;
+use crate::constant::Value;
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::sema::{SymbolId, SymbolTable};
+use crate::token::Span;
+use crate::typechecker::{BasicKind, Ty, TypeInfo};
+
+/// Generates GNU assembler x86-64 code (System V ABI) for a whole program.
+///
+/// `table` and `info` are what `sema::analyze` and `typechecker::check` returned for the program, so it's
+/// already known to be valid. The generated file is meant to be linked against libc, e.g. `cc a.s -o a.out`.
+pub fn generate(program: &Program, table: &SymbolTable, info: &TypeInfo) -> Result<String, CodegenError> {
+    let mut generator = CodeGenerator::new(table, info);
+    generator.program(program)?;
+    Ok(generator.finish())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodegenError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ERROR: {} on line {}", self.message, self.span.start.line)
+    }
+}
+
+impl ToDiagnostic for CodegenError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error("E0601", self.message.clone(), self.span)
+    }
+}
+
+type CodegenResult<T> = Result<T, CodegenError>;
+
+fn error<T>(message: impl Into<String>, span: Span) -> CodegenResult<T> {
+    Err(CodegenError {
+        message: message.into(),
+        span,
+    })
+}
+
+/// The types the code generator knows how to lower. Integers, bools and strings live in %rax
+/// (strings as pointers to NUL terminated bytes), floats live in %xmm0.
+///
+/// Integers narrower than 64 bits are kept sign or zero extended to 64 bits, and float32s are kept
+/// as float64s rounded to float32 precision, see `wrap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueType {
+    Int { bits: u32, signed: bool },
+    Float { single: bool },
+    Bool,
+    String,
+}
+
+impl ValueType {
+    fn from_basic(kind: BasicKind) -> Option<ValueType> {
+        let int = |bits, signed| Some(ValueType::Int { bits, signed });
+        match kind {
+            BasicKind::Int | BasicKind::Int64 => int(64, true),
+            BasicKind::Int8 => int(8, true),
+            BasicKind::Int16 => int(16, true),
+            BasicKind::Int32 => int(32, true),
+            BasicKind::Uint | BasicKind::Uint64 | BasicKind::Uintptr => int(64, false),
+            BasicKind::Uint8 => int(8, false),
+            BasicKind::Uint16 => int(16, false),
+            BasicKind::Uint32 => int(32, false),
+            BasicKind::Float32 => Some(ValueType::Float { single: true }),
+            BasicKind::Float64 => Some(ValueType::Float { single: false }),
+            BasicKind::Bool => Some(ValueType::Bool),
+            BasicKind::String => Some(ValueType::String),
+            BasicKind::Complex64 | BasicKind::Complex128 => None,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, Self::Float { .. })
+    }
+}
+
+fn value_type_from_name(name: &str) -> Option<ValueType> {
+    BasicKind::from_name(name).and_then(ValueType::from_basic)
+}
+
+fn value_type(ty: &Type) -> CodegenResult<ValueType> {
+    match &ty.kind {
+        TypeKind::Name(name, args) if args.is_empty() => match value_type_from_name(&name.name) {
+            Some(value_type) => Ok(value_type),
+            None => error(format!("unsupported type {}", name.name), ty.span),
+        },
+        _ => error("unsupported type", ty.span),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FuncSig {
+    params: Vec<ValueType>,
+    result: Option<ValueType>,
+}
+
+#[derive(Debug, Clone)]
+enum Location {
+    Local(i64), // offset from %rbp
+    Global(String),
+}
+
+impl Location {
+    fn operand(&self) -> String {
+        match self {
+            Self::Local(offset) => format!("{}(%rbp)", offset),
+            Self::Global(symbol) => format!("{}(%rip)", symbol),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Variable {
+    location: Location,
+    ty: ValueType,
+}
+
+/// Jump targets for `break` and `continue`. Switches have no continue target.
+struct BreakTarget {
+    label: Option<String>,
+    break_label: String,
+    continue_label: Option<String>,
+}
+
+const INT_ARG_REGISTERS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+const FLOAT_ARG_REGISTERS: usize = 8;
+
+/// Where an argument is passed.
+enum ArgLocation {
+    Register(String),
+    Stack(usize), // index among the arguments passed on the stack
+}
+
+/// Where the arguments of a function taking `params` go. The first six integer, bool and string arguments and the
+/// first eight floats are passed in registers, the rest on the stack.
+fn arg_locations(params: &[ValueType]) -> Vec<ArgLocation> {
+    let mut int_index = 0;
+    let mut float_index = 0;
+    let mut stack_index = 0;
+    params
+            .iter()
+            .map(|ty| {
+                if ty.is_float() && float_index < FLOAT_ARG_REGISTERS {
+                    float_index += 1;
+                    ArgLocation::Register(format!("%xmm{}", float_index - 1))
+                } else if !ty.is_float() && int_index < INT_ARG_REGISTERS.len() {
+                    int_index += 1;
+                    ArgLocation::Register(INT_ARG_REGISTERS[int_index - 1].to_string())
+                } else {
+                    stack_index += 1;
+                    ArgLocation::Stack(stack_index - 1)
+                }
+            })
+            .collect()
+}
+
+struct CodeGenerator<'a> {
+    table: &'a SymbolTable,
+    info: &'a TypeInfo,
+    output: String,  // finished functions
+    code: String,    // body of the function currently being generated
+    data: String,    // .data section
+    rodata: String,  // .rodata section
+    strings: HashMap<Vec<u8>, String>,
+    label_count: usize,
+    functions: HashMap<String, FuncSig>,
+    globals: HashMap<String, Variable>,
+    global_inits: Vec<(Variable, Option<Expr>)>, // run at the start of main, None sets the zero value
+    scopes: Vec<HashMap<String, Variable>>,
+    frame_size: i64,
+    stack_depth: usize, // 8 byte values pushed on top of the frame, used to keep calls 16 byte aligned
+    break_targets: Vec<BreakTarget>,
+    pending_label: Option<String>, // label of a labeled statement, claimed by the loop or switch that follows
+    func_name: String,
+    result: Option<ValueType>,
+    return_label: String,
+    helpers: Vec<&'static str>, // runtime helpers used, emitted once each
+}
+
+impl<'a> CodeGenerator<'a> {
+    fn new(table: &'a SymbolTable, info: &'a TypeInfo) -> CodeGenerator<'a> {
+        CodeGenerator {
+            table,
+            info,
+            output: String::new(),
+            code: String::new(),
+            data: String::new(),
+            rodata: String::new(),
+            strings: HashMap::new(),
+            label_count: 0,
+            functions: HashMap::new(),
+            globals: HashMap::new(),
+            global_inits: vec![],
+            scopes: vec![],
+            frame_size: 0,
+            stack_depth: 0,
+            break_targets: vec![],
+            pending_label: None,
+            func_name: String::new(),
+            result: None,
+            return_label: String::new(),
+            helpers: vec![],
+        }
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        self.code.push_str("    ");
+        self.code.push_str(instruction);
+        self.code.push('\n');
+    }
+
+    fn emit_label(&mut self, label: &str) {
+        self.code.push_str(label);
+        self.code.push_str(":\n");
+    }
+
+    fn new_label(&mut self) -> String {
+        self.label_count += 1;
+        format!(".L{}", self.label_count)
+    }
+
+    /// Returns the label of a NUL terminated copy of `bytes` in .rodata.
+    fn string_label(&mut self, bytes: &[u8]) -> String {
+        if let Some(label) = self.strings.get(bytes) {
+            return label.clone();
+        }
+        let label = format!(".Lstr{}", self.strings.len());
+        let _ = writeln!(self.rodata, "{}:\n    .string \"{}\"", label, escape_asm_string(bytes));
+        self.strings.insert(bytes.to_vec(), label.clone());
+        label
+    }
+
+    fn float_label(&mut self, value: f64) -> String {
+        self.label_count += 1;
+        let label = format!(".Lfloat{}", self.label_count);
+        let _ = writeln!(self.rodata, "    .align 8\n{}:\n    .quad {}", label, value.to_bits());
+        label
+    }
+
+    fn push(&mut self, ty: ValueType) {
+        if ty.is_float() {
+            self.emit("subq $8, %rsp");
+            self.emit("movsd %xmm0, (%rsp)");
+        } else {
+            self.emit("pushq %rax");
+        }
+        self.stack_depth += 1;
+    }
+
+    /// Pops the top of the stack into an integer register or %xmmN.
+    fn pop(&mut self, register: &str) {
+        if register.starts_with("%xmm") {
+            self.emit(&format!("movsd (%rsp), {}", register));
+            self.emit("addq $8, %rsp");
+        } else {
+            self.emit(&format!("popq {}", register));
+        }
+        self.stack_depth -= 1;
+    }
+
+    fn use_helper(&mut self, helper: &'static str) {
+        if !self.helpers.contains(&helper) {
+            self.helpers.push(helper);
+        }
+    }
+
+    /// Calls the runtime helper `symbol`, whose code is `helper`.
+    fn call_helper(&mut self, helper: &'static str, symbol: &str) {
+        self.use_helper(helper);
+        self.call(symbol);
+    }
+
+    /// Calls `symbol`, keeping the stack 16 byte aligned as the ABI requires.
+    fn call(&mut self, symbol: &str) {
+        let misaligned = self.stack_depth % 2 == 1;
+        if misaligned {
+            self.emit("subq $8, %rsp");
+        }
+        self.emit(&format!("call {}", symbol));
+        if misaligned {
+            self.emit("addq $8, %rsp");
+        }
+    }
+
+    fn store(&mut self, variable: &Variable) {
+        let operand = variable.location.operand();
+        if variable.ty.is_float() {
+            self.emit(&format!("movsd %xmm0, {}", operand));
+        } else {
+            self.emit(&format!("movq %rax, {}", operand));
+        }
+    }
+
+    fn load(&mut self, variable: &Variable) {
+        let operand = variable.location.operand();
+        if variable.ty.is_float() {
+            self.emit(&format!("movsd {}, %xmm0", operand));
+        } else {
+            self.emit(&format!("movq {}, %rax", operand));
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Variable> {
+        self.scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(name))
+                .or_else(|| self.globals.get(name))
+    }
+
+    fn declare_local(&mut self, name: &str, ty: ValueType) -> Variable {
+        self.frame_size += 8;
+        let variable = Variable {
+            location: Location::Local(-self.frame_size),
+            ty,
+        };
+        if name != "_" {
+            self.scopes.last_mut().unwrap().insert(name.to_string(), variable.clone());
+        }
+        variable
+    }
+
+    fn program(&mut self, program: &Program) -> CodegenResult<()> {
+        for decl in &program.decls {
+            if let TopLevelDecl::Func(func) = decl {
+                if func.receiver.is_some() {
+                    return error("methods are not supported by the code generator", func.span);
+                }
+                if !func.type_params.is_empty() {
+                    return error("generic functions are not supported by the code generator", func.span);
+                }
+                let params = func
+                        .signature
+                        .params
+                        .iter()
+                        .map(|param| value_type(&param.ty))
+                        .collect::<CodegenResult<Vec<_>>>()?;
+                let result = match func.signature.results.as_slice() {
+                    [] => None,
+                    [result] => Some(value_type(&result.ty)?),
+                    _ => return error("multiple return values are not supported by the code generator", func.signature.span),
+                };
+                self.functions.insert(func.name.name.clone(), FuncSig { params, result });
+            }
+        }
+        if !self.functions.contains_key("main") {
+            return error("function main is undeclared in the main package", program.package.span);
+        }
+
+        // every global has to be known before any function is generated, as functions can use globals
+        // declared after them. Constants need no storage, their uses are all folded.
+        let mut inits = HashMap::new();
+        for decl in &program.decls {
+            match decl {
+                TopLevelDecl::Decl(Decl::Var(specs, _)) => {
+                    for spec in specs {
+                        self.global_spec(spec, &mut inits)?;
+                    }
+                }
+                TopLevelDecl::Decl(decl @ Decl::Type(..)) => {
+                    return error("type declarations are not supported by the code generator", decl.span());
+                }
+                _ => {}
+            }
+        }
+        for id in &self.info.init_order {
+            if let Some(init) = inits.remove(id) {
+                self.global_inits.push(init);
+            }
+        }
+
+        for decl in &program.decls {
+            if let TopLevelDecl::Func(func) = decl {
+                self.function(func)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Allocates the variables of a package level var spec. Initializers are collected in `inits` so they can
+    /// be run in the order the type checker worked out, rather than in source order.
+    fn global_spec(
+        &mut self,
+        spec: &VarSpec,
+        inits: &mut HashMap<SymbolId, (Variable, Option<Expr>)>,
+    ) -> CodegenResult<()> {
+        if !spec.values.is_empty() && spec.values.len() != spec.names.len() {
+            return error("assignment mismatch", spec.names[0].span);
+        }
+        for (i, name) in spec.names.iter().enumerate() {
+            let value = spec.values.get(i);
+            let ty = match (&spec.ty, value) {
+                (Some(ty), _) => value_type(ty)?,
+                (None, Some(value)) => self.type_of(value)?,
+                (None, None) => return error("missing type or initializer", name.span),
+            };
+            let symbol = if name.name == "_" { self.new_label() } else { format!("golite_g_{}", name.name) };
+            let _ = writeln!(self.data, "    .align 8\n{}:\n    .quad 0", symbol);
+            let variable = Variable {
+                location: Location::Global(symbol),
+                ty,
+            };
+            match (value, self.table.lookup(name.span)) {
+                (Some(value), Some(id)) => {
+                    inits.insert(id, (variable.clone(), Some(value.clone())));
+                }
+                (Some(value), None) => self.global_inits.push((variable.clone(), Some(value.clone()))),
+                (None, _) if ty == ValueType::String => self.global_inits.push((variable.clone(), None)),
+                (None, _) => {}
+            }
+            if name.name != "_" {
+                self.globals.insert(name.name.clone(), variable);
+            }
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, func: &FuncDecl) -> CodegenResult<()> {
+        let body = match &func.body {
+            Some(body) => body,
+            None => return error("function declarations without a body are not supported", func.span),
+        };
+        let sig = self.functions[&func.name.name].clone();
+        self.func_name = func.name.name.clone();
+        self.result = sig.result;
+        self.return_label = self.new_label();
+        self.code.clear();
+        self.frame_size = 0;
+        self.stack_depth = 0;
+        self.scopes = vec![HashMap::new()];
+
+        // spill the incoming arguments into the frame.
+        let locations = arg_locations(&sig.params);
+        for ((param, ty), location) in func.signature.params.iter().zip(&sig.params).zip(locations) {
+            let name = param.name.as_ref().map(|name| name.name.as_str()).unwrap_or("_");
+            let variable = self.declare_local(name, *ty);
+            let operand = variable.location.operand();
+            match location {
+                ArgLocation::Register(register) if ty.is_float() => {
+                    self.emit(&format!("movsd {}, {}", register, operand));
+                }
+                ArgLocation::Register(register) => self.emit(&format!("movq {}, {}", register, operand)),
+                ArgLocation::Stack(index) => {
+                    // above the saved %rbp and the return address.
+                    self.emit(&format!("movq {}(%rbp), %rax", 16 + 8 * index));
+                    self.emit(&format!("movq %rax, {}", operand));
+                }
+            }
+        }
+
+        if func.name.name == "main" {
+            if !sig.params.is_empty() || sig.result.is_some() {
+                return error("func main must have no arguments and no return values", func.signature.span);
+            }
+            for (variable, value) in std::mem::take(&mut self.global_inits) {
+                match value {
+                    Some(value) => self.expr_as(&value, variable.ty)?,
+                    None => self.zero_value(variable.ty),
+                }
+                self.store(&variable);
+            }
+        }
+
+        self.stmts(&body.stmts)?;
+
+        let symbol = function_symbol(&func.name.name);
+        let frame_size = (self.frame_size + 15) / 16 * 16;
+        let _ = writeln!(self.output, "    .globl {}\n    .type {}, @function\n{}:", symbol, symbol, symbol);
+        self.output.push_str("    pushq %rbp\n    movq %rsp, %rbp\n");
+        if frame_size > 0 {
+            let _ = writeln!(self.output, "    subq ${}, %rsp", frame_size);
+        }
+        self.output.push_str(&self.code);
+        let _ = writeln!(self.output, "{}:", self.return_label);
+        if func.name.name == "main" {
+            self.output.push_str("    movl $0, %eax\n");
+        }
+        self.output.push_str("    leave\n    ret\n\n");
+        Ok(())
+    }
+
+    fn finish(mut self) -> String {
+        let mut result = String::from("    .text\n");
+        result.push_str(&self.output);
+        for helper in &self.helpers {
+            result.push_str(helper);
+        }
+        let true_label = self.string_label(b"true");
+        let false_label = self.string_label(b"false");
+        let _ = writeln!(result, "    .set .Ltrue, {}\n    .set .Lfalse, {}", true_label, false_label);
+        if !self.data.is_empty() {
+            result.push_str("\n    .data\n");
+            result.push_str(&self.data);
+        }
+        result.push_str("\n    .section .rodata\n");
+        result.push_str(".Lfmt_int:\n    .string \"%ld\"\n");
+        result.push_str(".Lfmt_uint:\n    .string \"%lu\"\n");
+        result.push_str(".Lfmt_float:\n    .string \"%+.6e\"\n");
+        result.push_str(".Lfmt_str:\n    .string \"%s\"\n");
+        result.push_str(&self.rodata);
+        result.push_str("\n    .section .note.GNU-stack,\"\",@progbits\n");
+        result
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> CodegenResult<()> {
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> CodegenResult<()> {
+        self.scopes.push(HashMap::new());
+        let result = self.stmts(stmts);
+        self.scopes.pop();
+        result
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> CodegenResult<()> {
+        match &stmt.kind {
+            StmtKind::Decl(Decl::Var(specs, _)) => {
+                for spec in specs {
+                    self.local_spec(&spec.names, &spec.ty, &spec.values)?;
+                }
+            }
+            StmtKind::Decl(Decl::Const(..)) => {} // every use of a constant is folded
+            StmtKind::ShortVarDecl(names, values) => {
+                if names.len() != values.len() {
+                    return error("assignment mismatch", stmt.span);
+                }
+                let types = values.iter().map(|value| self.type_of(value)).collect::<CodegenResult<Vec<_>>>()?;
+                for (value, ty) in values.iter().zip(&types) {
+                    self.expr_as(value, *ty)?;
+                    self.push(*ty);
+                }
+                for (name, ty) in names.iter().zip(&types).rev() {
+                    let existing = self.scopes.last().unwrap().get(&name.name).cloned();
+                    let variable = match existing {
+                        Some(variable) => variable,
+                        None => self.declare_local(&name.name, *ty),
+                    };
+                    self.pop_into(&variable);
+                }
+            }
+            StmtKind::Assign(lhs, None, rhs) => {
+                if lhs.len() != rhs.len() {
+                    return error("assignment mismatch", stmt.span);
+                }
+                let targets = lhs.iter().map(|target| self.assign_target(target)).collect::<CodegenResult<Vec<_>>>()?;
+                for (value, target) in rhs.iter().zip(&targets) {
+                    let ty = match target {
+                        Some(variable) => variable.ty,
+                        None => self.type_of(value)?,
+                    };
+                    self.expr_as(value, ty)?;
+                    self.push(ty);
+                }
+                for target in targets.iter().rev() {
+                    match target {
+                        Some(variable) => self.pop_into(variable),
+                        None => self.discard(),
+                    }
+                }
+            }
+            StmtKind::Assign(lhs, Some(op), rhs) => {
+                if lhs.len() != 1 || rhs.len() != 1 {
+                    return error(format!("assignment operator {}= requires single-valued expressions", op), stmt.span);
+                }
+                let variable = match self.assign_target(&lhs[0])? {
+                    Some(variable) => variable,
+                    None => return error("cannot use _ as value", lhs[0].span),
+                };
+                self.binary(*op, &lhs[0], &rhs[0], variable.ty, stmt.span)?;
+                self.store(&variable);
+            }
+            StmtKind::IncDec(target, increment) => {
+                let variable = match self.assign_target(target)? {
+                    Some(variable) if variable.ty != ValueType::Bool && variable.ty != ValueType::String => variable,
+                    _ => return error("invalid operand for ++ or --", target.span),
+                };
+                self.load(&variable);
+                if variable.ty.is_float() {
+                    let one = self.float_label(1.0);
+                    self.emit(&format!("movsd {}(%rip), %xmm1", one));
+                    self.emit(if *increment { "addsd %xmm1, %xmm0" } else { "subsd %xmm1, %xmm0" });
+                } else {
+                    self.emit(if *increment { "addq $1, %rax" } else { "subq $1, %rax" });
+                }
+                self.wrap(variable.ty);
+                self.store(&variable);
+            }
+            StmtKind::Expr(expr) => match &expr.kind {
+                ExprKind::Call(..) => {
+                    self.call_expr(expr)?;
+                }
+                _ => return error("expression is not used", expr.span),
+            },
+            StmtKind::Return(values) => {
+                match (values.as_slice(), self.result) {
+                    ([], None) => {}
+                    ([value], Some(ty)) => self.expr_as(value, ty)?,
+                    ([], Some(_)) => return error("not enough return values", stmt.span),
+                    _ => return error("too many return values", stmt.span),
+                }
+                let label = self.return_label.clone();
+                self.emit(&format!("jmp {}", label));
+            }
+            StmtKind::Block(block) => self.block(&block.stmts)?,
+            StmtKind::If(if_stmt) => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = &if_stmt.init {
+                    self.stmt(init)?;
+                }
+                let else_label = self.new_label();
+                let end_label = self.new_label();
+                self.condition(&if_stmt.cond, &else_label)?;
+                self.block(&if_stmt.then.stmts)?;
+                self.emit(&format!("jmp {}", end_label));
+                self.emit_label(&else_label);
+                if let Some(otherwise) = &if_stmt.otherwise {
+                    self.stmt(otherwise)?;
+                }
+                self.emit_label(&end_label);
+                self.scopes.pop();
+            }
+            StmtKind::For(for_stmt) => {
+                let label = self.pending_label.take();
+                self.scopes.push(HashMap::new());
+                if let Some(init) = &for_stmt.init {
+                    self.stmt(init)?;
+                }
+                let top_label = self.new_label();
+                let continue_label = self.new_label();
+                let end_label = self.new_label();
+                self.emit_label(&top_label);
+                if let Some(cond) = &for_stmt.cond {
+                    self.condition(cond, &end_label)?;
+                }
+                self.break_targets.push(BreakTarget {
+                    label,
+                    break_label: end_label.clone(),
+                    continue_label: Some(continue_label.clone()),
+                });
+                let body = self.block(&for_stmt.body.stmts);
+                self.break_targets.pop();
+                body?;
+                self.emit_label(&continue_label);
+                if let Some(post) = &for_stmt.post {
+                    self.stmt(post)?;
+                }
+                self.emit(&format!("jmp {}", top_label));
+                self.emit_label(&end_label);
+                self.scopes.pop();
+            }
+            StmtKind::Switch(switch) => self.switch(switch)?,
+            StmtKind::Break(label) => {
+                let target = self.find_break_target(label, false, stmt.span)?;
+                self.emit(&format!("jmp {}", target));
+            }
+            StmtKind::Continue(label) => {
+                let target = self.find_break_target(label, true, stmt.span)?;
+                self.emit(&format!("jmp {}", target));
+            }
+            StmtKind::Labeled(label, inner) => {
+                let goto_label = self.goto_label(&label.name);
+                self.emit_label(&goto_label);
+                self.pending_label = Some(label.name.clone());
+                let result = self.stmt(inner);
+                self.pending_label = None;
+                result?;
+            }
+            StmtKind::Goto(label) => {
+                let goto_label = self.goto_label(&label.name);
+                self.emit(&format!("jmp {}", goto_label));
+            }
+            StmtKind::Empty => {}
+            _ => return error("statement is not supported by the code generator", stmt.span),
+        }
+        Ok(())
+    }
+
+    fn goto_label(&self, name: &str) -> String {
+        // identifiers can't contain a '.', so it keeps the function's name apart from the label's.
+        format!(".Lgoto_{}.{}", self.func_name, name)
+    }
+
+    fn find_break_target(&self, label: &Option<Ident>, is_continue: bool, span: Span) -> CodegenResult<String> {
+        for target in self.break_targets.iter().rev() {
+            let matches_label = match label {
+                Some(label) => target.label.as_deref() == Some(label.name.as_str()),
+                None => true,
+            };
+            if !matches_label {
+                continue;
+            }
+            if !is_continue {
+                return Ok(target.break_label.clone());
+            }
+            if let Some(continue_label) = &target.continue_label {
+                return Ok(continue_label.clone());
+            }
+            if label.is_some() {
+                return error("invalid continue label", span);
+            }
+        }
+        let statement = if is_continue { "continue" } else { "break" };
+        error(format!("{} is not in a loop, switch, or select", statement), span)
+    }
+
+    fn switch(&mut self, switch: &SwitchStmt) -> CodegenResult<()> {
+        let label = self.pending_label.take();
+        self.scopes.push(HashMap::new());
+        if let Some(init) = &switch.init {
+            self.stmt(init)?;
+        }
+        let tag = match &switch.tag {
+            Some(tag) => {
+                let ty = self.type_of(tag)?;
+                self.expr_as(tag, ty)?;
+                let variable = self.declare_local("_", ty);
+                self.store(&variable);
+                Some((variable, tag.span))
+            }
+            None => None,
+        };
+
+        let end_label = self.new_label();
+        let body_labels = switch.clauses.iter().map(|_| self.new_label()).collect::<Vec<_>>();
+        let mut default_label = None;
+        for (clause, body_label) in switch.clauses.iter().zip(&body_labels) {
+            if clause.is_default {
+                default_label = Some(body_label.clone());
+            }
+            for expr in &clause.exprs {
+                match &tag {
+                    Some((variable, tag_span)) => {
+                        let tag_expr = Expr {
+                            kind: ExprKind::Ident(String::new()),
+                            span: *tag_span,
+                        };
+                        self.comparison(BinaryOp::Eq, &tag_expr, expr, Some(variable.clone()))?;
+                    }
+                    None => self.expr_as(expr, ValueType::Bool)?,
+                }
+                self.emit("testq %rax, %rax");
+                self.emit(&format!("jnz {}", body_label));
+            }
+        }
+        self.emit(&format!("jmp {}", default_label.as_ref().unwrap_or(&end_label)));
+
+        self.break_targets.push(BreakTarget {
+            label,
+            break_label: end_label.clone(),
+            continue_label: None,
+        });
+        for (i, clause) in switch.clauses.iter().enumerate() {
+            self.emit_label(&body_labels[i]);
+            let falls_through = matches!(clause.body.last(), Some(Stmt { kind: StmtKind::Fallthrough, .. }));
+            let body = if falls_through { &clause.body[..clause.body.len() - 1] } else { &clause.body[..] };
+            if let Err(error) = self.block(body) {
+                self.break_targets.pop();
+                return Err(error);
+            }
+            if falls_through {
+                match body_labels.get(i + 1) {
+                    Some(next) => self.emit(&format!("jmp {}", next)),
+                    None => {
+                        self.break_targets.pop();
+                        return error("cannot fallthrough final case in switch", clause.body.last().unwrap().span);
+                    }
+                }
+            } else {
+                self.emit(&format!("jmp {}", end_label));
+            }
+        }
+        self.break_targets.pop();
+        self.emit_label(&end_label);
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn local_spec(&mut self, names: &[Ident], ty: &Option<Type>, values: &[Expr]) -> CodegenResult<()> {
+        if !values.is_empty() && values.len() != names.len() {
+            return error("assignment mismatch", names[0].span);
+        }
+        for (i, name) in names.iter().enumerate() {
+            let ty = match (ty, values.get(i)) {
+                (Some(ty), _) => value_type(ty)?,
+                (None, Some(value)) => self.type_of(value)?,
+                (None, None) => return error("missing type or initializer", name.span),
+            };
+            match values.get(i) {
+                Some(value) => self.expr_as(value, ty)?,
+                None => self.zero_value(ty),
+            }
+            let variable = self.declare_local(&name.name, ty);
+            self.store(&variable);
+        }
+        Ok(())
+    }
+
+    fn zero_value(&mut self, ty: ValueType) {
+        match ty {
+            ValueType::Float { .. } => self.emit("xorpd %xmm0, %xmm0"),
+            ValueType::String => {
+                let label = self.string_label(b"");
+                self.emit(&format!("leaq {}(%rip), %rax", label));
+            }
+            _ => self.emit("xorl %eax, %eax"),
+        }
+    }
+
+    /// The variable written by an assignment to `target`, or None for the blank identifier.
+    fn assign_target(&self, target: &Expr) -> CodegenResult<Option<Variable>> {
+        match &target.kind {
+            ExprKind::Ident(name) if name == "_" => Ok(None),
+            ExprKind::Ident(name) => match self.lookup(name) {
+                Some(variable) => Ok(Some(variable.clone())),
+                None => error(format!("undefined: {}", name), target.span),
+            },
+            ExprKind::Paren(inner) => self.assign_target(inner),
+            _ => error("assignment target is not supported by the code generator", target.span),
+        }
+    }
+
+    fn pop_into(&mut self, variable: &Variable) {
+        if variable.ty.is_float() {
+            self.pop("%xmm0");
+        } else {
+            self.pop("%rax");
+        }
+        self.store(variable);
+    }
+
+    fn discard(&mut self) {
+        self.emit("addq $8, %rsp");
+        self.stack_depth -= 1;
+    }
+
+    /// Evaluates a boolean condition, jumping to `false_label` if it is false.
+    fn condition(&mut self, cond: &Expr, false_label: &str) -> CodegenResult<()> {
+        self.expr_as(cond, ValueType::Bool)?;
+        self.emit("testq %rax, %rax");
+        self.emit(&format!("jz {}", false_label));
+        Ok(())
+    }
+
+    /// The type the type checker gave `expr`. Constants that are still untyped get their default type.
+    fn type_of(&self, expr: &Expr) -> CodegenResult<ValueType> {
+        let ty = match self.info.types.get(&expr.span) {
+            Some(ty) => ty.default_type(),
+            None => return error("expression is not supported by the code generator", expr.span),
+        };
+        match ty {
+            Ty::Basic(kind) => match ValueType::from_basic(kind) {
+                Some(value_type) => Ok(value_type),
+                None => error(format!("unsupported type {}", kind), expr.span),
+            },
+            _ => error(format!("unsupported type {}", ty), expr.span),
+        }
+    }
+
+    /// Generates `expr`, which has type `ty`. The result is left in %rax or %xmm0.
+    fn expr_as(&mut self, expr: &Expr, ty: ValueType) -> CodegenResult<()> {
+        if let Some(value) = self.info.constants.get(&expr.span) {
+            return self.constant(value, ty, expr.span);
+        }
+        match &expr.kind {
+            ExprKind::Ident(name) => match self.lookup(name).cloned() {
+                Some(variable) => self.load(&variable),
+                None => return error(format!("undefined: {}", name), expr.span),
+            },
+            ExprKind::Paren(inner) => self.expr_as(inner, ty)?,
+            ExprKind::Unary(op, operand) => self.unary(*op, operand, ty)?,
+            ExprKind::Binary(op, lhs, rhs) => {
+                if is_comparison(*op) {
+                    self.comparison(*op, lhs, rhs, None)?;
+                } else if matches!(op, BinaryOp::LogicAnd | BinaryOp::LogicOr) {
+                    self.logical(*op, lhs, rhs)?;
+                } else {
+                    self.binary(*op, lhs, rhs, ty, expr.span)?;
+                }
+            }
+            ExprKind::Index(base, index) => self.index(base, index, expr.span)?,
+            ExprKind::Call(..) => {
+                if self.call_expr(expr)?.is_none() {
+                    return error("function call (no value) used as value", expr.span);
+                }
+            }
+            _ => return error("expression is not supported by the code generator", expr.span),
+        }
+        Ok(())
+    }
+
+    /// Loads a constant, which the type checker has already converted to `ty`.
+    fn constant(&mut self, value: &Value, ty: ValueType, span: Span) -> CodegenResult<()> {
+        match (value, ty) {
+            (Value::Bool(value), _) => self.emit(&format!("movq ${}, %rax", *value as i64)),
+            (Value::String(bytes), _) => {
+                let label = self.string_label(bytes);
+                self.emit(&format!("leaq {}(%rip), %rax", label));
+            }
+            (_, ValueType::Float { single }) => {
+                let value = value.to_f64().unwrap_or(f64::NAN);
+                let label = self.float_label(if single { value as f32 as f64 } else { value });
+                self.emit(&format!("movsd {}(%rip), %xmm0", label));
+            }
+            (Value::Int(value), _) => {
+                // uint64s past the int64 range are stored as the same bits.
+                match value.to_i64().or_else(|| value.to_u64().map(|value| value as i64)) {
+                    Some(value) => self.emit(&format!("movq ${}, %rax", value)),
+                    None => return error(format!("constant {} overflows uint64", value), span),
+                }
+            }
+            (Value::Float(_), _) => return error("constant is not supported by the code generator", span),
+        }
+        Ok(())
+    }
+
+    fn unary(&mut self, op: UnaryOp, operand: &Expr, ty: ValueType) -> CodegenResult<()> {
+        match op {
+            UnaryOp::Plus => self.expr_as(operand, ty)?,
+            UnaryOp::Neg => {
+                self.expr_as(operand, ty)?;
+                if ty.is_float() {
+                    self.emit("movsd %xmm0, %xmm1");
+                    self.emit("xorpd %xmm0, %xmm0");
+                    self.emit("subsd %xmm1, %xmm0");
+                } else {
+                    self.emit("negq %rax");
+                    self.wrap(ty);
+                }
+            }
+            UnaryOp::Not => {
+                self.expr_as(operand, ValueType::Bool)?;
+                self.emit("xorq $1, %rax");
+            }
+            UnaryOp::BitNot => {
+                self.expr_as(operand, ty)?;
+                self.emit("notq %rax");
+                self.wrap(ty);
+            }
+            _ => return error(format!("unary operator {} is not supported by the code generator", op), operand.span),
+        }
+        Ok(())
+    }
+
+    /// Arithmetic and bitwise operators, with both operands of type `ty` (except for shift counts).
+    fn binary(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr, ty: ValueType, span: Span) -> CodegenResult<()> {
+        let shift = matches!(op, BinaryOp::LShift | BinaryOp::RShift);
+        let rhs_ty = if shift { self.type_of(rhs)? } else { ty };
+        self.expr_as(rhs, rhs_ty)?;
+        self.push(rhs_ty);
+        self.expr_as(lhs, ty)?;
+        match ty {
+            ValueType::Float { .. } => {
+                self.pop("%xmm1");
+                let instruction = match op {
+                    BinaryOp::Add => "addsd",
+                    BinaryOp::Sub => "subsd",
+                    BinaryOp::Mul => "mulsd",
+                    BinaryOp::Div => "divsd",
+                    _ => return error(format!("operator {} is not supported by the code generator", op), span),
+                };
+                self.emit(&format!("{} %xmm1, %xmm0", instruction));
+            }
+            ValueType::Int { signed, .. } => {
+                self.pop("%rcx");
+                match op {
+                    BinaryOp::Add => self.emit("addq %rcx, %rax"),
+                    BinaryOp::Sub => self.emit("subq %rcx, %rax"),
+                    BinaryOp::Mul => self.emit("imulq %rcx, %rax"),
+                    BinaryOp::Div | BinaryOp::Mod => {
+                        if signed {
+                            self.emit("cqto");
+                            self.emit("idivq %rcx");
+                        } else {
+                            self.emit("xorl %edx, %edx");
+                            self.emit("divq %rcx");
+                        }
+                        if op == BinaryOp::Mod {
+                            self.emit("movq %rdx, %rax");
+                        }
+                    }
+                    BinaryOp::BitAnd => self.emit("andq %rcx, %rax"),
+                    BinaryOp::BitOr => self.emit("orq %rcx, %rax"),
+                    BinaryOp::BitXor => self.emit("xorq %rcx, %rax"),
+                    BinaryOp::BitClear => {
+                        self.emit("notq %rcx");
+                        self.emit("andq %rcx, %rax");
+                    }
+                    // the hardware only looks at the low 6 bits of the count, but in Go shifting by the
+                    // width or more shifts everything out.
+                    BinaryOp::RShift if signed => {
+                        self.emit("movl $63, %edx");
+                        self.emit("cmpq $64, %rcx");
+                        self.emit("cmovaeq %rdx, %rcx");
+                        self.emit("sarq %cl, %rax");
+                    }
+                    BinaryOp::LShift | BinaryOp::RShift => {
+                        let instruction = if op == BinaryOp::LShift { "shlq" } else { "shrq" };
+                        self.emit(&format!("{} %cl, %rax", instruction));
+                        self.emit("xorl %edx, %edx");
+                        self.emit("cmpq $64, %rcx");
+                        self.emit("cmovaeq %rdx, %rax");
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            ValueType::String if op == BinaryOp::Add => {
+                self.pop("%rsi");
+                self.emit("movq %rax, %rdi");
+                self.call_helper(CONCAT_HELPER, "golite_rt_concat");
+            }
+            _ => return error(format!("operator {} is not supported by the code generator", op), span),
+        }
+        self.wrap(ty);
+        Ok(())
+    }
+
+    /// Brings the result of an operation back into the range of `ty`. Narrow integers are sign or zero
+    /// extended from their low bits, so they wrap around like in Go, and float32s are rounded.
+    fn wrap(&mut self, ty: ValueType) {
+        match ty {
+            ValueType::Int { bits: 8, signed: true } => self.emit("movsbq %al, %rax"),
+            ValueType::Int { bits: 16, signed: true } => self.emit("movswq %ax, %rax"),
+            ValueType::Int { bits: 32, signed: true } => self.emit("movslq %eax, %rax"),
+            ValueType::Int { bits: 8, signed: false } => self.emit("movzbq %al, %rax"),
+            ValueType::Int { bits: 16, signed: false } => self.emit("movzwq %ax, %rax"),
+            ValueType::Int { bits: 32, signed: false } => self.emit("movl %eax, %eax"),
+            ValueType::Float { single: true } => {
+                self.emit("cvtsd2ss %xmm0, %xmm0");
+                self.emit("cvtss2sd %xmm0, %xmm0");
+            }
+            _ => {}
+        }
+    }
+
+    /// Comparison operators, leaving 0 or 1 in %rax. If `lhs_variable` is set, it is used in place of `lhs`.
+    fn comparison(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr, lhs_variable: Option<Variable>) -> CodegenResult<()> {
+        // an untyped constant operand has the type of the other one, so go by whichever isn't constant.
+        let ty = match &lhs_variable {
+            Some(variable) => variable.ty,
+            None if self.info.constants.contains_key(&lhs.span) => self.type_of(rhs)?,
+            None => self.type_of(lhs)?,
+        };
+        self.expr_as(rhs, ty)?;
+        self.push(ty);
+        match &lhs_variable {
+            Some(variable) => self.load(variable),
+            None => self.expr_as(lhs, ty)?,
+        }
+        match ty {
+            ValueType::Float { .. } => {
+                self.pop("%xmm1");
+                self.emit("ucomisd %xmm1, %xmm0");
+                let set = match op {
+                    BinaryOp::Eq => "sete",
+                    BinaryOp::NotEq => "setne",
+                    BinaryOp::Less => "setb",
+                    BinaryOp::LessEq => "setbe",
+                    BinaryOp::Greater => "seta",
+                    _ => "setae",
+                };
+                self.emit(&format!("{} %al", set));
+            }
+            ValueType::String => {
+                self.pop("%rsi");
+                self.emit("movq %rax, %rdi");
+                self.call("strcmp");
+                self.emit("cmpl $0, %eax");
+                self.emit(&format!("{} %al", int_set_instruction(op, true)));
+            }
+            ValueType::Int { signed, .. } => {
+                self.pop("%rcx");
+                self.emit("cmpq %rcx, %rax");
+                self.emit(&format!("{} %al", int_set_instruction(op, signed)));
+            }
+            ValueType::Bool => {
+                self.pop("%rcx");
+                self.emit("cmpq %rcx, %rax");
+                self.emit(&format!("{} %al", int_set_instruction(op, false)));
+            }
+        }
+        self.emit("movzbq %al, %rax");
+        Ok(())
+    }
+
+    /// Short circuiting && and ||.
+    fn logical(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr) -> CodegenResult<()> {
+        let end_label = self.new_label();
+        self.expr_as(lhs, ValueType::Bool)?;
+        self.emit("testq %rax, %rax");
+        if op == BinaryOp::LogicAnd {
+            self.emit(&format!("jz {}", end_label));
+        } else {
+            self.emit(&format!("jnz {}", end_label));
+        }
+        self.expr_as(rhs, ValueType::Bool)?;
+        self.emit_label(&end_label);
+        Ok(())
+    }
+
+    /// Generates a call, conversion or builtin. Returns the type of the result, if there is one.
+    fn call_expr(&mut self, expr: &Expr) -> CodegenResult<Option<ValueType>> {
+        let (callee, args, spread) = match &expr.kind {
+            ExprKind::Call(callee, args, spread) => (callee, args, *spread),
+            _ => unreachable!(),
+        };
+        let name = match &callee.kind {
+            ExprKind::Ident(name) => name,
+            _ => return error("call is not supported by the code generator", expr.span),
+        };
+        if spread {
+            return error("variadic calls are not supported by the code generator", expr.span);
+        }
+        if self.lookup(name).is_none() && !self.functions.contains_key(name) {
+            if BasicKind::from_name(name).is_some() {
+                let ty = self.type_of(expr)?;
+                return self.conversion(ty, args, expr.span).map(Some);
+            }
+            match name.as_str() {
+                "print" | "println" => {
+                    self.print(args, name == "println")?;
+                    return Ok(None);
+                }
+                "len" => return self.len(args, expr.span).map(Some),
+                _ => {}
+            }
+        }
+        let sig = match self.functions.get(name) {
+            Some(sig) => sig.clone(),
+            None => return error(format!("{} is not supported by the code generator", name), callee.span),
+        };
+        if args.len() != sig.params.len() {
+            let problem = if args.len() < sig.params.len() { "not enough" } else { "too many" };
+            return error(format!("{} arguments in call to {}", problem, name), expr.span);
+        }
+        for (arg, ty) in args.iter().zip(&sig.params) {
+            self.expr_as(arg, *ty)?;
+            self.push(*ty);
+        }
+
+        // the arguments are on the stack now, the last one on top. Register arguments are loaded from there, and
+        // stack arguments are copied above them in the order the ABI wants, first argument at the lowest address.
+        let locations = arg_locations(&sig.params);
+        let count = args.len();
+        for (i, location) in locations.iter().enumerate() {
+            let offset = 8 * (count - 1 - i);
+            match location {
+                ArgLocation::Register(register) if sig.params[i].is_float() => {
+                    self.emit(&format!("movsd {}(%rsp), {}", offset, register));
+                }
+                ArgLocation::Register(register) => self.emit(&format!("movq {}(%rsp), {}", offset, register)),
+                ArgLocation::Stack(_) => {}
+            }
+        }
+        let stack_args = locations
+                .iter()
+                .enumerate()
+                .filter(|(_, location)| matches!(location, ArgLocation::Stack(_)))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+        let padding = (self.stack_depth + stack_args.len()) % 2;
+        if padding == 1 {
+            self.emit("subq $8, %rsp");
+        }
+        for (copied, i) in stack_args.iter().rev().enumerate() {
+            let offset = 8 * (count - 1 - i + padding + copied);
+            self.emit(&format!("pushq {}(%rsp)", offset));
+        }
+        self.emit(&format!("call {}", function_symbol(name)));
+        let pushed = count + padding + stack_args.len();
+        if pushed > 0 {
+            self.emit(&format!("addq ${}, %rsp", 8 * pushed));
+        }
+        self.stack_depth -= count;
+        Ok(sig.result)
+    }
+
+    /// The len builtin, on strings. The length of a constant string is already folded.
+    fn len(&mut self, args: &[Expr], span: Span) -> CodegenResult<ValueType> {
+        match args {
+            [arg] if self.type_of(arg)? == ValueType::String => {
+                self.expr_as(arg, ValueType::String)?;
+                self.emit("movq %rax, %rdi");
+                self.call("strlen");
+                Ok(ValueType::Int { bits: 64, signed: true })
+            }
+            _ => error("len is only supported on strings by the code generator", span),
+        }
+    }
+
+    /// Indexing a string gives the byte at that index, after checking it's in range like Go does.
+    fn index(&mut self, base: &Expr, index: &Expr, span: Span) -> CodegenResult<()> {
+        if self.type_of(base)? != ValueType::String {
+            return error("indexing is only supported on strings by the code generator", span);
+        }
+        let index_ty = self.type_of(index)?;
+        self.expr_as(index, index_ty)?;
+        self.push(index_ty);
+        self.expr_as(base, ValueType::String)?;
+        self.push(ValueType::String);
+        self.emit("movq %rax, %rdi");
+        self.call("strlen");
+        self.pop("%rdi");
+        self.pop("%rsi");
+        // compared unsigned, a negative index is out of range too.
+        let in_range = self.new_label();
+        self.emit("cmpq %rax, %rsi");
+        self.emit(&format!("jb {}", in_range));
+        self.emit("movq %rsi, %rdi");
+        self.emit("movq %rax, %rsi");
+        self.call_helper(INDEX_PANIC_HELPER, "golite_rt_index_panic");
+        self.emit_label(&in_range);
+        self.emit("movzbq (%rdi,%rsi), %rax");
+        Ok(())
+    }
+
+    fn conversion(&mut self, ty: ValueType, args: &[Expr], span: Span) -> CodegenResult<ValueType> {
+        let arg = match args {
+            [arg] => arg,
+            _ => return error("wrong number of arguments in conversion", span),
+        };
+        let arg_ty = self.type_of(arg)?;
+        self.expr_as(arg, arg_ty)?;
+        match (arg_ty, ty) {
+            (ValueType::Int { .. }, ValueType::Int { .. }) | (ValueType::Float { .. }, ValueType::Float { .. }) => {
+                self.wrap(ty);
+            }
+            (ValueType::Int { bits: 64, signed: false }, ValueType::Float { .. }) => {
+                // cvtsi2sdq is signed only. Values with the top bit set are halved, keeping the low bit so
+                // they still round the same way, converted and doubled.
+                let big_label = self.new_label();
+                let end_label = self.new_label();
+                self.emit("testq %rax, %rax");
+                self.emit(&format!("js {}", big_label));
+                self.emit("cvtsi2sdq %rax, %xmm0");
+                self.emit(&format!("jmp {}", end_label));
+                self.emit_label(&big_label);
+                self.emit("movq %rax, %rdx");
+                self.emit("shrq $1, %rdx");
+                self.emit("andl $1, %eax");
+                self.emit("orq %rax, %rdx");
+                self.emit("cvtsi2sdq %rdx, %xmm0");
+                self.emit("addsd %xmm0, %xmm0");
+                self.emit_label(&end_label);
+                self.wrap(ty);
+            }
+            (ValueType::Int { .. }, ValueType::Float { .. }) => {
+                self.emit("cvtsi2sdq %rax, %xmm0");
+                self.wrap(ty);
+            }
+            (ValueType::Float { .. }, ValueType::Int { bits: 64, signed: false }) => {
+                // cvttsd2siq is signed only, so values from 2^63 up are converted less 2^63.
+                let limit = self.float_label(9223372036854775808.0);
+                let big_label = self.new_label();
+                let end_label = self.new_label();
+                self.emit(&format!("movsd {}(%rip), %xmm1", limit));
+                self.emit("ucomisd %xmm1, %xmm0");
+                self.emit(&format!("jae {}", big_label));
+                self.emit("cvttsd2siq %xmm0, %rax");
+                self.emit(&format!("jmp {}", end_label));
+                self.emit_label(&big_label);
+                self.emit("subsd %xmm1, %xmm0");
+                self.emit("cvttsd2siq %xmm0, %rax");
+                self.emit("btcq $63, %rax");
+                self.emit_label(&end_label);
+            }
+            (ValueType::Float { .. }, ValueType::Int { .. }) => {
+                self.emit("cvttsd2siq %xmm0, %rax");
+                self.wrap(ty);
+            }
+            (ValueType::Int { .. }, ValueType::String) => {
+                self.emit("movq %rax, %rdi");
+                self.call_helper(RUNE_STRING_HELPER, "golite_rt_rune_string");
+            }
+            (from, to) if from == to => {}
+            _ => return error("conversion is not supported by the code generator", span),
+        }
+        Ok(ty)
+    }
+
+    /// The print and println builtins. println separates its arguments with spaces and adds a newline.
+    fn print(&mut self, args: &[Expr], newline: bool) -> CodegenResult<()> {
+        for (i, arg) in args.iter().enumerate() {
+            if newline && i > 0 {
+                self.emit("movl $32, %edi");
+                self.call("putchar");
+            }
+            let ty = self.type_of(arg)?;
+            self.expr_as(arg, ty)?;
+            let function = match ty {
+                ValueType::Int { signed, .. } => {
+                    self.emit("movq %rax, %rsi");
+                    self.emit(if signed { "leaq .Lfmt_int(%rip), %rdi" } else { "leaq .Lfmt_uint(%rip), %rdi" });
+                    self.emit("xorl %eax, %eax");
+                    "printf"
+                }
+                ValueType::Float { .. } => {
+                    self.use_helper(PRINT_FLOAT_HELPER);
+                    "golite_rt_print_float"
+                }
+                ValueType::Bool => {
+                    self.emit("leaq .Ltrue(%rip), %rsi");
+                    self.emit("leaq .Lfalse(%rip), %rcx");
+                    self.emit("testq %rax, %rax");
+                    self.emit("cmovzq %rcx, %rsi");
+                    self.emit("leaq .Lfmt_str(%rip), %rdi");
+                    self.emit("xorl %eax, %eax");
+                    "printf"
+                }
+                ValueType::String => {
+                    self.emit("movq %rax, %rsi");
+                    self.emit("leaq .Lfmt_str(%rip), %rdi");
+                    self.emit("xorl %eax, %eax");
+                    "printf"
+                }
+            };
+            self.call(function);
+        }
+        if newline {
+            self.emit("movl $10, %edi");
+            self.call("putchar");
+        }
+        Ok(())
+    }
+}
+
+/// Symbols get a prefix per namespace, `golite_f_` for functions, `golite_g_` for globals and `golite_rt_` for the
+/// runtime helpers, so no GoLite name can clash with another or with a helper.
+fn function_symbol(name: &str) -> String {
+    if name == "main" {
+        name.to_string()
+    } else {
+        format!("golite_f_{}", name)
+    }
+}
+
+fn is_comparison(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Less | BinaryOp::LessEq | BinaryOp::Greater | BinaryOp::GreaterEq
+    )
+}
+
+fn int_set_instruction(op: BinaryOp, signed: bool) -> &'static str {
+    match (op, signed) {
+        (BinaryOp::Eq, _) => "sete",
+        (BinaryOp::NotEq, _) => "setne",
+        (BinaryOp::Less, true) => "setl",
+        (BinaryOp::LessEq, true) => "setle",
+        (BinaryOp::Greater, true) => "setg",
+        (_, true) => "setge",
+        (BinaryOp::Less, false) => "setb",
+        (BinaryOp::LessEq, false) => "setbe",
+        (BinaryOp::Greater, false) => "seta",
+        (_, false) => "setae",
+    }
+}
+
+/// Escapes bytes for use inside a `.string` directive.
+fn escape_asm_string(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' => result.push_str("\\\""),
+            b'\\' => result.push_str("\\\\"),
+            0x20..=0x7e => result.push(byte as char),
+            _ => result.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    result
+}
+
+/// char *golite_rt_concat(char *a, char *b): returns a newly allocated a + b.
+const CONCAT_HELPER: &str = "\
+golite_rt_concat:
+    pushq %rbp
+    movq %rsp, %rbp
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    pushq %r14
+    movq %rdi, %rbx
+    movq %rsi, %r12
+    call strlen
+    movq %rax, %r13
+    movq %r12, %rdi
+    call strlen
+    leaq 1(%r13,%rax), %rdi
+    call malloc
+    movq %rax, %r14
+    movq %rax, %rdi
+    movq %rbx, %rsi
+    call strcpy
+    movq %r14, %rdi
+    movq %r12, %rsi
+    call strcat
+    movq %r14, %rax
+    popq %r14
+    popq %r13
+    popq %r12
+    popq %rbx
+    leave
+    ret
+
+";
+
+/// void golite_rt_print_float(double x): prints x the way Go's print builtin does, e.g. +1.500000e+000.
+/// printf gets most of the way there, the exponent just needs padding to 3 digits and inf and nan
+/// need Go's spelling.
+const PRINT_FLOAT_HELPER: &str = "\
+golite_rt_print_float:
+    pushq %rbp
+    movq %rsp, %rbp
+    subq $48, %rsp
+    leaq -48(%rbp), %rdi
+    movl $48, %esi
+    leaq .Lfmt_float(%rip), %rdx
+    movl $1, %eax
+    call snprintf
+    leaq -48(%rbp), %rdi
+    movl $101, %esi
+    call strchr
+    testq %rax, %rax
+    jz .Lrt_print_float_special
+    cmpb $0, 4(%rax)
+    jne .Lrt_print_float_done
+    movb 3(%rax), %cl
+    movb %cl, 4(%rax)
+    movb 2(%rax), %cl
+    movb %cl, 3(%rax)
+    movb $48, 2(%rax)
+    movb $0, 5(%rax)
+    jmp .Lrt_print_float_done
+.Lrt_print_float_special:
+    cmpb $110, -47(%rbp)
+    jne .Lrt_print_float_inf
+    movl $0x4e614e, -48(%rbp)
+    jmp .Lrt_print_float_done
+.Lrt_print_float_inf:
+    movb $73, -47(%rbp)
+.Lrt_print_float_done:
+    leaq -48(%rbp), %rsi
+    leaq .Lfmt_str(%rip), %rdi
+    xorl %eax, %eax
+    call printf
+    leave
+    ret
+
+";
+
+/// void golite_rt_index_panic(long index, long length): reports an index out of range like Go does, and exits.
+const INDEX_PANIC_HELPER: &str = "\
+golite_rt_index_panic:
+    pushq %rbp
+    movq %rsp, %rbp
+    movq %rsi, %rcx
+    movq %rdi, %rdx
+    leaq .Lrt_index_panic_fmt(%rip), %rsi
+    movl $2, %edi
+    xorl %eax, %eax
+    call dprintf
+    movl $2, %edi
+    call exit
+    .section .rodata
+.Lrt_index_panic_fmt:
+    .string \"panic: runtime error: index out of range [%ld] with length %ld\\n\"
+    .text
+
+";
+
+/// char *golite_rt_rune_string(long rune): returns a newly allocated UTF-8 encoding of rune. Like Go, runes that
+/// aren't valid code points become U+FFFD.
+const RUNE_STRING_HELPER: &str = "\
+golite_rt_rune_string:
+    pushq %rbp
+    movq %rsp, %rbp
+    pushq %rbx
+    subq $8, %rsp
+    movq %rdi, %rbx
+    movl $5, %edi
+    call malloc
+    movq %rbx, %rcx
+    cmpq $0x10ffff, %rcx
+    ja .Lrt_rune_invalid
+    movq %rcx, %rdx
+    andq $-2048, %rdx
+    cmpq $0xd800, %rdx
+    jne .Lrt_rune_valid
+.Lrt_rune_invalid:
+    movl $0xfffd, %ecx
+.Lrt_rune_valid:
+    cmpq $0x80, %rcx
+    jae .Lrt_rune_2
+    movb %cl, (%rax)
+    movb $0, 1(%rax)
+    jmp .Lrt_rune_done
+.Lrt_rune_2:
+    cmpq $0x800, %rcx
+    jae .Lrt_rune_3
+    movl %ecx, %edx
+    shrl $6, %edx
+    orl $0xc0, %edx
+    movb %dl, (%rax)
+    movb $0, 2(%rax)
+    jmp .Lrt_rune_last
+.Lrt_rune_3:
+    cmpq $0x10000, %rcx
+    jae .Lrt_rune_4
+    movl %ecx, %edx
+    shrl $12, %edx
+    orl $0xe0, %edx
+    movb %dl, (%rax)
+    movl %ecx, %edx
+    shrl $6, %edx
+    andl $0x3f, %edx
+    orl $0x80, %edx
+    movb %dl, 1(%rax)
+    movb $0, 3(%rax)
+    andl $0x3f, %ecx
+    orl $0x80, %ecx
+    movb %cl, 2(%rax)
+    jmp .Lrt_rune_done
+.Lrt_rune_4:
+    movl %ecx, %edx
+    shrl $18, %edx
+    orl $0xf0, %edx
+    movb %dl, (%rax)
+    movl %ecx, %edx
+    shrl $12, %edx
+    andl $0x3f, %edx
+    orl $0x80, %edx
+    movb %dl, 1(%rax)
+    movl %ecx, %edx
+    shrl $6, %edx
+    andl $0x3f, %edx
+    orl $0x80, %edx
+    movb %dl, 2(%rax)
+    movb $0, 4(%rax)
+    andl $0x3f, %ecx
+    orl $0x80, %ecx
+    movb %cl, 3(%rax)
+    jmp .Lrt_rune_done
+.Lrt_rune_last:
+    andl $0x3f, %ecx
+    orl $0x80, %ecx
+    movb %cl, 1(%rax)
+.Lrt_rune_done:
+    addq $8, %rsp
+    popq %rbx
+    leave
+    ret
+
+";
+
+#[cfg(test)]
+pub mod codegen_test {
+    use std::env;
+    use std::fs;
+    use std::process::{Command, Output};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::sema;
+    use crate::typechecker;
+
+    fn generate(file_name: &str) -> Result<String, super::CodegenError> {
+        let program = Parser::new(Lexer::new(file_name.to_string()).unwrap()).parse().unwrap();
+        let table = sema::analyze(&program).unwrap();
+        let info = typechecker::check(&program, &table).unwrap();
+        super::generate(&program, &table, &info)
+    }
+
+    /// Assembles and links `assembly` with cc, runs it and returns its stdout.
+    /// Returns None if there's no cc to build with.
+    fn run(assembly: &str, name: &str) -> Option<String> {
+        let output = run_output(assembly, name)?;
+        assert!(output.status.success());
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    /// Like `run`, but returns everything the program did, successful or not.
+    fn run_output(assembly: &str, name: &str) -> Option<Output> {
+        let dir = env::temp_dir().join(format!("codegen_test_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.s");
+        let binary = dir.join("a.out");
+        fs::write(&source, assembly).unwrap();
+        let status = match Command::new("cc").arg(&source).arg("-o").arg(&binary).status() {
+            Ok(status) => status,
+            Err(_) => return None,
+        };
+        assert!(status.success(), "cc failed on:\n{}", assembly);
+        let output = Command::new(&binary).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        Some(output)
+    }
+
+    #[test]
+    pub fn given_valid_program_should_build_and_run() {
+        let assembly = generate("testfiles/codegen.test").unwrap();
+        assert!(assembly.contains("    .globl main\n"));
+        if let Some(output) = run(&assembly, "valid") {
+            assert_eq!(
+                output,
+                "hello, world\n\
+                 55 +1.750000e+000 true 97\n\
+                 sum 15\n\
+                 +4.500000e+000 4 +3.750000e+000\n\
+                 fizz fizz fizz fizz fizzbuzz \n\
+                 20 -3 -1 -1 8\n\
+                 yes\n\
+                 fell through\n\
+                 true tab\there\n\
+                 C:\\dir \"quoted\"\n"
+            );
+        }
+    }
+
+    #[test]
+    pub fn given_sized_and_unsigned_types_should_behave_like_go() {
+        let assembly = generate("testfiles/codegen_types.test").unwrap();
+        if let Some(output) = run(&assembly, "types") {
+            assert_eq!(
+                output,
+                "21 20 +1.000000e+001\n\
+                 -128 44 0 -128\n\
+                 9223372036854775808 true 3074457345618258602 1 2\n\
+                 -4 -1 0\n\
+                 +1.000000e-001 false +9.223372e+018 9223372036854775808\n\
+                 +Inf -Inf NaN\n"
+            );
+        }
+    }
+
+    #[test]
+    pub fn given_names_like_the_runtime_helpers_should_not_clash() {
+        let assembly = generate("testfiles/codegen_names.test").unwrap();
+        if let Some(output) = run(&assembly, "names") {
+            assert_eq!(output, "+1.500000e+000\nhello world! 2 1\na_b\na\n");
+        }
+    }
+
+    #[test]
+    pub fn given_strings_and_many_arguments_should_build_and_run() {
+        let assembly = generate("testfiles/codegen_strings.test").unwrap();
+        if let Some(output) = run(&assembly, "strings") {
+            assert_eq!(output, "6 3 104 195 111\né😀A true\n44\n+1.230000e+002\n");
+        }
+    }
+
+    #[test]
+    pub fn given_index_out_of_range_should_panic() {
+        let assembly = generate("testfiles/codegen_panic.test").unwrap();
+        if let Some(output) = run_output(&assembly, "panic") {
+            assert_eq!(output.status.code(), Some(2));
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), "97\n");
+            assert_eq!(
+                String::from_utf8(output.stderr).unwrap(),
+                "panic: runtime error: index out of range [5] with length 3\n"
+            );
+        }
+    }
+
+    #[test]
+    pub fn given_program_without_main_should_report_error() {
+        let error = generate("testfiles/print_ast.test").unwrap_err();
+        assert_eq!(error.to_string(), "ERROR: function main is undeclared in the main package on line 1");
+    }
+}
@@ -0,0 +1,291 @@
+use std::fmt;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use crate::ast::{BinaryOp, UnaryOp};
+use crate::typechecker::BasicKind;
+
+/// The exact value of a constant expression.
+///
+/// Untyped constants keep arbitrary precision. A value only gets rounded or range checked when it's
+/// converted to a type, see `convert`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    String(Vec<u8>), // strings are bytes, which aren't necessarily valid UTF-8
+    Int(BigInt),
+    Float(BigRational),
+}
+
+/// Why a constant can't be converted to a type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inexact {
+    Truncated, // a float with a fractional part converted to an integer type
+    Overflows,
+}
+
+impl fmt::Display for Inexact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Truncated => "truncated",
+            Self::Overflows => "overflows",
+        })
+    }
+}
+
+/// Why a binary operator on constants has no value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Undefined {
+    DivisionByZero,
+    Operator, // the operator isn't defined on the operands, which type checking rules out
+}
+
+impl fmt::Display for Undefined {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::DivisionByZero => "division by zero",
+            Self::Operator => "operator not defined",
+        })
+    }
+}
+
+impl Value {
+    /// The value as an i64, if it's an integer that fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        self.to_integer()?.to_i64()
+    }
+
+    /// The value as an f64, rounded to the nearest float.
+    pub fn to_f64(&self) -> Option<f64> {
+        self.to_rational()?.to_f64()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Self::Int(value) => value.is_zero(),
+            Self::Float(value) => value.is_zero(),
+            _ => false,
+        }
+    }
+
+    /// The value as an integer, if it's numeric and has no fractional part.
+    fn to_integer(&self) -> Option<BigInt> {
+        match self {
+            Self::Int(value) => Some(value.clone()),
+            Self::Float(value) if value.is_integer() => Some(value.to_integer()),
+            _ => None,
+        }
+    }
+
+    fn to_rational(&self) -> Option<BigRational> {
+        match self {
+            Self::Int(value) => Some(BigRational::from_integer(value.clone())),
+            Self::Float(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{:?}", String::from_utf8_lossy(value)),
+            Self::Int(value) => write!(f, "{}", value),
+            Self::Float(value) => f.write_str(&float_string(value)),
+        }
+    }
+}
+
+/// Formats a float constant like Go does, with `%.6g`, so a huge or tiny value comes out as e.g. `1e+400`
+/// rather than hundreds of digits. The digits are worked out exactly, as the value needn't fit in an f64.
+fn float_string(value: &BigRational) -> String {
+    if value.is_zero() {
+        return "0".to_string();
+    }
+    let sign = if value.is_negative() { "-" } else { "" };
+    let abs = value.abs();
+    // the decimal exponent, such that 10^exp <= abs < 10^(exp + 1).
+    let mut exp = abs.numer().to_string().len() as i64 - abs.denom().to_string().len() as i64;
+    while abs < power_of_ten(exp) {
+        exp -= 1;
+    }
+    while abs >= power_of_ten(exp + 1) {
+        exp += 1;
+    }
+    let mut digits = (abs / power_of_ten(exp - 5)).round().to_integer();
+    if digits >= BigInt::from(1_000_000) {
+        digits /= 10;
+        exp += 1;
+    }
+    let digits = digits.to_string();
+    let trim = |number: String| number.trim_end_matches('0').trim_end_matches('.').to_string();
+    let formatted = if !(-4..6).contains(&exp) {
+        let mantissa = trim(format!("{}.{}", &digits[..1], &digits[1..]));
+        format!("{}e{}{:02}", mantissa, if exp < 0 { '-' } else { '+' }, exp.abs())
+    } else if exp >= 0 {
+        let (whole, fraction) = digits.split_at(exp as usize + 1);
+        trim(format!("{}.{}", whole, fraction))
+    } else {
+        trim(format!("0.{}{}", "0".repeat((-exp - 1) as usize), digits))
+    };
+    // like Go, don't let rounding make a fraction look like a whole number.
+    match value.to_f64() {
+        Some(float) if !value.is_integer() && !formatted.contains(['.', 'e']) => format!("{}", float),
+        _ => format!("{}{}", sign, formatted),
+    }
+}
+
+fn power_of_ten(exp: i64) -> BigRational {
+    let power = BigRational::from_integer(BigInt::from(10).pow(exp.unsigned_abs() as u32));
+    if exp < 0 {
+        power.recip()
+    } else {
+        power
+    }
+}
+
+/// Converts a constant to a basic type, rounding floats and checking that it fits.
+pub fn convert(value: &Value, kind: BasicKind) -> Result<Value, Inexact> {
+    if kind.is_integer() {
+        let integer = match value {
+            Value::Float(float) if !float.is_integer() => return Err(Inexact::Truncated),
+            _ => value.to_integer().ok_or(Inexact::Overflows)?,
+        };
+        let (min, max) = int_range(kind);
+        return if integer < min || integer > max { Err(Inexact::Overflows) } else { Ok(Value::Int(integer)) };
+    }
+    if kind.is_float() || kind.is_complex() {
+        let float = value.to_rational().ok_or(Inexact::Overflows)?;
+        let single = matches!(kind, BasicKind::Float32 | BasicKind::Complex64);
+        let rounded = match float.to_f64() {
+            Some(rounded) if single && rounded.abs() > f32::MAX as f64 => return Err(Inexact::Overflows),
+            Some(rounded) if single => rounded as f32 as f64,
+            Some(rounded) => rounded,
+            None => return Err(Inexact::Overflows),
+        };
+        return BigRational::from_float(rounded).map(Value::Float).ok_or(Inexact::Overflows);
+    }
+    Ok(value.clone())
+}
+
+/// The smallest and largest values of an integer type.
+fn int_range(kind: BasicKind) -> (BigInt, BigInt) {
+    let (bits, signed) = match kind {
+        BasicKind::Int8 => (8, true),
+        BasicKind::Int16 => (16, true),
+        BasicKind::Int32 => (32, true),
+        BasicKind::Int | BasicKind::Int64 => (64, true),
+        BasicKind::Uint8 => (8, false),
+        BasicKind::Uint16 => (16, false),
+        BasicKind::Uint32 => (32, false),
+        _ => (64, false),
+    };
+    if signed {
+        let max = (BigInt::one() << (bits - 1)) - 1;
+        (-&max - 1, max)
+    } else {
+        (BigInt::zero(), (BigInt::one() << bits) - 1)
+    }
+}
+
+/// Evaluates a unary operator. `kind` is the type of the operand, if it's typed.
+pub fn unary(op: UnaryOp, value: &Value, kind: Option<BasicKind>) -> Option<Value> {
+    Some(match (op, value) {
+        (UnaryOp::Plus, _) => value.clone(),
+        (UnaryOp::Neg, Value::Int(value)) => Value::Int(-value),
+        (UnaryOp::Neg, Value::Float(value)) => Value::Float(-value),
+        (UnaryOp::Not, Value::Bool(value)) => Value::Bool(!value),
+        (UnaryOp::BitNot, Value::Int(value)) => match kind {
+            // for unsigned types, ^x flips only the bits the type has.
+            Some(kind) if kind.is_integer() && int_range(kind).0.is_zero() => Value::Int(int_range(kind).1 ^ value),
+            _ => Value::Int(!value),
+        },
+        _ => return None,
+    })
+}
+
+/// Evaluates a binary arithmetic or logical operator.
+///
+/// Integers divide with truncation, like Go. Mixing an integer and a float gives a float.
+pub fn binary(op: BinaryOp, lhs: &Value, rhs: &Value) -> Result<Value, Undefined> {
+    if matches!(op, BinaryOp::Div | BinaryOp::Mod) && rhs.is_zero() {
+        return Err(Undefined::DivisionByZero);
+    }
+    match (lhs, rhs) {
+        (Value::Bool(lhs), Value::Bool(rhs)) => match op {
+            BinaryOp::LogicAnd => Ok(Value::Bool(*lhs && *rhs)),
+            BinaryOp::LogicOr => Ok(Value::Bool(*lhs || *rhs)),
+            _ => Err(Undefined::Operator),
+        },
+        (Value::String(lhs), Value::String(rhs)) if op == BinaryOp::Add => Ok(Value::String([&lhs[..], rhs].concat())),
+        (Value::Int(lhs), Value::Int(rhs)) => Ok(Value::Int(match op {
+            BinaryOp::Add => lhs + rhs,
+            BinaryOp::Sub => lhs - rhs,
+            BinaryOp::Mul => lhs * rhs,
+            BinaryOp::Div => lhs / rhs,
+            BinaryOp::Mod => lhs % rhs,
+            BinaryOp::BitAnd => lhs & rhs,
+            BinaryOp::BitOr => lhs | rhs,
+            BinaryOp::BitXor => lhs ^ rhs,
+            BinaryOp::BitClear => lhs & !rhs,
+            _ => return Err(Undefined::Operator),
+        })),
+        _ => {
+            let (lhs, rhs) = lhs.to_rational().zip(rhs.to_rational()).ok_or(Undefined::Operator)?;
+            Ok(Value::Float(match op {
+                BinaryOp::Add => lhs + rhs,
+                BinaryOp::Sub => lhs - rhs,
+                BinaryOp::Mul => lhs * rhs,
+                BinaryOp::Div => lhs / rhs,
+                _ => return Err(Undefined::Operator),
+            }))
+        }
+    }
+}
+
+/// Evaluates `lhs << count` or `lhs >> count`.
+pub fn shift(op: BinaryOp, lhs: &Value, count: &Value) -> Option<Value> {
+    let lhs = lhs.to_integer()?;
+    // anything shifted this far doesn't fit in any type anyway.
+    let count = count.to_integer()?.to_usize().filter(|count| *count <= 10_000)?;
+    match op {
+        BinaryOp::LShift => Some(Value::Int(lhs << count)),
+        BinaryOp::RShift => Some(Value::Int(lhs >> count)),
+        _ => None,
+    }
+}
+
+/// Evaluates a comparison.
+pub fn compare(op: BinaryOp, lhs: &Value, rhs: &Value) -> Option<bool> {
+    let ordering = match (lhs, rhs) {
+        (Value::Bool(lhs), Value::Bool(rhs)) => {
+            return match op {
+                BinaryOp::Eq => Some(lhs == rhs),
+                BinaryOp::NotEq => Some(lhs != rhs),
+                _ => None,
+            };
+        }
+        (Value::String(lhs), Value::String(rhs)) => lhs.cmp(rhs),
+        (Value::Int(lhs), Value::Int(rhs)) => lhs.cmp(rhs),
+        _ => lhs.to_rational()?.cmp(&rhs.to_rational()?),
+    };
+    Some(match op {
+        BinaryOp::Eq => ordering.is_eq(),
+        BinaryOp::NotEq => ordering.is_ne(),
+        BinaryOp::Less => ordering.is_lt(),
+        BinaryOp::LessEq => ordering.is_le(),
+        BinaryOp::Greater => ordering.is_gt(),
+        BinaryOp::GreaterEq => ordering.is_ge(),
+        _ => return None,
+    })
+}
+
+/// Whether `value` is negative.
+pub fn is_negative(value: &Value) -> bool {
+    match value {
+        Value::Int(value) => value.is_negative(),
+        Value::Float(value) => value.is_negative(),
+        _ => false,
+    }
+}
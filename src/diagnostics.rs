@@ -0,0 +1,422 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::token::Span;
+use crate::token_dump::json_string;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A message attached to a span of the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A fix for a diagnostic: replacing the text at `span`, which may be empty, with `replacement`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub message: String,
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// A problem found in the source, with everything needed to report it like rustc does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,    // e.g. "E0101"; each stage's `to_diagnostic` picks codes from its own hundred
+    pub message: String,
+    pub primary: Label,        // underlined with `^`
+    pub secondary: Vec<Label>, // related places, underlined with `-`
+    pub help: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            primary: Label {
+                span,
+                message: String::new(),
+            },
+            secondary: vec![],
+            help: vec![],
+            suggestions: vec![],
+        }
+    }
+
+    /// Sets the message shown next to the primary span.
+    pub fn with_primary_label(mut self, message: impl Into<String>) -> Diagnostic {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.secondary.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help.push(help.into());
+        self
+    }
+
+    pub fn with_suggestion(mut self, message: impl Into<String>, span: Span, replacement: impl Into<String>) -> Diagnostic {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            span,
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    /// Renders the diagnostic, quoting the source lines its labels point at:
+    ///
+    /// ```text
+    /// error[E0301]: undefined: x
+    ///  --> main.go:4:9
+    ///   |
+    /// 4 |     y := x
+    ///   |          ^ not found in this scope
+    /// ```
+    pub fn render(&self, sources: &SourceMap, color: bool) -> String {
+        let style = Style { color };
+        let file = sources.file(self.primary.span.file_id);
+        let start = self.primary.span.start;
+
+        // (line, is primary, label) for every label in the same file as the primary one, by line.
+        let mut lines: BTreeMap<u32, Vec<(bool, &Label)>> = BTreeMap::new();
+        lines.entry(start.line).or_default().push((true, &self.primary));
+        for label in self.secondary.iter().filter(|label| label.span.file_id == self.primary.span.file_id) {
+            lines.entry(label.span.start.line).or_default().push((false, label));
+        }
+        let width = lines.keys().last().unwrap().to_string().len();
+        let gutter = |line: &str| format!("{}{:>width$} |{}", style.gutter(), line, style.reset(), width = width);
+
+        let severity = match self.severity {
+            Severity::Error => style.error(),
+            Severity::Warning => style.warning(),
+        };
+        let mut out = format!(
+            "{}{}[{}]{}{}: {}{}\n",
+            severity,
+            self.severity,
+            self.code,
+            style.reset(),
+            style.bold(),
+            self.message,
+            style.reset()
+        );
+        let name = file.map_or("<unknown>", |file| file.name.as_str());
+        out.push_str(&format!(
+            "{:width$}{}-->{} {}:{}:{}\n",
+            "",
+            style.gutter(),
+            style.reset(),
+            name,
+            start.line,
+            start.column,
+            width = width
+        ));
+
+        if let Some(file) = file {
+            out.push_str(&format!("{}\n", gutter("")));
+            let mut previous = None;
+            for (line, labels) in &lines {
+                if previous.is_some_and(|previous| line - previous > 1) {
+                    out.push_str(&format!("{}...{}\n", style.gutter(), style.reset()));
+                }
+                previous = Some(*line);
+                let text = expand_tabs(file.line(*line));
+                let quoted = format!("{} {}", gutter(&line.to_string()), text);
+                out.push_str(quoted.trim_end());
+                out.push('\n');
+                for (primary, label) in labels {
+                    let (mark, mark_style) = if *primary { ('^', severity) } else { ('-', style.gutter()) };
+                    let first = display_column(file.line(*line), label.span.start.column);
+                    let last = if label.span.end.line == *line {
+                        display_column(file.line(*line), label.span.end.column)
+                    } else {
+                        // a span running onto later lines is underlined to the end of its first line.
+                        text.chars().count() + 1
+                    };
+                    let underline = mark.to_string().repeat(last.saturating_sub(first).max(1));
+                    let annotation = format!(
+                        "{} {:first$}{}{} {}{}",
+                        gutter(""),
+                        "",
+                        mark_style,
+                        underline,
+                        label.message,
+                        style.reset(),
+                        first = first - 1
+                    );
+                    out.push_str(annotation.trim_end());
+                    out.push('\n');
+                }
+            }
+        }
+        let suggestions = self
+                .suggestions
+                .iter()
+                .map(|suggestion| format!("{}: `{}`", suggestion.message, suggestion.replacement));
+        let help = self.help.iter().cloned().chain(suggestions).collect::<Vec<_>>();
+        if !help.is_empty() {
+            out.push_str(&format!("{}\n", gutter("")));
+        }
+        for help in &help {
+            out.push_str(&format!(
+                "{:width$} {}={} {}help{}: {}\n",
+                "",
+                style.gutter(),
+                style.reset(),
+                style.bold(),
+                style.reset(),
+                help,
+                width = width
+            ));
+        }
+        out
+    }
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic as one line of JSON, for editors and other tools.
+    pub fn to_json(&self, sources: &SourceMap) -> String {
+        let label = |message: &str| if message.is_empty() { "null".to_string() } else { json_string(message) };
+        let mut spans = vec![format!(
+            "{{{},\"is_primary\":true,\"label\":{}}}",
+            span_json(self.primary.span, sources),
+            label(&self.primary.message)
+        )];
+        spans.extend(self.secondary.iter().map(|secondary| {
+            format!(
+                "{{{},\"is_primary\":false,\"label\":{}}}",
+                span_json(secondary.span, sources),
+                label(&secondary.message)
+            )
+        }));
+        let help = self.help.iter().map(|help| json_string(help)).collect::<Vec<_>>();
+        let suggestions = self
+                .suggestions
+                .iter()
+                .map(|suggestion| {
+                    format!(
+                        "{{\"message\":{},\"replacement\":{},\"span\":{{{}}}}}",
+                        json_string(&suggestion.message),
+                        json_string(&suggestion.replacement),
+                        span_json(suggestion.span, sources)
+                    )
+                })
+                .collect::<Vec<_>>();
+        format!(
+            "{{\"severity\":\"{}\",\"code\":\"{}\",\"message\":{},\"spans\":[{}],\"help\":[{}],\"suggestions\":[{}]}}",
+            self.severity,
+            self.code,
+            json_string(&self.message),
+            spans.join(","),
+            help.join(","),
+            suggestions.join(",")
+        )
+    }
+}
+
+/// The fields describing a span in JSON diagnostics, without the surrounding braces.
+fn span_json(span: Span, sources: &SourceMap) -> String {
+    let file = sources.file(span.file_id).map_or("<unknown>", |file| file.name.as_str());
+    format!(
+        "\"file\":{},\"line_start\":{},\"column_start\":{},\"line_end\":{},\"column_end\":{},\"byte_start\":{},\"byte_end\":{}",
+        json_string(file),
+        span.start.line,
+        span.start.column,
+        span.end.line,
+        span.end.column,
+        span.start.offset,
+        span.end.offset
+    )
+}
+
+/// An error from one of the compiler's stages that can be reported as a diagnostic.
+pub trait ToDiagnostic {
+    fn to_diagnostic(&self) -> Diagnostic;
+}
+
+/// A source file, kept so diagnostics can quote it.
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+}
+
+impl SourceFile {
+    /// The text of the given 1-based line, or an empty string past the end of the file.
+    fn line(&self, line: u32) -> &str {
+        self.text.lines().nth(line as usize - 1).unwrap_or("")
+    }
+}
+
+/// The source files being compiled, indexed by the `file_id` in their spans.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// Adds a file and returns the id its spans should be tagged with.
+    pub fn add(&mut self, name: String, text: String) -> usize {
+        self.files.push(SourceFile { name, text });
+        self.files.len() - 1
+    }
+
+    pub fn file(&self, file_id: usize) -> Option<&SourceFile> {
+        self.files.get(file_id)
+    }
+}
+
+/// Tabs are shown as 4 spaces, so underlines have to be shifted to match.
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', "    ")
+}
+
+/// The 1-based column on screen of the given 1-based character column in `text`.
+fn display_column(text: &str, column: u32) -> usize {
+    let before = column as usize - 1;
+    let tabs = text.chars().take(before).filter(|c| *c == '\t').count();
+    before + 3 * tabs + 1
+}
+
+/// ANSI escapes for the parts of a diagnostic, or nothing if color is off.
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn code(&self, code: &'static str) -> &'static str {
+        if self.color {
+            code
+        } else {
+            ""
+        }
+    }
+
+    fn error(&self) -> &'static str {
+        self.code("\x1b[1;31m")
+    }
+
+    fn warning(&self) -> &'static str {
+        self.code("\x1b[1;33m")
+    }
+
+    fn gutter(&self) -> &'static str {
+        self.code("\x1b[1;34m")
+    }
+
+    fn bold(&self) -> &'static str {
+        self.code("\x1b[1m")
+    }
+
+    fn reset(&self) -> &'static str {
+        self.code("\x1b[0m")
+    }
+}
+
+#[cfg(test)]
+pub mod diagnostics_test {
+    use super::{Diagnostic, SourceMap, ToDiagnostic};
+    use crate::lexer::Lexer;
+    use crate::token::{Position, Span};
+
+    fn span(start: (u32, u32), end: (u32, u32)) -> Span {
+        Span {
+            file_id: 0,
+            start: Position::new(start.0, start.1, 0),
+            end: Position::new(end.0, end.1, 0),
+        }
+    }
+
+    fn sources(text: &str) -> SourceMap {
+        let mut sources = SourceMap::default();
+        sources.add("main.go".to_string(), text.to_string());
+        sources
+    }
+
+    #[test]
+    pub fn given_error_should_quote_line_and_underline_span() {
+        let sources = sources("package main\nfunc main() {\n\ty := x + 1\n}\n");
+        let diagnostic = Diagnostic::error("E0301", "undefined: x", span((3, 7), (3, 8)))
+                .with_primary_label("not found in this scope");
+        assert_eq!(diagnostic.render(&sources, false), "\
+error[E0301]: undefined: x
+ --> main.go:3:7
+  |
+3 |     y := x + 1
+  |          ^ not found in this scope
+");
+    }
+
+    #[test]
+    pub fn given_labels_and_help_should_render_each() {
+        let sources = sources("a := 1\n\n\na := 2\n");
+        let diagnostic = Diagnostic::error("E0302", "a redeclared in this block", span((4, 1), (4, 2)))
+                .with_label(span((1, 1), (1, 2)), "previous declaration")
+                .with_help("use = to assign to the existing a");
+        assert_eq!(diagnostic.render(&sources, false), "\
+error[E0302]: a redeclared in this block
+ --> main.go:4:1
+  |
+1 | a := 1
+  | - previous declaration
+...
+4 | a := 2
+  | ^
+  |
+  = help: use = to assign to the existing a
+");
+        assert!(diagnostic.render(&sources, true).starts_with("\x1b[1;31merror[E0302]\x1b[0m\x1b[1m: a redeclared"));
+    }
+
+    #[test]
+    pub fn given_lex_error_should_point_at_offending_character() {
+        let text = "x := 1 @ 2\n";
+        let (_, errors) = Lexer::from_source(text).lex_all();
+        assert_eq!(errors[0].to_diagnostic().render(&sources(text), false), "\
+error[E0101]: unknown character '@'
+ --> main.go:1:8
+  |
+1 | x := 1 @ 2
+  |        ^ not valid in source
+");
+    }
+
+    #[test]
+    pub fn given_suggestion_should_emit_one_line_of_json() {
+        let text = "x\n/* \"open\"";
+        let (_, errors) = Lexer::from_source(text).lex_all();
+        let diagnostic = errors[0].to_diagnostic();
+        assert_eq!(diagnostic.to_json(&sources(text)), concat!(
+            r#"{"severity":"error","code":"E0113","message":"unterminated block comment","spans":["#,
+            r#"{"file":"main.go","line_start":2,"column_start":1,"line_end":2,"column_end":10,"byte_start":2,"byte_end":11,"#,
+            r#""is_primary":true,"label":"comment starts here"}],"help":[],"suggestions":[{"message":"close the comment at the end of the file","#,
+            r#""replacement":"*/","span":{"file":"main.go","line_start":2,"column_start":10,"line_end":2,"column_end":10,"byte_start":11,"byte_end":11}}]}"#,
+        ));
+        assert!(diagnostic.render(&sources(text), false).ends_with("= help: close the comment at the end of the file: `*/`\n"));
+    }
+}
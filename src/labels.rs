@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::ast::*;
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::token::Span;
+
+/// Checks the labels of every function in `program`, and the statements that use them.
+///
+/// Labels live in their own namespace per function (function literals get their own), separate from other
+/// identifiers. All errors are reported, sorted by position.
+pub fn check(program: &Program) -> Result<(), Vec<LabelError>> {
+    let mut checker = Checker::default();
+    for decl in &program.decls {
+        match decl {
+            TopLevelDecl::Func(func) => {
+                if let Some(body) = &func.body {
+                    checker.function(body);
+                }
+            }
+            TopLevelDecl::Decl(decl) => checker.decl(decl),
+            TopLevelDecl::Bad(_) => {}
+        }
+    }
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        checker.errors.sort_by_key(|error| error.span().start.offset);
+        Err(checker.errors)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LabelError {
+    Undefined(String, Span),
+    Redefined { name: String, span: Span, previous: Span },
+    Unused(String, Span),
+    InvalidBreak(String, Span),    // `break L` where L doesn't label an enclosing for, switch or select
+    InvalidContinue(String, Span), // `continue L` where L doesn't label an enclosing for
+    JumpOverDeclaration { name: String, span: Span, declaration: Span },
+    JumpIntoBlock { name: String, span: Span, block: Span },
+    BreakOutsideLoop(Span),
+    ContinueOutsideLoop(Span),
+}
+
+impl LabelError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Undefined(_, span)
+            | Self::Redefined { span, .. }
+            | Self::Unused(_, span)
+            | Self::InvalidBreak(_, span)
+            | Self::InvalidContinue(_, span)
+            | Self::JumpOverDeclaration { span, .. }
+            | Self::JumpIntoBlock { span, .. }
+            | Self::BreakOutsideLoop(span)
+            | Self::ContinueOutsideLoop(span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Undefined(name, _) => write!(f, "ERROR: label {} not defined", name)?,
+            Self::Redefined { name, previous, .. } => write!(
+                f,
+                "ERROR: label {} already defined (previous definition on line {})",
+                name, previous.start.line
+            )?,
+            Self::Unused(name, _) => write!(f, "ERROR: label {} defined and not used", name)?,
+            Self::InvalidBreak(name, _) => write!(f, "ERROR: invalid break label {}", name)?,
+            Self::InvalidContinue(name, _) => write!(f, "ERROR: invalid continue label {}", name)?,
+            Self::JumpOverDeclaration { name, declaration, .. } => write!(
+                f,
+                "ERROR: goto {} jumps over variable declaration (declared on line {})",
+                name, declaration.start.line
+            )?,
+            Self::JumpIntoBlock { name, block, .. } => {
+                write!(f, "ERROR: goto {} jumps into block (starting on line {})", name, block.start.line)?
+            }
+            Self::BreakOutsideLoop(_) => write!(f, "ERROR: break is not in a loop, switch, or select")?,
+            Self::ContinueOutsideLoop(_) => write!(f, "ERROR: continue is not in a loop")?,
+        }
+        write!(f, " on line {}", self.span().start.line)
+    }
+}
+
+impl ToDiagnostic for LabelError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let span = self.span();
+        match self {
+            Self::Undefined(name, _) => Diagnostic::error("E0401", format!("label {} not defined", name), span),
+            Self::Redefined { name, previous, .. } => {
+                Diagnostic::error("E0402", format!("label {} already defined", name), span)
+                        .with_label(*previous, "previous definition")
+            }
+            Self::Unused(name, _) => Diagnostic::error("E0403", format!("label {} defined and not used", name), span),
+            Self::InvalidBreak(name, _) => Diagnostic::error("E0404", format!("invalid break label {}", name), span)
+                    .with_help("a break label must name an enclosing for, switch or select statement"),
+            Self::InvalidContinue(name, _) => {
+                Diagnostic::error("E0405", format!("invalid continue label {}", name), span)
+                        .with_help("a continue label must name an enclosing for statement")
+            }
+            Self::JumpOverDeclaration { name, declaration, .. } => {
+                Diagnostic::error("E0406", format!("goto {} jumps over variable declaration", name), span)
+                        .with_label(*declaration, "declared here")
+            }
+            Self::JumpIntoBlock { name, block, .. } => {
+                Diagnostic::error("E0407", format!("goto {} jumps into block", name), span)
+                        .with_label(*block, "block starts here")
+            }
+            Self::BreakOutsideLoop(_) => Diagnostic::error("E0408", "break is not in a loop, switch, or select", span),
+            Self::ContinueOutsideLoop(_) => Diagnostic::error("E0409", "continue is not in a loop", span),
+        }
+    }
+}
+
+/// A statement list, such as a function body, an if branch or a case clause.
+struct BlockInfo<'a> {
+    stmts: &'a [Stmt],
+    span: Span,
+}
+
+/// Where a statement is: the index of the block and of the statement within it, for each enclosing block.
+type Path = Vec<(usize, usize)>;
+
+struct Label {
+    span: Span,
+    path: Path,
+    used: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Loop,
+    Switch, // also select
+}
+
+/// The labels of a single function body.
+#[derive(Default)]
+struct Function<'a> {
+    labels: HashMap<String, Label>,
+    blocks: Vec<BlockInfo<'a>>,
+    path: Path,
+    targets: Vec<(Option<String>, Target)>, // the enclosing statements that break and continue can refer to
+    gotos: Vec<(&'a Ident, Path)>,
+    unresolved: Vec<(&'a Ident, bool)>, // labeled breaks (false) and continues (true) that aren't to an enclosing target
+}
+
+#[derive(Default)]
+struct Checker<'a> {
+    functions: Vec<Function<'a>>,
+    errors: Vec<LabelError>,
+}
+
+impl<'a> Checker<'a> {
+    fn function(&mut self, body: &'a Block) {
+        self.functions.push(Function::default());
+        self.block(&body.stmts, body.span);
+        let function = self.functions.pop().unwrap();
+        self.finish(function);
+    }
+
+    /// Resolves the labels used by a function once all of them are known.
+    fn finish(&mut self, mut function: Function<'a>) {
+        for (label, path) in &function.gotos {
+            match function.labels.get_mut(&label.name) {
+                Some(target) => {
+                    target.used = true;
+                    if let Some(error) = check_goto(&function.blocks, label, path, &target.path) {
+                        self.errors.push(error);
+                    }
+                }
+                None => self.errors.push(LabelError::Undefined(label.name.clone(), label.span)),
+            }
+        }
+        for (label, is_continue) in &function.unresolved {
+            match function.labels.get_mut(&label.name) {
+                Some(target) => {
+                    target.used = true;
+                    self.errors.push(if *is_continue {
+                        LabelError::InvalidContinue(label.name.clone(), label.span)
+                    } else {
+                        LabelError::InvalidBreak(label.name.clone(), label.span)
+                    });
+                }
+                None => self.errors.push(LabelError::Undefined(label.name.clone(), label.span)),
+            }
+        }
+        for (name, label) in function.labels {
+            if !label.used {
+                self.errors.push(LabelError::Unused(name, label.span));
+            }
+        }
+    }
+
+    fn current(&mut self) -> &mut Function<'a> {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn block(&mut self, stmts: &'a [Stmt], span: Span) {
+        let function = self.current();
+        let id = function.blocks.len();
+        function.blocks.push(BlockInfo { stmts, span });
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.current().path.push((id, i));
+            self.stmt(stmt, None);
+            self.current().path.pop();
+        }
+    }
+
+    /// Checks a statement. `label` is set if the statement is labeled.
+    fn stmt(&mut self, stmt: &'a Stmt, label: Option<&'a Ident>) {
+        match &stmt.kind {
+            StmtKind::Decl(decl) => self.decl(decl),
+            StmtKind::Labeled(label, inner) => {
+                let function = self.current();
+                let path = function.path.clone();
+                if let Some(previous) = function.labels.get(&label.name) {
+                    let previous = previous.span;
+                    self.errors.push(LabelError::Redefined {
+                        name: label.name.clone(),
+                        span: label.span,
+                        previous,
+                    });
+                } else {
+                    function.labels.insert(
+                        label.name.clone(),
+                        Label {
+                            span: label.span,
+                            path,
+                            used: false,
+                        },
+                    );
+                }
+                self.stmt(inner, Some(label));
+            }
+            StmtKind::Expr(expr) | StmtKind::Go(expr) | StmtKind::Defer(expr) => self.expr(expr),
+            StmtKind::Send(channel, value) => {
+                self.expr(channel);
+                self.expr(value);
+            }
+            StmtKind::IncDec(target, _) => self.expr(target),
+            StmtKind::Assign(lhs, _, rhs) => self.exprs(lhs.iter().chain(rhs)),
+            StmtKind::ShortVarDecl(_, values) | StmtKind::Return(values) => self.exprs(values),
+            StmtKind::Break(None) => {
+                if self.current().targets.is_empty() {
+                    self.errors.push(LabelError::BreakOutsideLoop(stmt.span));
+                }
+            }
+            StmtKind::Continue(None) => {
+                if !self.current().targets.iter().any(|(_, target)| *target == Target::Loop) {
+                    self.errors.push(LabelError::ContinueOutsideLoop(stmt.span));
+                }
+            }
+            StmtKind::Break(Some(name)) => self.labeled_jump(name, false),
+            StmtKind::Continue(Some(name)) => self.labeled_jump(name, true),
+            StmtKind::Goto(name) => {
+                let function = self.current();
+                let path = function.path.clone();
+                function.gotos.push((name, path));
+            }
+            StmtKind::Fallthrough | StmtKind::Empty | StmtKind::Bad => {}
+            StmtKind::Block(block) => self.block(&block.stmts, block.span),
+            StmtKind::If(if_stmt) => {
+                self.init(&if_stmt.init);
+                self.expr(&if_stmt.cond);
+                self.block(&if_stmt.then.stmts, if_stmt.then.span);
+                if let Some(otherwise) = &if_stmt.otherwise {
+                    self.stmt(otherwise, None);
+                }
+            }
+            StmtKind::Switch(switch) => {
+                self.init(&switch.init);
+                self.exprs(&switch.tag);
+                self.exprs(switch.clauses.iter().flat_map(|clause| &clause.exprs));
+                self.targets(label, Target::Switch, |checker| {
+                    for clause in &switch.clauses {
+                        checker.block(&clause.body, clause.span);
+                    }
+                });
+            }
+            StmtKind::TypeSwitch(switch) => {
+                self.init(&switch.init);
+                self.expr(&switch.expr);
+                self.targets(label, Target::Switch, |checker| {
+                    for clause in &switch.clauses {
+                        checker.block(&clause.body, clause.span);
+                    }
+                });
+            }
+            StmtKind::Select(clauses) => {
+                for clause in clauses {
+                    self.init(&clause.comm);
+                }
+                self.targets(label, Target::Switch, |checker| {
+                    for clause in clauses {
+                        checker.block(&clause.body, clause.span);
+                    }
+                });
+            }
+            StmtKind::For(for_stmt) => {
+                self.init(&for_stmt.init);
+                self.exprs(&for_stmt.cond);
+                self.init(&for_stmt.post);
+                self.targets(label, Target::Loop, |checker| {
+                    checker.block(&for_stmt.body.stmts, for_stmt.body.span)
+                });
+            }
+            StmtKind::Range(range) => {
+                self.exprs(range.key.iter().chain(&range.value).chain([&range.expr]));
+                self.targets(label, Target::Loop, |checker| checker.block(&range.body.stmts, range.body.span));
+            }
+        }
+    }
+
+    /// An optional simple statement in a statement header, e.g. the `i := 0` of a for loop.
+    fn init(&mut self, stmt: &'a Option<Box<Stmt>>) {
+        if let Some(stmt) = stmt {
+            self.stmt(stmt, None);
+        }
+    }
+
+    /// Runs `body` with the statement being checked as a target for break and continue.
+    fn targets(&mut self, label: Option<&Ident>, target: Target, body: impl FnOnce(&mut Self)) {
+        self.current().targets.push((label.map(|label| label.name.clone()), target));
+        body(self);
+        self.current().targets.pop();
+    }
+
+    fn labeled_jump(&mut self, name: &'a Ident, is_continue: bool) {
+        let function = self.current();
+        let enclosing = function.targets.iter().any(|(label, target)| {
+            label.as_ref() == Some(&name.name) && (!is_continue || *target == Target::Loop)
+        });
+        if enclosing {
+            function.labels.get_mut(&name.name).unwrap().used = true;
+        } else {
+            function.unresolved.push((name, is_continue));
+        }
+    }
+
+    fn decl(&mut self, decl: &'a Decl) {
+        match decl {
+            Decl::Const(specs, _) => self.exprs(specs.iter().flat_map(|spec| &spec.values)),
+            Decl::Var(specs, _) => self.exprs(specs.iter().flat_map(|spec| &spec.values)),
+            Decl::Type(..) => {}
+        }
+    }
+
+    fn exprs(&mut self, exprs: impl IntoIterator<Item = &'a Expr>) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    /// Looks for function literals, which have labels of their own.
+    fn expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::FuncLit(_, body) => self.function(body),
+            ExprKind::CompositeLit(_, elements) => {
+                for element in elements {
+                    self.exprs(&element.key);
+                    self.expr(&element.value);
+                }
+            }
+            ExprKind::Paren(inner) | ExprKind::Selector(inner, _) | ExprKind::TypeAssert(inner, _) | ExprKind::Unary(_, inner) => {
+                self.expr(inner)
+            }
+            ExprKind::Index(base, index) | ExprKind::Binary(_, base, index) => {
+                self.expr(base);
+                self.expr(index);
+            }
+            ExprKind::Instantiation(base, args) | ExprKind::Call(base, args, _) => {
+                self.expr(base);
+                self.exprs(args);
+            }
+            ExprKind::Slice(base, low, high, max) => {
+                self.expr(base);
+                for bound in [low, high, max].into_iter().flatten() {
+                    self.expr(bound);
+                }
+            }
+            ExprKind::Ident(_)
+            | ExprKind::IntLit(..)
+            | ExprKind::FloatLit(..)
+            | ExprKind::CharLit(..)
+            | ExprKind::StringLit(..)
+            | ExprKind::Type(_) => {}
+        }
+    }
+}
+
+/// A goto must not jump into a block, or forward over a variable declaration in the label's block.
+fn check_goto(blocks: &[BlockInfo], label: &Ident, from: &Path, to: &Path) -> Option<LabelError> {
+    // paths start at the function body, so the first block they don't share is the one being jumped into.
+    let depth = to.len() - 1;
+    for (i, &(block, _)) in to.iter().enumerate() {
+        if from.get(i).map(|&(from_block, _)| from_block) != Some(block) {
+            return Some(LabelError::JumpIntoBlock {
+                name: label.name.clone(),
+                span: label.span,
+                block: blocks[block].span,
+            });
+        }
+    }
+    let (block, label_index) = to[depth];
+    let goto_index = from[depth].1;
+    if label_index <= goto_index {
+        return None;
+    }
+    let declaration = blocks[block].stmts[goto_index + 1..label_index].iter().find(|stmt| {
+        matches!(stmt.kind, StmtKind::Decl(Decl::Var(..)) | StmtKind::ShortVarDecl(..))
+    })?;
+    Some(LabelError::JumpOverDeclaration {
+        name: label.name.clone(),
+        span: label.span,
+        declaration: declaration.span,
+    })
+}
+
+#[cfg(test)]
+pub mod labels_test {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn check(file_name: &str) -> Result<(), Vec<String>> {
+        let program = Parser::new(Lexer::new(file_name.to_string()).unwrap()).parse().unwrap();
+        super::check(&program).map_err(|errors| errors.iter().map(|error| error.to_string()).collect())
+    }
+
+    #[test]
+    pub fn given_valid_labels_should_report_no_errors() {
+        assert_eq!(check("testfiles/codegen.test"), Ok(()));
+        assert_eq!(check("testfiles/types.test"), Ok(()));
+    }
+
+    #[test]
+    pub fn given_label_errors_should_report_all_of_them() {
+        assert_eq!(
+            check("testfiles/illegal_labels.test").unwrap_err(),
+            vec![
+                "ERROR: label unused defined and not used on line 5",
+                "ERROR: invalid continue label retry on line 11",
+                "ERROR: goto skip jumps over variable declaration (declared on line 14) on line 13",
+                "ERROR: goto inner jumps into block (starting on line 17) on line 16",
+                "ERROR: invalid break label retry on line 23",
+                "ERROR: label missing not defined on line 25",
+                "ERROR: label retry already defined (previous definition on line 9) on line 27",
+                "ERROR: break is not in a loop, switch, or select on line 29",
+                "ERROR: label skip not defined on line 31",
+            ]
+        );
+    }
+}
@@ -1,114 +1,534 @@
+use std::fmt;
 use std::fs::File;
-use std::io::{BufReader, BufRead};
-use crate::token::{self, TokenKind};
+use std::io::{self, BufReader, BufRead, Cursor, Read};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::token::{Position, Span, Token, TokenKind, Trivia, TriviaKind};
 
-const EOF_CHAR: char = 0xFF as char;
+/// The value of an escape sequence in a string or char literal.
+enum Escape {
+    Byte(u8),
+    Rune(char),
+}
+
+/// A lexical error. Each variant carries the span of the offending input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    UnknownCharacter(char, Span),
+    IncompleteElipses(Span),          // `..` not followed by a third `.`
+    InvalidEscapeSequence(Option<char>, Span), // `\` followed by a character that isn't a valid escape, or the end of the file
+    InvalidUtf8(Span),
+    MalformedEscape(char, usize, Span), // e.g. `\x4`, along with the escape's letter (or first octal digit) and digit count
+    InvalidCodePoint(u32, Span),       // a `\u` or `\U` escape that isn't a valid rune, like a surrogate half
+    OctalEscapeTooLarge(u32, Span),
+    UnescapedCharacter(char, Span),    // e.g. a raw newline or tab inside of a string or char
+    EmptyChar(Span),
+    UnterminatedChar(Span),
+    UnterminatedString(Span),     // a string still open at the end of the file
+    UnterminatedRawString(Span),
+    UnterminatedComment(Span),    // a `/*` comment still open at the end of the file
+    InvalidDigit(char, &'static str, Span), // e.g. `8` in an octal literal, along with the kind of literal
+    MissingDigits(&'static str, Span),      // a base prefix like `0x` with nothing after it
+    MisplacedUnderscore(Span),              // `_` that doesn't separate two digits
+    InvalidRadixPoint(&'static str, Span),  // a `.` in a binary or octal literal
+    InvalidExponent(char, Span),            // an `e` exponent on a hex literal or a `p` exponent on a decimal one
+    MissingExponent(Span),                  // a hex float without a `p` exponent, e.g. `0x1.8`
+    MissingExponentDigits(Span),
+    ExponentTooLarge(Span),
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnknownCharacter(_, span)
+            | Self::IncompleteElipses(span)
+            | Self::InvalidEscapeSequence(_, span)
+            | Self::InvalidUtf8(span)
+            | Self::MalformedEscape(_, _, span)
+            | Self::InvalidCodePoint(_, span)
+            | Self::OctalEscapeTooLarge(_, span)
+            | Self::UnescapedCharacter(_, span)
+            | Self::EmptyChar(span)
+            | Self::UnterminatedChar(span)
+            | Self::UnterminatedString(span)
+            | Self::UnterminatedRawString(span)
+            | Self::UnterminatedComment(span)
+            | Self::InvalidDigit(_, _, span)
+            | Self::MissingDigits(_, span)
+            | Self::MisplacedUnderscore(span)
+            | Self::InvalidRadixPoint(_, span)
+            | Self::InvalidExponent(_, span)
+            | Self::MissingExponent(span)
+            | Self::MissingExponentDigits(span)
+            | Self::ExponentTooLarge(span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line = self.span().start.line;
+        match self {
+            Self::UnknownCharacter(c, _) => write!(f, "ERROR: Unknown character {:?} found on line {}", c, line),
+            Self::IncompleteElipses(_) => write!(f, "ERROR: Unknown symbol \"..\" found on line {}", line),
+            Self::InvalidEscapeSequence(Some(c), _) => write!(f, "ERROR: Invalid escape character sequence \"\\{}\" on line {}", c, line),
+            Self::InvalidEscapeSequence(None, _) => write!(f, "ERROR: escape sequence not terminated on line {}", line),
+            Self::InvalidUtf8(_) => write!(f, "ERROR: invalid UTF-8 encoding on line {}", line),
+            Self::MalformedEscape(c, digits, _) => {
+                let kind = if c.is_ascii_digit() { "octal" } else { "hexadecimal" };
+                write!(f, "ERROR: escape sequence must have {} {} digits on line {}", digits, kind, line)
+            }
+            Self::InvalidCodePoint(value, _) => write!(f, "ERROR: escape sequence is invalid Unicode code point U+{:04X} on line {}", value, line),
+            Self::OctalEscapeTooLarge(value, _) => write!(f, "ERROR: octal escape value {} > 255 on line {}", value, line),
+            Self::UnescapedCharacter(c, _) => write!(f, "ERROR: Escape character {:?} inside of string or char (perhaps you want to use a valid escape sequence instead?) on line {}", c, line),
+            Self::EmptyChar(_) => write!(f, "ERROR: empty character on line {}", line),
+            Self::UnterminatedChar(_) => write!(f, "ERROR: unterminated char literal on line {}", line),
+            Self::UnterminatedString(_) => write!(f, "ERROR: unterminated string starting at line {}", line),
+            Self::UnterminatedRawString(_) => write!(f, "ERROR: raw string literal not terminated, starting on line {}", line),
+            Self::UnterminatedComment(_) => write!(f, "ERROR: unterminated comment starting at line {}", line),
+            Self::InvalidDigit(c, kind, _) => write!(f, "ERROR: invalid digit {:?} in {} literal on line {}", c, kind, line),
+            Self::MissingDigits(kind, _) => write!(f, "ERROR: {} literal has no digits on line {}", kind, line),
+            Self::MisplacedUnderscore(_) => write!(f, "ERROR: '_' must separate successive digits on line {}", line),
+            Self::InvalidRadixPoint(kind, _) => write!(f, "ERROR: invalid radix point in {} literal on line {}", kind, line),
+            Self::InvalidExponent('p', _) => write!(f, "ERROR: 'p' exponent requires hexadecimal mantissa on line {}", line),
+            Self::InvalidExponent(c, _) => write!(f, "ERROR: '{}' exponent requires decimal mantissa on line {}", c, line),
+            Self::MissingExponent(_) => write!(f, "ERROR: hexadecimal mantissa requires a 'p' exponent on line {}", line),
+            Self::MissingExponentDigits(_) => write!(f, "ERROR: exponent has no digits on line {}", line),
+            Self::ExponentTooLarge(_) => write!(f, "ERROR: exponent too large on line {}", line),
+        }
+    }
+}
+
+impl ToDiagnostic for LexError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let span = self.span();
+        match self {
+            Self::UnknownCharacter(c, _) => Diagnostic::error("E0101", format!("unknown character {:?}", c), span)
+                    .with_primary_label("not valid in source"),
+            Self::IncompleteElipses(_) => Diagnostic::error("E0102", "unknown symbol `..`", span)
+                    .with_suggestion("variadic parameters take three dots", span, "..."),
+            Self::InvalidEscapeSequence(Some(c), _) => {
+                Diagnostic::error("E0103", format!("unknown escape sequence `\\{}`", c), span)
+                        .with_help("valid escapes are \\a \\b \\f \\n \\r \\t \\v \\\\ \\' \\\", \\x, \\u, \\U and octal escapes")
+            }
+            Self::InvalidEscapeSequence(None, _) => Diagnostic::error("E0103", "escape sequence not terminated", span),
+            Self::InvalidUtf8(_) => Diagnostic::error("E0104", "invalid UTF-8 encoding", span),
+            Self::MalformedEscape(c, digits, _) => {
+                let kind = if c.is_ascii_digit() { "octal" } else { "hexadecimal" };
+                Diagnostic::error("E0105", format!("escape sequence must have {} {} digits", digits, kind), span)
+            }
+            Self::InvalidCodePoint(value, _) => Diagnostic::error(
+                "E0106",
+                format!("escape sequence is invalid Unicode code point U+{:04X}", value),
+                span,
+            ),
+            Self::OctalEscapeTooLarge(value, _) => {
+                Diagnostic::error("E0107", format!("octal escape value {} > 255", value), span)
+            }
+            Self::UnescapedCharacter('\n', _) => {
+                Diagnostic::error("E0108", "unescaped newline in string or char literal", span)
+                        .with_help("use the escape sequence \\n, or a raw string to span several lines")
+            }
+            Self::UnescapedCharacter(c, _) => {
+                Diagnostic::error("E0108", format!("unescaped {:?} in string or char literal", c), span)
+                        .with_suggestion("use an escape sequence instead", span, escape_sequence(*c))
+            }
+            Self::EmptyChar(_) => Diagnostic::error("E0109", "empty character literal", span),
+            Self::UnterminatedChar(_) => Diagnostic::error("E0110", "unterminated character literal", span)
+                    .with_help("a character literal holds exactly one character or escape sequence"),
+            Self::UnterminatedString(_) => Diagnostic::error("E0111", "unterminated string literal", span)
+                    .with_primary_label("string starts here"),
+            Self::UnterminatedRawString(_) => Diagnostic::error("E0112", "unterminated raw string literal", span)
+                    .with_primary_label("raw string starts here")
+                    .with_suggestion("close the raw string at the end of the file", end_of(span), "`"),
+            Self::UnterminatedComment(_) => Diagnostic::error("E0113", "unterminated block comment", span)
+                    .with_primary_label("comment starts here")
+                    .with_suggestion("close the comment at the end of the file", end_of(span), "*/"),
+            Self::InvalidDigit(c, kind, _) => {
+                Diagnostic::error("E0114", format!("invalid digit {:?} in {} literal", c, kind), span)
+            }
+            Self::MissingDigits(kind, _) => Diagnostic::error("E0115", format!("{} literal has no digits", kind), span),
+            Self::MisplacedUnderscore(_) => Diagnostic::error("E0116", "'_' must separate successive digits", span),
+            Self::InvalidRadixPoint(kind, _) => {
+                Diagnostic::error("E0117", format!("invalid radix point in {} literal", kind), span)
+            }
+            Self::InvalidExponent('p', _) => {
+                Diagnostic::error("E0118", "'p' exponent requires hexadecimal mantissa", span)
+            }
+            Self::InvalidExponent(c, _) => {
+                Diagnostic::error("E0118", format!("'{}' exponent requires decimal mantissa", c), span)
+            }
+            Self::MissingExponent(_) => Diagnostic::error("E0119", "hexadecimal mantissa requires a 'p' exponent", span),
+            Self::MissingExponentDigits(_) => Diagnostic::error("E0120", "exponent has no digits", span),
+            Self::ExponentTooLarge(_) => Diagnostic::error("E0121", "exponent too large", span),
+        }
+    }
+}
+
+/// The empty span at the end of `span`, where missing text can be inserted.
+fn end_of(span: Span) -> Span {
+    Span {
+        start: span.end,
+        ..span
+    }
+}
+
+/// The escape sequence that stands for a character which can't appear unescaped in a literal.
+fn escape_sequence(c: char) -> String {
+    match c {
+        '\x07' => "\\a".to_string(),
+        '\x08' => "\\b".to_string(),
+        '\x0c' => "\\f".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        '\x0b' => "\\v".to_string(),
+        c => format!("\\x{:02x}", c as u32),
+    }
+}
 
 pub struct Lexer {
-    reader: BufReader<File>,
-    current_line: u32,
-    current_char: char,
+    reader: Box<dyn BufRead>,
+    file_id: usize,
+    current_char: Option<char>, // None at the end of the file
+    current_pos: Position, // position of current_char
+    invalid_utf8: Option<Span>, // the first invalid UTF-8 sequence read since the last token
+    next_pos: Position,    // position of the next character to be read
+    insert_semicolon: bool, // whether a newline or EOF right now should produce a semicolon
+    pending_semicolon: bool, // set when a block comment swallowed a newline that needs a semicolon
+    pending_error: Option<LexError>, // an error in a trailing comment, reported after the token it follows
 }
 
 impl Lexer {
-    pub fn new(input_file: String) -> Lexer {
-        let input = File::open(input_file);
-        let reader = BufReader::new(input.unwrap());
-        Lexer {
-            reader,
-            current_line: 0,
-            current_char: ' ',
-        }
+    /// Creates a lexer for the file at the given path, failing if it can't be opened.
+    pub fn new(input_file: String) -> io::Result<Lexer> {
+        Ok(Lexer::from_reader(File::open(input_file)?, 0))
+    }
+
+    /// Creates a lexer for source that's already in memory, like a snippet in a test or a buffer in an editor.
+    pub fn from_source(source: &str) -> Lexer {
+        Lexer::from_reader(Cursor::new(source.as_bytes().to_vec()), 0)
+    }
+
+    /// Creates a lexer that reads its source from any reader, such as stdin.
+    pub fn from_reader(reader: impl Read + 'static, file_id: usize) -> Lexer {
+        let start = Position::new(1, 1, 0);
+        let mut lexer = Lexer {
+            reader: Box::new(BufReader::new(reader)),
+            file_id,
+            current_char: None,
+            current_pos: start,
+            invalid_utf8: None,
+            next_pos: start,
+            insert_semicolon: false,
+            pending_semicolon: false,
+            pending_error: None,
+        };
+        lexer.advance();
+        lexer
     }
 
-    /// consumes the next character in the file. 
-    /// 
-    /// If EOF is reached, returns nothing. 
+    /// Consumes the next character in the file, decoding it from UTF-8.
+    ///
+    /// If EOF is reached, current_char is set to None. An invalid UTF-8 sequence becomes U+FFFD and is
+    /// remembered in invalid_utf8, so the token it's part of can be reported as an error.
     fn advance(&mut self) {
-        return match self.reader.fill_buf() {
-            Ok(buffer) if (buffer.len() > 0)  => {
-                let tmp = buffer[0] as char;
-                self.reader.consume(1);
-                self.current_char = tmp;
-            }
+        self.current_pos = self.next_pos;
+        let first = match self.reader.fill_buf() {
+            Ok(buffer) if !buffer.is_empty() => buffer[0],
             _ => {
-                self.current_char = EOF_CHAR;
+                self.current_char = None;
+                return;
             }
         };
+        self.reader.consume(1);
+        let len = match first {
+            0x00..=0x7f => 1,
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => 0, // a continuation byte or a byte that never appears in UTF-8
+        };
+        let mut bytes = vec![first];
+        while bytes.len() < len {
+            match self.reader.fill_buf() {
+                Ok(buffer) if !buffer.is_empty() && buffer[0] & 0xc0 == 0x80 => {
+                    bytes.push(buffer[0]);
+                    self.reader.consume(1);
+                }
+                _ => break,
+            }
+        }
+        let decoded = std::str::from_utf8(&bytes).ok().and_then(|str| str.chars().next());
+        if decoded.is_none() && self.invalid_utf8.is_none() {
+            let end = Position {
+                column: self.next_pos.column + 1,
+                offset: self.next_pos.offset + bytes.len(),
+                ..self.next_pos
+            };
+            self.invalid_utf8 = Some(Span { file_id: self.file_id, start: self.next_pos, end });
+        }
+        let c = decoded.unwrap_or(char::REPLACEMENT_CHARACTER);
+        self.current_char = Some(c);
+        if c == '\n' {
+            self.next_pos.line += 1;
+            self.next_pos.column = 1;
+        } else {
+            self.next_pos.column += 1;
+        }
+        self.next_pos.offset += bytes.len();
     }
 
-    pub fn lex(&mut self) -> token::TokenKind {
+    /// Returns the next token in the file, along with its span.
+    ///
+    /// On error, the offending input has already been skipped, so calling `lex` again
+    /// picks up with the rest of the file.
+    ///
+    /// Semicolons are inserted at the end of a line (or the file) following the rules
+    /// in the language specification. These tokens are marked as `implicit`.
+    ///
+    /// Comments aren't returned as tokens. A comment after a token on the same line is part of that
+    /// token's `trailing` trivia, any other comment is part of the next token's `leading` trivia.
+    pub fn lex(&mut self) -> Result<Token, LexError> {
+        if let Some(error) = self.pending_error.take() {
+            return Err(error);
+        }
+        if self.pending_semicolon {
+            self.pending_semicolon = false;
+            self.insert_semicolon = false;
+            return Ok(self.implicit_semicolon(self.span_from(self.current_pos)));
+        }
         self.skip_whitespace();
-        match self.current_char {
-            EOF_CHAR => TokenKind::EOF,
-            '(' => {
+        if self.insert_semicolon && (self.current_char == Some('\n') || self.current_char.is_none()) {
+            let span = self.current_char_span();
+            self.advance();
+            self.insert_semicolon = false;
+            return Ok(self.implicit_semicolon(span));
+        }
+
+        let mut leading = vec![];
+        while let Some(comment) = self.read_comment()? {
+            leading.push(comment);
+            self.skip_whitespace();
+        }
+        self.check_utf8()?;
+        let start = self.current_pos;
+        let kind = self.lex_kind();
+        self.check_utf8()?;
+        let kind = kind?;
+        let span = self.span_from(start);
+        self.insert_semicolon = kind.triggers_semicolon();
+        let trailing = self.read_trailing_comments();
+        Ok(Token {
+            kind,
+            span,
+            implicit: false,
+            leading,
+            trailing,
+        })
+    }
+
+    /// Reports the invalid UTF-8 sequence read since the last token, if any.
+    fn check_utf8(&mut self) -> Result<(), LexError> {
+        // current_char hasn't been lexed yet, so an invalid sequence there belongs to the next token.
+        match self.invalid_utf8.filter(|invalid| invalid.start.offset < self.current_pos.offset) {
+            Some(invalid) => {
+                self.invalid_utf8 = None;
+                Err(LexError::InvalidUtf8(invalid))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Reads the comments following a token on the same line.
+    fn read_trailing_comments(&mut self) -> Vec<Trivia> {
+        let mut trailing = vec![];
+        loop {
+            while matches!(self.current_char, Some(' ' | '\t' | '\r')) {
+                self.advance();
+            }
+            match self.read_comment() {
+                Ok(Some(comment)) => {
+                    let spans_lines = comment.span.start.line != comment.span.end.line;
+                    trailing.push(comment);
+                    if spans_lines {
+                        // a block comment spanning lines acts like a newline.
+                        self.pending_semicolon = self.insert_semicolon;
+                        return trailing;
+                    }
+                }
+                Ok(None) => return trailing,
+                Err(error) => {
+                    self.pending_error = Some(error);
+                    return trailing;
+                }
+            }
+        }
+    }
+
+    /// Reads a `//` or `/* */` comment if one starts at current_char.
+    fn read_comment(&mut self) -> Result<Option<Trivia>, LexError> {
+        if self.current_char != Some('/') {
+            return Ok(None);
+        }
+        let kind = match self.peek_byte() {
+            Some(b'/') => TriviaKind::LineComment,
+            Some(b'*') => TriviaKind::BlockComment,
+            _ => return Ok(None),
+        };
+        let start = self.current_pos;
+        let mut text = String::new();
+        self.push_current(&mut text);
+        self.push_current(&mut text);
+        match kind {
+            TriviaKind::LineComment => {
+                while self.current_char != Some('\n') && self.current_char.is_some() {
+                    self.push_current(&mut text);
+                }
+            }
+            TriviaKind::BlockComment => {
+                while !(self.current_char == Some('*') && self.peek_byte() == Some(b'/')) {
+                    if self.current_char.is_none() {
+                        return Err(LexError::UnterminatedComment(self.span_from(start)));
+                    }
+                    self.push_current(&mut text);
+                }
+                self.push_current(&mut text);
+                self.push_current(&mut text);
+            }
+        }
+        Ok(Some(Trivia {
+            kind,
+            text,
+            span: self.span_from(start),
+        }))
+    }
+
+    /// The byte after current_char, without consuming it.
+    fn peek_byte(&mut self) -> Option<u8> {
+        self.reader.fill_buf().ok().and_then(|buffer| buffer.first().copied())
+    }
+
+    fn implicit_semicolon(&self, span: Span) -> Token {
+        Token {
+            kind: TokenKind::Semicolon,
+            span,
+            implicit: true,
+            leading: vec![],
+            trailing: vec![],
+        }
+    }
+
+    /// Lexes the rest of the file, recovering from errors as they are found.
+    ///
+    /// Returns every token up to and including EOF, along with every error encountered.
+    pub fn lex_all(&mut self) -> (Vec<Token>, Vec<LexError>) {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+        loop {
+            match self.lex() {
+                Ok(token) => {
+                    let done = token.kind == TokenKind::EOF;
+                    tokens.push(token);
+                    if done {
+                        return (tokens, errors);
+                    }
+                }
+                Err(error) => errors.push(error),
+            }
+        }
+    }
+
+    /// The span from `start` up to (but not including) current_char.
+    fn span_from(&self, start: Position) -> Span {
+        Span {
+            file_id: self.file_id,
+            start,
+            end: self.current_pos,
+        }
+    }
+
+    /// The span covering only current_char.
+    fn current_char_span(&self) -> Span {
+        Span {
+            file_id: self.file_id,
+            start: self.current_pos,
+            end: self.next_pos,
+        }
+    }
+
+    fn lex_kind(&mut self) -> Result<TokenKind, LexError> {
+        let start = self.current_pos;
+        Ok(match self.current_char {
+            None => TokenKind::EOF,
+            Some('(') => {
                 self.advance();
                 TokenKind::LeftParen 
             },
-            ')' => {
+            Some(')') => {
                 self.advance();
                 TokenKind::RightParen
             },
-            '{' => {
+            Some('{') => {
                 self.advance();
                 TokenKind::LeftBrace
             },
-            '}' => {
+            Some('}') => {
                 self.advance();
                 TokenKind::RightBrace
             }
-            '[' => {
+            Some('[') => {
                 self.advance();
                 TokenKind::LeftBracket
             },
-            ']' => {
+            Some(']') => {
                 self.advance();
                 TokenKind::RightBracket
             },
-            '~' => {
+            Some('~') => {
                 self.advance();
                 TokenKind::BitNotOp
             },
-            ';' => {
+            Some(';') => {
                 self.advance();
                 TokenKind::Semicolon
             },
-            ',' => {
+            Some(',') => {
                 self.advance();
                 TokenKind::Comma
             },
-            '+' => {
+            Some('+') => {
                 self.advance();
                 match self.current_char {
-                    '+' => {
+                    Some('+') => {
                         self.advance();
                         TokenKind::IncrementOp
                     },
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::PlusEqOp
                     },
                     _ => TokenKind::PlusOp,
                 }
             }
-            '-' => {
+            Some('-') => {
                 self.advance();
                 match self.current_char {
-                    '-' => {
+                    Some('-') => {
                         self.advance();
                         TokenKind::DecrementOp
                     },
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::MinusEqOp
                     },
                     _ => TokenKind::MinusOp,
                 }
             }
-            '*' => {
+            Some('*') => {
                 self.advance();
                 match self.current_char {
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::StarEqOp
                     }
@@ -117,39 +537,22 @@ impl Lexer {
                     }
                 }
             }
-            '/' => {
+            Some('/') => {
                 self.advance();
                 match self.current_char {
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::DivEqOp
                     }
-                    '/' => {
-                        while self.current_char != '\n' {
-                            self.advance();
-                        }
-                        TokenKind::Comment
-                    }
-                    '*' => {
-                        let mut star_found = false;
-                        loop {
-                            self.advance();
-                            if star_found && self.current_char == '/' {
-                                break;
-                            }
-                            star_found = self.current_char == '*';
-                        }
-                        TokenKind::Comment
-                    }
                     _ => {
                         TokenKind::DivOp
                     }
                 }
             }
-            '%' => {
+            Some('%') => {
                 self.advance();
                 match self.current_char {
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::ModEqOp
                     }
@@ -158,10 +561,10 @@ impl Lexer {
                     }
                 }
             }
-            '=' => {
+            Some('=') => {
                 self.advance();
                 match self.current_char {
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::LogicEqOp
                     }
@@ -170,10 +573,10 @@ impl Lexer {
                     }
                 }
             }
-            ':' => {
+            Some(':') => {
                 self.advance();
                 match self.current_char {
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::AssignOp
                     }
@@ -182,20 +585,20 @@ impl Lexer {
                     }
                 }
             }
-            '&' => {
+            Some('&') => {
                 self.advance();
                 match self.current_char {
-                    '&' => {
+                    Some('&') => {
                         self.advance();
                         TokenKind::LogicAndOp
                     }
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::BitAndEqOp
                     }
-                    '^' => {
+                    Some('^') => {
                         self.advance();
-                        if self.current_char == '=' {
+                        if self.current_char == Some('=') {
                             self.advance();
                             TokenKind::BitClearEqOp
                         } else {
@@ -207,14 +610,14 @@ impl Lexer {
                     }
                 }
             }
-            '|' => {
+            Some('|') => {
                 self.advance();
                 match self.current_char {
-                    '|' => {
+                    Some('|') => {
                         self.advance();
                         TokenKind::LogicOrOp
                     }
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::BitOrEqOp
                     }
@@ -223,10 +626,10 @@ impl Lexer {
                     }
                 }
             }
-            '^' => {
+            Some('^') => {
                 self.advance();
                 match self.current_char {
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::BitXorEqOp
                     }
@@ -235,10 +638,10 @@ impl Lexer {
                     }
                 }
             }
-            '!' => {
+            Some('!') => {
                 self.advance();
                 match self.current_char {
-                    '=' => {
+                    Some('=') => {
                         self.advance();
                         TokenKind::LogicNotEqOp
                     }
@@ -247,71 +650,54 @@ impl Lexer {
                     }
                 }
             }
-            '<' => self.handle_less_than(),
-            '>' => self.handle_greater_than(),
-            '.' => {
+            Some('<') => self.handle_less_than(),
+            Some('>') => self.handle_greater_than(),
+            Some('.') => {
                 self.advance();
                 match self.current_char {
-                    '.' => {
+                    Some('.') => {
                         self.advance();
-                        if self.current_char != '.' {
-                            panic!("ERROR: Unknown symbol found on line {}", self.current_line);
+                        if self.current_char != Some('.') {
+                            return Err(LexError::IncompleteElipses(self.span_from(start)));
                         }
                         self.advance();
                         TokenKind::Elipses
                     }
-                    '0'..='9' => { // floats that are < 1 without a leading 0.
-                        TokenKind::FloatLiteral(self.handle_decimal())
-                    }
+                    Some('0'..='9') => self.handle_number(start, String::from("."))?, // floats that are < 1 without a leading 0.
                     _ => TokenKind::Dot,
                 }
             }
-            '\"' => self.handle_strings(),
-            '\'' => self.handle_char(),
-            _ => {
-                if self.current_char.is_ascii_alphabetic() || self.current_char == '_' {
-                    self.handle_identifers_and_keywords()
-                } else if self.current_char.is_digit(10) {
-                    self.handle_number()
-                }else {
-                    panic!("ERROR: Unknown character found on line {}", self.current_line);
-                }
+            Some('\"') => self.handle_strings()?,
+            Some('`') => self.handle_raw_string(start)?,
+            Some('\'') => self.handle_char()?,
+            // like Go, identifiers can use any Unicode letter.
+            Some(c) if c.is_alphabetic() || c == '_' => self.handle_identifers_and_keywords(),
+            Some(c) if c.is_ascii_digit() => self.handle_number(start, String::new())?,
+            Some(c) => {
+                let error = LexError::UnknownCharacter(c, self.current_char_span());
+                self.advance();
+                return Err(error);
             }
-        }
-    }
-
-    /// Returns the float value of the decimal part of a float.
-    fn handle_decimal(&mut self) -> f32 {
-        let mut float_value = vec!['0', '.', self.current_char];
-        self.advance();
-        while self.current_char.is_digit(10) {
-            float_value.push(self.current_char);
-            self.advance();
-        }
-        float_value
-                .into_iter()
-                .collect::<String>()
-                .parse::<f32>()
-                .unwrap()
+        })
     }
 
     fn handle_less_than(&mut self) -> TokenKind {
         self.advance();
         match self.current_char {
-            '-' => {
+            Some('-') => {
                 self.advance();
                 TokenKind::DirectionOp
             }
-            '<' => {
+            Some('<') => {
                 self.advance();
-                if self.current_char == '=' {
+                if self.current_char == Some('=') {
                     self.advance();
                     TokenKind::LShiftEqOp
                 } else {
                     TokenKind::LShiftOp
                 }
             }
-            '=' => {
+            Some('=') => {
                 self.advance();
                 TokenKind::LogicLessEqOp
             }
@@ -324,16 +710,16 @@ impl Lexer {
     fn handle_greater_than(&mut self) -> TokenKind {
         self.advance();
         match self.current_char {
-            '<' => {
+            Some('>') => {
                 self.advance();
-                if self.current_char == '=' {
+                if self.current_char == Some('=') {
                     self.advance();
                     TokenKind::RShiftEqOp
                 } else {
                     TokenKind::RShiftOp
                 }
             }
-            '=' => {
+            Some('=') => {
                 self.advance();
                 TokenKind::LogicGreaterEqOp
             }
@@ -343,89 +729,224 @@ impl Lexer {
         }
     }
 
-    fn handle_strings(&mut self) -> TokenKind {
-        let mut string_result: Vec<char> = vec![];
+    fn handle_strings(&mut self) -> Result<TokenKind, LexError> {
+        let start = self.current_pos;
+        let mut raw = String::from('"');
+        let mut value = vec![];
+        let mut error = None;
         self.advance();
-        while self.current_char != '\"' {
-            if self.current_char == '\\' {
-                string_result.push('\\');
-                self.advance();
-                if !self.is_valid_escape_sequence_char(self.current_char) {
-                    panic!("ERROR: Invalid escape character sequence");
+        while self.current_char != Some('\"') {
+            if self.current_char == Some('\n') {
+                // the string can't continue onto the next line, so stop here.
+                let newline_error = LexError::UnescapedCharacter('\n', self.current_char_span());
+                return Err(error.unwrap_or(newline_error));
+            }
+            if self.current_char.is_none() {
+                return Err(LexError::UnterminatedString(self.span_from(start)));
+            }
+            if self.current_char == Some('\\') {
+                match self.read_escape(&mut raw) {
+                    Ok(Escape::Byte(byte)) => value.push(byte),
+                    Ok(Escape::Rune(rune)) => value.extend_from_slice(rune.encode_utf8(&mut [0; 4]).as_bytes()),
+                    Err(escape_error) => {
+                        error.get_or_insert(escape_error);
+                    }
                 }
-            } else if self.is_escape(self.current_char) {
-                panic!("ERROR: Escape character inside of string (perhaps you want to use a valid escape sequence instead?)");
+                continue;
+            }
+            let c = self.current_char.unwrap();
+            if Self::is_escape(c) {
+                error.get_or_insert(LexError::UnescapedCharacter(c, self.current_char_span()));
             }
-            string_result.push(self.current_char);
+            raw.push(c);
+            value.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
             self.advance()
         }
+        raw.push('"');
         self.advance();
-        TokenKind::StringLiteral(string_result.into_iter().collect::<String>().to_string())
+        match error {
+            Some(error) => Err(error),
+            None => Ok(TokenKind::StringLiteral(raw, value)),
+        }
     }
- 
-    fn handle_char(&mut self) -> TokenKind {
+
+    /// Lexes a backtick string. It can span lines and backslashes have no special meaning. Carriage returns
+    /// are dropped, like Go does, so the value doesn't depend on the line endings of the file.
+    fn handle_raw_string(&mut self, start: Position) -> Result<TokenKind, LexError> {
+        let mut string_result = String::new();
         self.advance();
-        let mut char_result: Vec<char> = vec![];
-        if self.current_char == '\'' {
-            panic!("ERROR: empty character");
+        while self.current_char != Some('`') {
+            match self.current_char {
+                None => return Err(LexError::UnterminatedRawString(self.span_from(start))),
+                Some('\r') => {}
+                Some(c) => string_result.push(c),
+            }
+            self.advance();
         }
-        if self.current_char == '\\' {
-            char_result.push('\\');
+        self.advance();
+        Ok(TokenKind::RawStringLiteral(string_result))
+    }
+
+    fn handle_char(&mut self) -> Result<TokenKind, LexError> {
+        let start = self.current_pos;
+        self.advance();
+        let mut raw = String::from('\'');
+        let mut rune = None;
+        let mut error = None;
+        if self.current_char == Some('\'') {
             self.advance();
-            if !self.is_valid_escape_sequence_char(self.current_char) {
-                panic!("ERROR: Invalid escape character sequence");
+            return Err(LexError::EmptyChar(self.span_from(start)));
+        }
+        if self.current_char == Some('\\') {
+            match self.read_escape(&mut raw) {
+                Ok(Escape::Byte(byte)) => rune = Some(byte as char),
+                Ok(Escape::Rune(value)) => rune = Some(value),
+                Err(escape_error) => error = Some(escape_error),
             }
-        } else if self.is_escape(self.current_char) {
-            panic!("ERROR: Escape character inside of char (perhaps you want to use a valid escape sequence instead?)");
+        } else if let Some(c) = self.current_char.filter(|c| *c != '\n') {
+            if Self::is_escape(c) {
+                error = Some(LexError::UnescapedCharacter(c, self.current_char_span()));
+            }
+            raw.push(c);
+            rune = Some(c);
+            self.advance();
         }
-        char_result.push(self.current_char);
-        self.advance();
-        if self.current_char != '\'' {
-            panic!("ERROR: invalid char literal");
+        if self.current_char != Some('\'') {
+            // skip the rest of the literal, without running past the end of the line.
+            while self.current_char != Some('\'') && self.current_char != Some('\n') && self.current_char.is_some() {
+                self.advance();
+            }
+            if self.current_char == Some('\'') {
+                self.advance();
+            }
+            return Err(LexError::UnterminatedChar(self.span_from(start)));
         }
+        raw.push('\'');
         self.advance();
-        TokenKind::CharLiteral(char_result.into_iter().collect::<String>().to_string())
-    }
-
-    fn is_valid_escape_sequence_char(&mut self, c: char) -> bool {
-        if c == 'a' || c == 'b' || c == 'f' || c == 'n'
-                || c == 'r' || c == 't' || c == 'v' || c == '\\'
-                || c == '\'' || c == '\"' {
-            true
-        } else {
-            false
+        match error {
+            Some(error) => Err(error),
+            None => Ok(TokenKind::CharLiteral(raw, rune.unwrap_or_default())),
         }
     }
 
-    fn handle_number(&mut self) -> TokenKind {
-        let mut number = String::new();
-        number.push(self.current_char);
+    /// Reads an escape sequence starting at the `\\` in current_char, adding its text to `raw`.
+    ///
+    /// `\\x` and octal escapes give a single byte, everything else gives a rune.
+    fn read_escape(&mut self, raw: &mut String) -> Result<Escape, LexError> {
+        let start = self.current_pos;
+        raw.push('\\');
         self.advance();
-        while self.current_char.is_digit(10) {
-            number.push(self.current_char);
+        let c = match self.current_char {
+            Some(c) => c,
+            None => return Err(LexError::InvalidEscapeSequence(None, self.span_from(start))),
+        };
+        let simple = match c {
+            'a' => Some('\x07'),
+            'b' => Some('\x08'),
+            'f' => Some('\x0c'),
+            'n' => Some('\n'),
+            'r' => Some('\r'),
+            't' => Some('\t'),
+            'v' => Some('\x0b'),
+            '\\' | '\'' | '"' => Some(c),
+            _ => None,
+        };
+        let (digits, radix) = match c {
+            _ if simple.is_some() => (0, 0),
+            'x' => (2, 16),
+            'u' => (4, 16),
+            'U' => (8, 16),
+            '0'..='7' => (3, 8),
+            _ => {
+                let span = Span { end: self.next_pos, ..self.span_from(start) };
+                // leave a newline for the caller, which has to stop there.
+                if c != '\n' {
+                    raw.push(c);
+                    self.advance();
+                }
+                return Err(LexError::InvalidEscapeSequence(Some(c), span));
+            }
+        };
+        if let Some(rune) = simple {
+            raw.push(c);
             self.advance();
+            return Ok(Escape::Rune(rune));
         }
-        let int_result = number.parse::<i32>().unwrap();
-        if self.current_char == '.' {
+        // the digits of an octal escape start right after the `\\`.
+        if radix == 16 {
+            raw.push(c);
             self.advance();
-            let mut float_result = int_result as f32;
-            if self.current_char.is_digit(10) {
-                let decimal_part = self.handle_decimal();
-                float_result = float_result + decimal_part;
+        }
+        let mut value: u32 = 0;
+        for _ in 0..digits {
+            match self.current_char.and_then(|digit| Some((digit, digit.to_digit(radix)?))) {
+                Some((digit, digit_value)) => {
+                    value = value * radix + digit_value;
+                    raw.push(digit);
+                }
+                None => return Err(LexError::MalformedEscape(c, digits, self.span_from(start))),
             }
-            TokenKind::FloatLiteral(float_result)
-        } else {
-            TokenKind::IntLiteral(int_result)
+            self.advance();
+        }
+        let span = self.span_from(start);
+        match c {
+            'u' | 'U' => char::from_u32(value).map(Escape::Rune).ok_or(LexError::InvalidCodePoint(value, span)),
+            'x' => Ok(Escape::Byte(value as u8)),
+            _ if value > 255 => Err(LexError::OctalEscapeTooLarge(value, span)),
+            _ => Ok(Escape::Byte(value as u8)),
         }
     }
 
+    /// Lexes an integer or float literal. The whole literal is read first and then parsed in one go by
+    /// `parse_number`. `text` is "." if the literal started with a `.`, which has already been consumed.
+    fn handle_number(&mut self, start: Position, mut text: String) -> Result<TokenKind, LexError> {
+        if text.is_empty() {
+            self.push_current(&mut text);
+            if let (Some(prefix @ ('x' | 'X' | 'b' | 'B' | 'o' | 'O')), "0") = (self.current_char, text.as_str()) {
+                text.push(prefix);
+                self.advance();
+            }
+        }
+        let hex = text.eq_ignore_ascii_case("0x");
+        self.read_digits(&mut text, hex);
+        if self.current_char == Some('.') && !text.starts_with('.') {
+            text.push('.');
+            self.advance();
+            self.read_digits(&mut text, hex);
+        }
+        // e is a digit in hex literals, so they can only have a p exponent.
+        let exponent = matches!(self.current_char, Some('p' | 'P')) || (!hex && matches!(self.current_char, Some('e' | 'E')));
+        if exponent {
+            self.push_current(&mut text);
+            if matches!(self.current_char, Some('+' | '-')) {
+                self.push_current(&mut text);
+            }
+            self.read_digits(&mut text, false);
+        }
+        parse_number(text, self.span_from(start))
+    }
+
+    /// Reads digits and `_` separators. All decimal digits are read whatever the base, so that e.g. `0b102`
+    /// is reported as one bad literal.
+    fn read_digits(&mut self, text: &mut String, hex: bool) {
+        while self.current_char.is_some_and(|c| c.is_ascii_digit() || c == '_' || (hex && c.is_ascii_hexdigit())) {
+            self.push_current(text);
+        }
+    }
+
+    /// Adds current_char to `text` and advances past it.
+    fn push_current(&mut self, text: &mut String) {
+        if let Some(c) = self.current_char {
+            text.push(c);
+        }
+        self.advance();
+    }
+
     fn handle_identifers_and_keywords(&mut self) -> TokenKind {
         let mut word = String::new();
-        word.push(self.current_char);
-        self.advance();
-        while self.current_char.is_ascii_alphanumeric() || self.current_char == '_' {
-            word.push(self.current_char);
-            self.advance();
+        self.push_current(&mut word);
+        while self.current_char.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.push_current(&mut word);
         }
         match word.as_str() {
             "break" => TokenKind::BreakKeyword,
@@ -457,78 +978,674 @@ impl Lexer {
         }
     }
 
-    fn is_escape(&mut self, c: char) -> bool {
-        let num_char = c as u8;
-        // Rust doesn't recognize some escapes like '\f' so I had to hard code them as u8's
-        if num_char == 0x07 || num_char == 0x08
-                || num_char == 0x0c || num_char == 0x0a
-                || num_char == 0x0d || num_char == 0x09
-                || num_char == 0x0b || num_char == 0x22
-                || num_char == 0x27 || num_char == 0x5c {
-            true
-        } else {
-            false
-        }
+    /// Whether `c` has to be written as an escape sequence inside a string or char. The other kind of quote
+    /// doesn't, so `"'"` and `'"'` are fine.
+    fn is_escape(c: char) -> bool {
+        matches!(c, '\x07' | '\x08' | '\x0c' | '\n' | '\r' | '\t' | '\x0b' | '\\')
     }
 
     /// Advances until the current_char is not a whitespace character.
+    ///
+    /// Stops at a newline if a semicolon needs to be inserted there.
     fn skip_whitespace(&mut self) {
-        while self.current_char.is_whitespace() {
-            if self.current_char == '\n' {
-                self.current_line += 1;
-            }
+        while self.current_char.is_some_and(|c| c.is_whitespace()) && !(self.insert_semicolon && self.current_char == Some('\n')) {
             self.advance();
         }
     }
 }
 
+/// The largest exponent allowed in a float literal. Anything bigger couldn't be represented by any type anyway.
+const MAX_EXPONENT: i64 = 10_000;
+
+/// Parses a literal read by `handle_number` into its exact value.
+fn parse_number(text: String, span: Span) -> Result<TokenKind, LexError> {
+    let (base, kind, body) = match text.get(..2).map(|prefix| prefix.to_ascii_lowercase()).as_deref() {
+        Some("0x") => (16, "hexadecimal", &text[2..]),
+        Some("0b") => (2, "binary", &text[2..]),
+        Some("0o") => (8, "octal", &text[2..]),
+        _ => (10, "decimal", text.as_str()),
+    };
+    let prefixed = base != 10;
+    let exponent_at = body.find(|c| matches!(c, 'p' | 'P') || (base != 16 && matches!(c, 'e' | 'E')));
+    let (mantissa, exponent) = match exponent_at {
+        Some(at) => (&body[..at], Some(&body[at..])),
+        None => (body, None),
+    };
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (mantissa, None),
+    };
+    if fraction.is_none() && exponent.is_none() {
+        return parse_int(&text, base, kind, span);
+    }
+
+    if let (Some(_), 2 | 8) = (fraction, base) {
+        return Err(LexError::InvalidRadixPoint(kind, span));
+    }
+    let exponent_char = exponent.and_then(|exponent| exponent.chars().next()).map(|c| c.to_ascii_lowercase());
+    match exponent_char {
+        Some('e') if base != 10 => return Err(LexError::InvalidExponent('e', span)),
+        Some('p') if base != 16 => return Err(LexError::InvalidExponent('p', span)),
+        None if base == 16 => return Err(LexError::MissingExponent(span)),
+        _ => {}
+    }
+    check_underscores(whole, prefixed, span)?;
+    let fraction = fraction.unwrap_or_default();
+    check_underscores(fraction, false, span)?;
+    let digits = format!("{}{}", whole, fraction).replace('_', "");
+    if digits.is_empty() {
+        return Err(LexError::MissingDigits(kind, span));
+    }
+
+    let exponent = match exponent {
+        Some(exponent) => {
+            let exponent = exponent[1..].trim_start_matches('+');
+            let exponent_digits = exponent.trim_start_matches('-');
+            check_underscores(exponent_digits, false, span)?;
+            if exponent_digits.is_empty() {
+                return Err(LexError::MissingExponentDigits(span));
+            }
+            match exponent.replace('_', "").parse::<i64>() {
+                Ok(exponent) if exponent.abs() <= MAX_EXPONENT => exponent,
+                _ => return Err(LexError::ExponentTooLarge(span)),
+            }
+        }
+        None => 0,
+    };
+    // the value is mantissa * 10^exponent for decimal literals and mantissa * 2^exponent for hex ones.
+    let fraction_len = fraction.replace('_', "").len() as i64;
+    let (radix, power) = if base == 16 { (2, exponent - 4 * fraction_len) } else { (10, exponent - fraction_len) };
+    let mantissa = BigInt::parse_bytes(digits.as_bytes(), base).unwrap();
+    let scale = BigInt::from(radix).pow(power.unsigned_abs() as u32);
+    let value = if power >= 0 {
+        BigRational::from_integer(mantissa * scale)
+    } else {
+        BigRational::new(mantissa, scale)
+    };
+    Ok(TokenKind::FloatLiteral(text, value))
+}
+
+/// Parses an integer literal in the given base. Decimal literals with a leading 0 are octal, e.g. `0755`.
+fn parse_int(text: &str, base: u32, kind: &'static str, span: Span) -> Result<TokenKind, LexError> {
+    let prefixed = base != 10;
+    let (digits, base, kind) = match base {
+        10 if text.len() > 1 && text.starts_with('0') => (&text[1..], 8, "octal"),
+        10 => (text, 10, kind),
+        _ => (&text[2..], base, kind),
+    };
+    // after a prefix like 0x, the literal can start with an underscore.
+    check_underscores(if prefixed { digits } else { text }, prefixed, span)?;
+    if digits.is_empty() && prefixed {
+        return Err(LexError::MissingDigits(kind, span));
+    }
+    let digits = digits.replace('_', "");
+    if let Some(invalid) = digits.chars().find(|c| !c.is_digit(base)) {
+        return Err(LexError::InvalidDigit(invalid, kind, span));
+    }
+    let value = BigInt::parse_bytes(digits.as_bytes(), base).unwrap();
+    Ok(TokenKind::IntLiteral(text.to_string(), value))
+}
+
+/// Checks that every `_` in a number's digits is between two digits (or follows a base prefix).
+fn check_underscores(digits: &str, after_prefix: bool, span: Span) -> Result<(), LexError> {
+    let misplaced = digits.contains("__") || digits.ends_with('_') || (!after_prefix && digits.starts_with('_'));
+    if misplaced {
+        Err(LexError::MisplacedUnderscore(span))
+    } else {
+        Ok(())
+    }
+}
+
+
 #[cfg(test)]
 pub mod lexer_test {
-    use crate::token::TokenKind;
-    /// TODO: Need to properly check these...
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+    use num_traits::ToPrimitive;
+    use super::LexError;
+    use crate::token::{Position, Span, Token, TokenKind, Trivia, TriviaKind};
+
+    /// Lexes the whole file, including the trailing EOF token.
+    fn lex_all(file_name: &str) -> (Vec<Token>, Vec<LexError>) {
+        super::Lexer::new(file_name.to_string()).unwrap().lex_all()
+    }
+
+    fn span(start: (u32, u32, usize), end: (u32, u32, usize)) -> Span {
+        Span {
+            file_id: 0,
+            start: Position::new(start.0, start.1, start.2),
+            end: Position::new(end.0, end.1, end.2),
+        }
+    }
+
+    fn assert_token(token: &Token, kind: TokenKind, start: (u32, u32, usize), end: (u32, u32, usize)) {
+        assert_eq!(token.kind, kind);
+        assert_eq!(token.span, span(start, end));
+        assert!(!token.implicit);
+    }
+
+    fn assert_implicit_semicolon(token: &Token, start: (u32, u32, usize), end: (u32, u32, usize)) {
+        assert_eq!(token.kind, TokenKind::Semicolon);
+        assert_eq!(token.span, span(start, end));
+        assert!(token.implicit);
+    }
+
     #[test]
     pub fn given_valid_tokens_should_output_token_strings() {
-        let file_name = "testfiles/all_tokens.test".to_string();
-        let mut lexer = super::Lexer::new(file_name);
-        let mut curr_token = lexer.lex();
-        while curr_token != TokenKind::EOF {
-            print!("{}\n", curr_token.to_string() );
-            curr_token = lexer.lex();
-        }
+        let (tokens, errors) = lex_all("testfiles/all_tokens.test");
+        assert!(errors.is_empty());
+        assert_token(&tokens[0], TokenKind::PlusOp, (1, 1, 0), (1, 2, 1));
+        assert_token(&tokens[1], TokenKind::BitAndOp, (1, 6, 5), (1, 7, 6));
+        assert_token(&tokens[2], TokenKind::PlusEqOp, (1, 12, 11), (1, 14, 13));
+        assert_token(&tokens[8], TokenKind::RightParen, (1, 48, 47), (1, 49, 48));
+        assert_implicit_semicolon(&tokens[9], (1, 49, 48), (2, 1, 49));
+        assert_token(&tokens[10], TokenKind::MinusOp, (2, 1, 49), (2, 2, 50));
+
+        let first_keyword = tokens
+                .iter()
+                .position(|token| token.kind == TokenKind::BreakKeyword)
+                .unwrap();
+        let comments = &tokens[first_keyword].leading;
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].kind, TriviaKind::LineComment);
+        assert_eq!(comments[0].text, "// comment - should be ignore. ");
+        assert_eq!(comments[1].kind, TriviaKind::BlockComment);
+        assert_eq!(comments[1].span.start.line, 10);
+        assert_eq!(comments[1].span.end.line, 17);
+        assert_eq!(tokens[first_keyword].span.start.line, 19);
+        assert_eq!(tokens[first_keyword].span.start.column, 1);
+        assert_token(&tokens[first_keyword + 1], TokenKind::DefaultKeyword, (19, 14, 410), (19, 21, 417));
+
+        let eof = tokens.last().unwrap();
+        assert_eq!(eof.span.start, eof.span.end);
     }
 
     #[test]
     pub fn given_valid_strings_and_chars_should_output_correct_tokens() {
-        let file_name = "testfiles/strings_and_chars.test".to_string();
-        let mut lexer = super::Lexer::new(file_name);
-        let mut curr_token = lexer.lex();
-        while curr_token != TokenKind::EOF {
-            print!("{}\n", curr_token.to_string() );
-            curr_token = lexer.lex();
-        }
+        let (tokens, errors) = lex_all("testfiles/strings_and_chars.test");
+        assert!(errors.is_empty());
+        assert_eq!(tokens.len(), 5);
+        assert_token(
+            &tokens[0],
+            TokenKind::StringLiteral("\"asdf\\n\\t\\\\\\\"\\\'\\r\"".to_string(), b"asdf\n\t\\\"'\r".to_vec()),
+            (1, 1, 0),
+            (1, 19, 18),
+        );
+        assert_implicit_semicolon(&tokens[1], (1, 19, 18), (2, 1, 19));
+        assert_token(&tokens[2], TokenKind::CharLiteral("'\\n'".to_string(), '\n'), (2, 1, 19), (2, 5, 23));
+        assert_implicit_semicolon(&tokens[3], (2, 5, 23), (2, 5, 23));
+        assert_token(&tokens[4], TokenKind::EOF, (2, 5, 23), (2, 5, 23));
     }
 
     #[test]
-    #[should_panic]
-    pub fn given_invalid_strings_and_chars_should_panic() {
-        let file_name = "testfiles/illegal_strings_and_chars.test".to_string();
-        let mut lexer = super::Lexer::new(file_name);
-        let mut curr_token = lexer.lex();
-        while curr_token != TokenKind::EOF {
-            print!("{}\n", curr_token.to_string() );
-            curr_token = lexer.lex();
-        }
+    pub fn given_invalid_strings_and_chars_should_report_errors() {
+        let (tokens, errors) = lex_all("testfiles/illegal_strings_and_chars.test");
+        assert_eq!(errors, vec![
+            LexError::UnescapedCharacter('\n', span((1, 2, 1), (2, 1, 2))),
+            LexError::UnescapedCharacter('\n', span((2, 2, 3), (3, 1, 4))),
+            LexError::UnterminatedChar(span((3, 1, 4), (3, 7, 10))),
+        ]);
+        assert_token(&tokens[0], TokenKind::CharLiteral("'a'".to_string(), 'a'), (4, 1, 11), (4, 4, 14));
+        assert_implicit_semicolon(&tokens[1], (4, 4, 14), (5, 1, 15));
+        assert_eq!(tokens[2].kind, TokenKind::EOF);
+        assert_eq!(tokens[2].leading[0].text, "// \"\"");
+    }
+
+    #[test]
+    pub fn given_missing_file_should_fail_to_open() {
+        let error = super::Lexer::new("testfiles/missing.test".to_string()).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    pub fn given_invalid_tokens_should_report_errors_and_keep_lexing() {
+        let mut lexer = super::Lexer::new("testfiles/illegal_tokens.test".to_string()).unwrap();
+        assert_token(&lexer.lex().unwrap(), TokenKind::DivOp, (1, 1, 0), (1, 2, 1));
+        assert_eq!(lexer.lex(), Err(LexError::UnknownCharacter('@', span((2, 1, 2), (2, 2, 3)))));
+        assert_eq!(lexer.lex(), Err(LexError::UnknownCharacter('#', span((3, 1, 4), (3, 2, 5)))));
+        assert_eq!(lexer.lex(), Err(LexError::UnknownCharacter('$', span((4, 1, 6), (4, 2, 7)))));
+        assert_eq!(lexer.lex(), Err(LexError::UnterminatedChar(span((6, 1, 9), (6, 18, 26)))));
+        assert_eq!(lexer.lex().unwrap().kind, TokenKind::EOF);
     }
 
     #[test]
-    #[should_panic]
-    pub fn given_invalid_tokens_should_panic() {
-        let file_name = "testfiles/illegal_tokens.test".to_string();
-        let mut lexer = super::Lexer::new(file_name);
-        let mut curr_token = lexer.lex();
-        while curr_token != TokenKind::EOF {
-            print!("{}\n", curr_token.to_string() );
-            curr_token = lexer.lex();
+    pub fn given_bad_escapes_and_elipses_should_report_each_error() {
+        let (tokens, errors) = lex_all("testfiles/illegal_escapes_and_elipses.test");
+        assert_eq!(errors, vec![
+            LexError::InvalidEscapeSequence(Some('q'), span((1, 4, 3), (1, 6, 5))),
+            LexError::EmptyChar(span((2, 1, 7), (2, 3, 9))),
+            LexError::InvalidEscapeSequence(Some('z'), span((3, 2, 11), (3, 4, 13))),
+            LexError::IncompleteElipses(span((4, 1, 15), (4, 3, 17))),
+        ]);
+        assert_eq!(tokens.iter().map(|token| token.kind.clone()).collect::<Vec<_>>(), vec![
+            TokenKind::Identifier("a".to_string()),
+            TokenKind::Semicolon,
+            TokenKind::EOF,
+        ]);
+    }
+
+    #[test]
+    pub fn given_line_ending_tokens_should_insert_semicolons() {
+        let (tokens, errors) = lex_all("testfiles/semicolons.test");
+        assert!(errors.is_empty());
+        let ident = |name: &str| TokenKind::Identifier(name.to_string());
+        let expected = vec![
+            (TokenKind::PackageKeyword, false),
+            (ident("main"), false),
+            (TokenKind::Semicolon, true),
+            (ident("x"), false),
+            (TokenKind::IncrementOp, false),
+            (TokenKind::Semicolon, true),
+            (ident("y"), false),
+            (TokenKind::Semicolon, true),
+            (ident("z"), false),
+            (TokenKind::Semicolon, true),
+            (TokenKind::ReturnKeyword, false),
+            (TokenKind::Semicolon, true),
+            (ident("f"), false),
+            (TokenKind::LeftParen, false),
+            (ident("a"), false),
+            (TokenKind::Comma, false),
+            (ident("b"), false),
+            (TokenKind::RightParen, false),
+            (TokenKind::Semicolon, true),
+            (TokenKind::LeftBracket, false),
+            (TokenKind::RightBracket, false),
+            (ident("int"), false),
+            (TokenKind::LeftBrace, false),
+            (TokenKind::IntLiteral("1".to_string(), 1.into()), false),
+            (TokenKind::RightBrace, false),
+            (TokenKind::Semicolon, true),
+            (TokenKind::BreakKeyword, false),
+            (TokenKind::Semicolon, false),
+            (TokenKind::ContinueKeyword, false),
+            (TokenKind::Semicolon, true),
+            (TokenKind::EOF, false),
+        ];
+        let actual = tokens
+                .into_iter()
+                .map(|token| (token.kind, token.implicit))
+                .collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    pub fn given_comments_should_attach_them_to_neighbouring_tokens() {
+        let (tokens, errors) = super::Lexer::from_source("// doc\nx /* a */ + y // end\n\n/* b */ z /* c\n*/").lex_all();
+        assert!(errors.is_empty());
+        let comments = |trivia: &Vec<Trivia>| trivia.iter().map(|comment| comment.text.clone()).collect::<Vec<_>>();
+        let attached = tokens
+                .iter()
+                .map(|token| (token.kind.to_string(), comments(&token.leading), comments(&token.trailing)))
+                .collect::<Vec<_>>();
+        let owned = |texts: &[&str]| texts.iter().map(|text| text.to_string()).collect::<Vec<_>>();
+        assert_eq!(attached, vec![
+            ("Identifier x".to_string(), owned(&["// doc"]), owned(&["/* a */"])),
+            ("+".to_string(), owned(&[]), owned(&[])),
+            ("Identifier y".to_string(), owned(&[]), owned(&["// end"])),
+            (";".to_string(), owned(&[]), owned(&[])),
+            ("Identifier z".to_string(), owned(&["/* b */"]), owned(&["/* c\n*/"])),
+            (";".to_string(), owned(&[]), owned(&[])),
+            ("EOF".to_string(), owned(&[]), owned(&[])),
+        ]);
+        assert_eq!(tokens[0].leading[0].span, span((1, 1, 0), (1, 7, 6)));
+    }
+
+    #[test]
+    pub fn given_integer_literals_in_every_base_should_lex_their_values() {
+        let (tokens, errors) = lex_all("testfiles/numbers.test");
+        assert!(errors.is_empty());
+        let values = tokens
+                .iter()
+                .filter_map(|token| match &token.kind {
+                    TokenKind::IntLiteral(_, value) => value.to_u64(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+        assert_eq!(values, vec![42, 1_000_000, 0o755, 0o17, 7, 0xFF, 0xDEAD_BEEF, 0b1010, 1, 0, u64::MAX, 0o600]);
+        assert_token(&tokens[1], TokenKind::IntLiteral("1_000_000".to_string(), 1_000_000.into()), (1, 4, 3), (1, 13, 12));
+        assert_eq!(tokens[13].kind, TokenKind::FloatLiteral("3.25".to_string(), BigRational::new(13.into(), 4.into())));
+        assert_eq!(tokens[14].kind.to_string(), "Float 1_0.5");
+    }
+
+    #[test]
+    pub fn given_malformed_integer_literals_should_report_errors() {
+        let (tokens, errors) = lex_all("testfiles/illegal_numbers.test");
+        assert_eq!(errors, vec![
+            LexError::MissingDigits("hexadecimal", span((1, 1, 0), (1, 3, 2))),
+            LexError::InvalidDigit('2', "binary", span((1, 4, 3), (1, 9, 8))),
+            LexError::InvalidDigit('8', "octal", span((1, 10, 9), (1, 13, 12))),
+            LexError::MisplacedUnderscore(span((1, 14, 13), (1, 18, 17))),
+            LexError::MisplacedUnderscore(span((1, 19, 18), (1, 22, 21))),
+            LexError::MisplacedUnderscore(span((1, 44, 43), (1, 47, 46))),
+        ]);
+        // literals too big for any type are kept exactly, the type checker reports them when they're used.
+        let big = BigInt::from(u64::MAX) + 1;
+        assert_eq!(tokens[0].kind, TokenKind::IntLiteral("18446744073709551616".to_string(), big));
+        assert_eq!(tokens.len(), 3);
+    }
+
+    #[test]
+    pub fn given_float_literals_should_lex_their_exact_values() {
+        let (tokens, errors) = lex_all("testfiles/floats.test");
+        assert_eq!(errors, vec![LexError::InvalidExponent('p', span((2, 40, 95), (2, 43, 98)))]);
+        let ratio = |numerator: i64, denominator: i64| BigRational::new(numerator.into(), denominator.into());
+        let values = tokens
+                .iter()
+                .filter_map(|token| match &token.kind {
+                    TokenKind::FloatLiteral(_, value) => Some(value.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+        assert_eq!(values, vec![
+            ratio(602, 100) * BigRational::from_integer(BigInt::from(10).pow(23)),
+            ratio(1_000_000_000, 1),
+            ratio(1, 1000),
+            ratio(50, 1),
+            ratio(10, 1),
+            ratio(1, 10),
+            ratio(155, 10),
+            ratio(10_000_001, 10_000),
+            ratio(10_000_000_000, 1),
+            ratio(1, 4),
+            ratio(3, 1),
+            ratio(1, 1),
+            ratio(0x1FFF, 0x10000),
+        ]);
+        // 0x1e is an int, e is a hex digit.
+        assert_eq!(tokens[14].kind, TokenKind::IntLiteral("0x1e".to_string(), 30.into()));
+        assert_token(&tokens[3], TokenKind::FloatLiteral(".5e+2".to_string(), ratio(50, 1)), (1, 18, 17), (1, 23, 22));
+        // parsed exactly, so rounding to a float happens once.
+        assert_eq!(values[5].to_f64(), Some(0.1));
+        assert_eq!(values[0].to_f64(), Some(6.02e23));
+    }
+
+    #[test]
+    pub fn given_malformed_float_literals_should_report_errors() {
+        let (_, errors) = lex_all("testfiles/illegal_floats.test");
+        assert_eq!(errors, vec![
+            LexError::MissingExponentDigits(span((1, 1, 0), (1, 3, 2))),
+            LexError::MissingExponentDigits(span((1, 4, 3), (1, 7, 6))),
+            LexError::MissingExponent(span((1, 8, 7), (1, 13, 12))),
+            LexError::InvalidRadixPoint("binary", span((1, 14, 13), (1, 19, 18))),
+            LexError::InvalidExponent('e', span((1, 20, 19), (1, 25, 24))),
+            LexError::MissingDigits("hexadecimal", span((1, 26, 25), (1, 31, 30))),
+            LexError::MisplacedUnderscore(span((1, 32, 31), (1, 36, 35))),
+            LexError::MisplacedUnderscore(span((1, 37, 36), (1, 41, 40))),
+            LexError::ExponentTooLarge(span((1, 42, 41), (1, 49, 48))),
+        ]);
+    }
+
+    #[test]
+    pub fn given_raw_strings_should_keep_backslashes_and_span_lines() {
+        let (tokens, errors) = lex_all("testfiles/raw_strings.test");
+        assert_token(&tokens[2], TokenKind::RawStringLiteral("\\d+\\n".to_string()), (1, 6, 5), (1, 13, 12));
+        assert_token(&tokens[6], TokenKind::RawStringLiteral("SELECT *\n  FROM t".to_string()), (2, 10, 22), (3, 10, 42));
+        // the line after a multi-line raw string is numbered correctly.
+        assert_token(&tokens[8], TokenKind::Identifier("y".to_string()), (4, 1, 43), (4, 2, 44));
+        assert_eq!(errors, vec![LexError::UnterminatedRawString(span((5, 1, 50), (6, 1, 64)))]);
+    }
+
+    #[test]
+    pub fn given_numeric_escapes_should_decode_to_bytes_and_runes() {
+        let (tokens, errors) = lex_all("testfiles/escapes.test");
+        let expected = [&[0x41, 0x41][..], "é😀".as_bytes(), &[0xff]].concat();
+        assert_eq!(tokens[0].kind, TokenKind::StringLiteral("\"\\x41\\101\\u00e9\\U0001F600\\xff\"".to_string(), expected));
+        let runes = tokens
+                .iter()
+                .filter_map(|token| match token.kind {
+                    TokenKind::CharLiteral(_, value) => Some(value),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+        assert_eq!(runes, vec!['\x7f', '\u{ff}', '\u{12e4}', '\u{101234}']);
+        assert_eq!(errors, vec![
+            LexError::MalformedEscape('x', 2, span((3, 2, 68), (3, 5, 71))),
+            LexError::OctalEscapeTooLarge(256, span((3, 9, 75), (3, 13, 79))),
+            LexError::InvalidCodePoint(0xD800, span((3, 16, 82), (3, 22, 88))),
+            LexError::InvalidCodePoint(0x110000, span((3, 25, 91), (3, 35, 101))),
+        ]);
+    }
+
+    #[test]
+    pub fn given_utf8_source_should_decode_characters_and_report_invalid_bytes() {
+        let (tokens, errors) = lex_all("testfiles/unicode.test");
+        assert_eq!(tokens[0].leading[0].text, "// héllo wörld");
+        // columns count characters, offsets count bytes.
+        assert_token(&tokens[0], TokenKind::Identifier("π".to_string()), (2, 1, 17), (2, 2, 19));
+        assert_token(&tokens[2], TokenKind::StringLiteral("\"ÿ日本\"".to_string(), "ÿ日本".as_bytes().to_vec()), (2, 6, 23), (2, 11, 33));
+        // ÿ is U+00FF, the byte that used to mark the end of the file.
+        assert_token(&tokens[4], TokenKind::CharLiteral("'ÿ'".to_string(), 'ÿ'), (2, 14, 36), (2, 17, 40));
+        assert_eq!(tokens[6].kind, TokenKind::Identifier("naïve_x1".to_string()));
+        assert_eq!(errors, vec![LexError::InvalidUtf8(span((4, 1, 56), (4, 2, 57)))]);
+        assert_token(&tokens[10], TokenKind::Identifier("y".to_string()), (4, 3, 58), (4, 4, 59));
+    }
+
+    #[test]
+    pub fn given_unterminated_string_should_report_error_at_its_start() {
+        let (tokens, errors) = lex_all("testfiles/unterminated_string.test");
+        assert_eq!(errors, vec![LexError::UnterminatedString(span((2, 6, 7), (2, 12, 13)))]);
+        assert_eq!(errors[0].to_string(), "ERROR: unterminated string starting at line 2");
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::EOF);
+    }
+
+    #[test]
+    pub fn given_unterminated_block_comment_should_report_error_at_its_start() {
+        let (tokens, errors) = lex_all("testfiles/unterminated_comment.test");
+        assert_eq!(errors, vec![LexError::UnterminatedComment(span((2, 1, 2), (4, 1, 23)))]);
+        assert_eq!(errors[0].to_string(), "ERROR: unterminated comment starting at line 2");
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::EOF);
+    }
+
+    #[test]
+    pub fn given_line_comment_at_end_of_file_should_stop_at_eof() {
+        let (tokens, errors) = lex_all("testfiles/trailing_comment.test");
+        assert!(errors.is_empty());
+        assert_token(&tokens[1], TokenKind::IncrementOp, (1, 2, 1), (1, 4, 3));
+        assert_eq!(tokens[1].trailing[0].span, span((1, 4, 3), (1, 17, 16)));
+        assert_implicit_semicolon(&tokens[2], (1, 17, 16), (1, 17, 16));
+        assert_token(&tokens[3], TokenKind::EOF, (1, 17, 16), (1, 17, 16));
+    }
+
+    #[test]
+    pub fn given_source_in_memory_should_lex_it_like_a_file() {
+        let (tokens, errors) = super::Lexer::from_source("x := 'a'\n").lex_all();
+        assert!(errors.is_empty());
+        assert_token(&tokens[0], TokenKind::Identifier("x".to_string()), (1, 1, 0), (1, 2, 1));
+        assert_token(&tokens[1], TokenKind::AssignOp, (1, 3, 2), (1, 5, 4));
+        assert_token(&tokens[2], TokenKind::CharLiteral("'a'".to_string(), 'a'), (1, 6, 5), (1, 9, 8));
+        assert_implicit_semicolon(&tokens[3], (1, 9, 8), (2, 1, 9));
+        assert_token(&tokens[4], TokenKind::EOF, (2, 1, 9), (2, 1, 9));
+    }
+
+    #[test]
+    pub fn given_reader_should_tag_spans_with_its_file_id() {
+        let mut lexer = super::Lexer::from_reader(&b"/* x */ y"[..], 3);
+        let token = lexer.lex().unwrap();
+        assert_eq!(token.kind, TokenKind::Identifier("y".to_string()));
+        assert_eq!(token.span.file_id, 3);
+        assert_eq!(token.leading[0].span.file_id, 3);
+    }
+}
+
+#[cfg(test)]
+pub mod lexer_proptest {
+    use super::Lexer;
+    use crate::token::TokenKind;
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+    use num_traits::Pow;
+    use proptest::prelude::*;
+    use proptest::sample::select;
+
+    /// Every token whose spelling is fixed, i.e. keywords, operators and punctuation.
+    fn fixed_tokens() -> Vec<TokenKind> {
+        use TokenKind::*;
+        vec![
+            LeftParen, RightParen, LeftBrace, RightBrace, LeftBracket, RightBracket,
+            PlusOp, MinusOp, StarOp, DivOp, ModOp, IncrementOp, DecrementOp, EqOp, AssignOp,
+            BitAndOp, BitOrOp, BitXorOp, BitClearOp, BitNotOp, PlusEqOp, MinusEqOp, StarEqOp, DivEqOp, ModEqOp,
+            BitAndEqOp, BitOrEqOp, BitXorEqOp, BitClearEqOp, LogicAndOp, LogicOrOp, LogicNotOp, LogicEqOp,
+            LShiftOp, LShiftEqOp, RShiftOp, RShiftEqOp, LogicNotEqOp, LogicLessOp, LogicGreaterOp,
+            LogicLessEqOp, LogicGreaterEqOp, DirectionOp,
+            Colon, Semicolon, Dot, Comma, Elipses,
+            BreakKeyword, DefaultKeyword, FuncKeyword, InterfaceKeyword, SelectKeyword, CaseKeyword,
+            DeferKeyword, GoKeyword, MapKeyword, StructKeyword, ChanKeyword, ElseKeyword, GotoKeyword,
+            PackageKeyword, SwitchKeyword, ConstKeyword, FallThroughKeyword, IfKeyword, RangeKeyword,
+            TypeKeyword, ContinueKeyword, ForKeyword, ImportKeyword, ReturnKeyword, VarKeyword,
+        ]
+    }
+
+    fn identifier() -> impl Strategy<Value = (String, TokenKind)> {
+        let keywords = fixed_tokens()
+                .iter()
+                .map(|kind| kind.to_string())
+                .filter(|spelling| spelling.starts_with(char::is_alphabetic))
+                .collect::<Vec<_>>();
+        "[a-zA-Z_πé][a-zA-Z0-9_π]{0,6}"
+                .prop_filter("keywords aren't identifiers", move |name| !keywords.contains(name))
+                .prop_map(|name| (name.clone(), TokenKind::Identifier(name)))
+    }
+
+    fn int_literal() -> impl Strategy<Value = (String, TokenKind)> {
+        (any::<u64>(), 0..4).prop_map(|(value, base)| {
+            let text = match base {
+                0 => format!("{}", value),
+                1 => format!("0x{:X}", value),
+                2 => format!("0o{:o}", value),
+                _ => format!("0b{:b}", value),
+            };
+            (text.clone(), TokenKind::IntLiteral(text, value.into()))
+        })
+    }
+
+    fn float_literal() -> impl Strategy<Value = (String, TokenKind)> {
+        (any::<u32>(), "[0-9]{1,5}", -20i32..20).prop_map(|(whole, fraction, exponent)| {
+            let text = format!("{}.{}e{}", whole, fraction, exponent);
+            let digits = format!("{}{}", whole, fraction).parse::<BigInt>().unwrap();
+            let scale = exponent - fraction.len() as i32;
+            let ten = BigRational::from_integer(10.into());
+            let value = BigRational::from_integer(digits) * Pow::pow(ten, scale);
+            (text.clone(), TokenKind::FloatLiteral(text, value))
+        })
+    }
+
+    /// Escape sequences, along with the bytes they stand for.
+    fn escape() -> impl Strategy<Value = (&'static str, &'static [u8])> {
+        select(vec![
+            ("\\n", &b"\n"[..]),
+            ("\\t", b"\t"),
+            ("\\\\", b"\\"),
+            ("\\x41", b"A"),
+            ("\\377", b"\xff"),
+            ("\\u00e9", "é".as_bytes()),
+            ("\\U0001F600", "😀".as_bytes()),
+        ])
+    }
+
+    fn string_literal() -> impl Strategy<Value = (String, TokenKind)> {
+        let piece = prop_oneof![
+            "[a-zA-Z0-9 é日']".prop_map(|text| (text.clone(), text.into_bytes())),
+            escape().prop_map(|(text, value)| (text.to_string(), value.to_vec())),
+        ];
+        prop::collection::vec(piece, 0..8).prop_map(|pieces| {
+            let mut raw = String::from('"');
+            let mut value = vec![];
+            for (text, bytes) in pieces {
+                raw.push_str(&text);
+                value.extend(bytes);
+            }
+            raw.push('"');
+            (raw.clone(), TokenKind::StringLiteral(raw, value))
+        })
+    }
+
+    fn char_literal() -> impl Strategy<Value = (String, TokenKind)> {
+        prop_oneof![
+            "[a-zA-Z0-9 é日\"]".prop_map(|text| (text.clone(), text.chars().next().unwrap())),
+            select(vec![("\\n", '\n'), ("\\'", '\''), ("\\x41", 'A'), ("\\u00e9", 'é')])
+                    .prop_map(|(text, value)| (text.to_string(), value)),
+        ]
+        .prop_map(|(text, value)| {
+            let raw = format!("'{}'", text);
+            (raw.clone(), TokenKind::CharLiteral(raw, value))
+        })
+    }
+
+    fn raw_string_literal() -> impl Strategy<Value = (String, TokenKind)> {
+        "[a-z \\n\"\\\\]{0,8}".prop_map(|value| (format!("`{}`", value), TokenKind::RawStringLiteral(value)))
+    }
+
+    fn token() -> impl Strategy<Value = (String, TokenKind)> {
+        prop_oneof![
+            4 => select(fixed_tokens()).prop_map(|kind| (kind.to_string(), kind)),
+            2 => identifier(),
+            1 => int_literal(),
+            1 => float_literal(),
+            1 => string_literal(),
+            1 => char_literal(),
+            1 => raw_string_literal(),
+        ]
+    }
+
+    /// Input that's mostly bytes the lexer treats specially, so it reaches deep into its branches.
+    fn lexer_bytes() -> impl Strategy<Value = Vec<u8>> {
+        let byte = prop_oneof![
+            any::<u8>(),
+            select(b"\"'`/*\\\n\r\t .0123456789xXoObBeEpP_+-<>=&|^!:abcu".to_vec()),
+        ];
+        prop::collection::vec(byte, 0..64)
+    }
+
+    proptest! {
+        #[test]
+        fn given_rendered_tokens_should_lex_them_back(
+            tokens in prop::collection::vec((token(), select(vec![" ", "\t", "  ", "\n", " \r\n\t"])), 0..24),
+        ) {
+            let mut source = String::new();
+            let mut expected = vec![];
+            let mut last_kind = None;
+            for ((text, kind), separator) in &tokens {
+                expected.push((kind.clone(), false, Some(source.len()..source.len() + text.len())));
+                source.push_str(text);
+                source.push_str(separator);
+                if separator.contains('\n') && kind.triggers_semicolon() {
+                    expected.push((TokenKind::Semicolon, true, None));
+                }
+                last_kind = Some((kind, separator.contains('\n')));
+            }
+            if let Some((kind, false)) = last_kind {
+                if kind.triggers_semicolon() {
+                    expected.push((TokenKind::Semicolon, true, None));
+                }
+            }
+            expected.push((TokenKind::EOF, false, None));
+
+            let (lexed, errors) = Lexer::from_source(&source).lex_all();
+            prop_assert!(errors.is_empty(), "{:?} in {:?}", errors, source);
+            let actual = lexed
+                    .iter()
+                    .zip(&expected)
+                    .map(|(token, (_, _, range))| {
+                        let range = range.as_ref().map(|_| token.span.start.offset..token.span.end.offset);
+                        (token.kind.clone(), token.implicit, range)
+                    })
+                    .collect::<Vec<_>>();
+            prop_assert_eq!(lexed.len(), expected.len(), "in {:?}", source);
+            prop_assert_eq!(actual, expected, "in {:?}", source);
         }
     }
-}
\ No newline at end of file
+
+    proptest! {
+        #![proptest_config(ProptestConfig { timeout: 5000, ..ProptestConfig::default() })]
+
+        #[test]
+        fn given_arbitrary_bytes_should_reach_eof_without_panicking(bytes in lexer_bytes()) {
+            let limit = 2 * bytes.len() + 4;
+            let mut lexer = Lexer::from_reader(std::io::Cursor::new(bytes), 0);
+            // every call consumes input, apart from the implicit semicolon that can follow a token.
+            let calls = (1..=limit).find(|_| matches!(lexer.lex(), Ok(token) if token.kind == TokenKind::EOF));
+            prop_assert!(calls.is_some(), "no EOF after {} calls", limit);
+        }
+    }
+}
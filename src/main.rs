@@ -1,10 +1,106 @@
 pub mod argparse;
+pub mod ast;
+pub mod ast_printer;
+pub mod codegen;
+pub mod constant;
+pub mod diagnostics;
+pub mod labels;
 pub mod lexer;
 pub mod parser;
+pub mod sema;
 pub mod token;
+pub mod token_dump;
+pub mod token_stream;
+pub mod typechecker;
+
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::process;
+use argparse::ErrorFormat;
+use diagnostics::{SourceMap, ToDiagnostic};
+
+/// Reads the whole input file, or stdin if the path is `-`.
+fn read_input(input_file: &str) -> io::Result<Vec<u8>> {
+    if input_file == "-" {
+        let mut source = vec![];
+        io::stdin().lock().read_to_end(&mut source)?;
+        Ok(source)
+    } else {
+        fs::read(input_file)
+    }
+}
+
+/// Prints every error as a diagnostic, then exits.
+///
+/// Human-readable diagnostics quote the source, in color if stderr is a terminal; JSON ones are one per line.
+fn report<E: ToDiagnostic>(errors: &[E], sources: &SourceMap, format: ErrorFormat) -> ! {
+    if format == ErrorFormat::Json {
+        for error in errors {
+            eprintln!("{}", error.to_diagnostic().to_json(sources));
+        }
+        process::exit(1);
+    }
+    let color = io::stderr().is_terminal();
+    for error in errors {
+        eprintln!("{}", error.to_diagnostic().render(sources, color));
+    }
+    let plural = if errors.len() == 1 { "" } else { "s" };
+    eprintln!("error: aborting due to {} previous error{}", errors.len(), plural);
+    process::exit(1);
+}
 
 fn main() {
     let options = argparse::Arguments::new();
-    let lexer = lexer::Lexer::new(options.input_file);
+    let source = match read_input(&options.input_file) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("error: could not read {}: {}", options.input_file, error);
+            process::exit(1);
+        }
+    };
+
+    let mut sources = SourceMap::default();
+    let name = if options.input_file == "-" { "<stdin>" } else { &options.input_file };
+    let file_id = sources.add(name.to_string(), String::from_utf8_lossy(&source).into_owned());
+
+    if let Some(format) = options.token_format {
+        let mut lexer = lexer::Lexer::from_reader(io::Cursor::new(source.clone()), file_id);
+        match token_dump::dump_tokens(&mut lexer, format, &mut io::stdout().lock()) {
+            Ok(errors) if errors.is_empty() => process::exit(0),
+            Ok(errors) => report(&errors, &sources, options.error_format),
+            Err(_) => process::exit(1), // stdout was closed
+        }
+    }
+
+    let lexer = lexer::Lexer::from_reader(io::Cursor::new(source), file_id);
     let parser = parser::Parser::new(lexer);
+    let program = match parser.parse() {
+        Ok(program) => program,
+        Err(errors) => report(&errors, &sources, options.error_format),
+    };
+
+    if options.print_ast {
+        print!("{}", ast_printer::print_program(&program));
+    }
+
+    let symbols = match sema::analyze(&program) {
+        Ok(symbols) => symbols,
+        Err(errors) => report(&errors, &sources, options.error_format),
+    };
+    if let Err(errors) = labels::check(&program) {
+        report(&errors, &sources, options.error_format);
+    }
+    let info = match typechecker::check(&program, &symbols) {
+        Ok(info) => info,
+        Err(errors) => report(&errors, &sources, options.error_format),
+    };
+
+    let assembly = match codegen::generate(&program, &symbols, &info) {
+        Ok(assembly) => assembly,
+        Err(error) => report(&[error], &sources, options.error_format),
+    };
+    if let Err(error) = fs::write(&options.output_file, assembly) {
+        eprintln!("error: could not write {}: {}", options.output_file, error);
+        process::exit(1);
+    }
 }
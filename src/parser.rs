@@ -1,12 +1,1764 @@
-use crate::lexer;
+use std::fmt;
+use crate::ast::*;
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::lexer::{self, LexError};
+use crate::token::{Span, Token, TokenKind};
+use crate::token_stream::TokenStream;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    Lex(LexError),
+    UnexpectedToken {
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    Invalid(String, Span), // a construct that is well formed token-wise, but not allowed
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Lex(error) => error.span(),
+            Self::UnexpectedToken { span, .. } | Self::Invalid(_, span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Lex(error) => write!(f, "{}", error),
+            Self::UnexpectedToken { expected, found, span } => {
+                write!(f, "ERROR: expected {}, found {} on line {}", expected, found, span.start.line)
+            }
+            Self::Invalid(message, span) => write!(f, "ERROR: {} on line {}", message, span.start.line),
+        }
+    }
+}
+
+impl ToDiagnostic for ParseError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::Lex(error) => error.to_diagnostic(),
+            Self::UnexpectedToken { expected, found, span } => {
+                Diagnostic::error("E0201", format!("expected {}, found {}", expected, found), *span)
+                        .with_primary_label(format!("expected {}", expected))
+            }
+            Self::Invalid(message, span) => Diagnostic::error("E0202", message.clone(), *span),
+        }
+    }
+}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+/// The result of parsing a simple statement in a context where a range clause is allowed.
+enum SimpleStmt {
+    Stmt(Stmt),
+    Range(Option<Expr>, Option<Expr>, bool, Expr),
+}
+
 pub struct Parser {
-    lexer: lexer::Lexer
+    tokens: TokenStream,
+    no_composite_lit: bool,   // set in if/for/switch headers, where `T {` starts the body
+    errors: Vec<ParseError>,  // syntax errors recovered from so far
 }
 
 impl Parser {
-    pub fn new(input_lexer: lexer::Lexer) -> Parser {
+    pub fn new(lexer: lexer::Lexer) -> Parser {
         Parser {
-            lexer: input_lexer,
+            tokens: TokenStream::new(lexer),
+            no_composite_lit: false,
+            errors: vec![],
+        }
+    }
+
+    /// Parses the whole file, failing with every lexical and syntax error in it.
+    pub fn parse(self) -> Result<Program, Vec<ParseError>> {
+        match self.parse_recovering() {
+            (program, errors) if errors.is_empty() => Ok(program),
+            (_, errors) => Err(errors),
+        }
+    }
+
+    /// Parses the whole file, carrying on after syntax errors.
+    ///
+    /// Declarations and statements with syntax errors are kept in the AST as `Bad` nodes. The errors, lexical and
+    /// syntax ones alike, are returned sorted by position.
+    pub fn parse_recovering(mut self) -> (Program, Vec<ParseError>) {
+        let program = self.parse_program();
+        let mut errors: Vec<ParseError> = self.tokens.take_errors().into_iter().map(ParseError::Lex).collect();
+        errors.append(&mut self.errors);
+        errors.sort_by_key(|error| error.span().start.offset);
+        (program, errors)
+    }
+
+    fn current(&self) -> &Token {
+        self.tokens.current()
+    }
+
+    /// Consumes the current token and returns it.
+    fn advance(&mut self) -> Token {
+        self.tokens.next()
+    }
+
+    /// Returns the kind of the token `n` tokens after the current one.
+    fn peek(&mut self, n: usize) -> &TokenKind {
+        &self.tokens.peek(n).kind
+    }
+
+    /// The text and value of the current token if it's a string literal, raw or not.
+    fn string_literal(&self) -> Option<(String, Vec<u8>)> {
+        match &self.current().kind {
+            TokenKind::StringLiteral(raw, value) => Some((raw.clone(), value.clone())),
+            TokenKind::RawStringLiteral(value) => Some((format!("`{}`", value), value.clone().into_bytes())),
+            _ => None,
+        }
+    }
+
+    fn at(&self, kind: &TokenKind) -> bool {
+        self.current().kind == *kind
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.at(kind) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: &TokenKind) -> ParseResult<Token> {
+        self.tokens.expect(kind)
+    }
+
+    fn expect_ident(&mut self) -> ParseResult<Ident> {
+        match &self.current().kind {
+            TokenKind::Identifier(name) => {
+                let ident = Ident {
+                    name: name.clone(),
+                    span: self.current().span,
+                };
+                self.advance();
+                Ok(ident)
+            }
+            _ => Err(self.unexpected("identifier")),
+        }
+    }
+
+    /// Statements and declarations end in a semicolon, which may be left out before a closing ) or }.
+    fn expect_semicolon(&mut self) -> ParseResult<()> {
+        match self.current().kind {
+            TokenKind::Semicolon => {
+                self.advance();
+                Ok(())
+            }
+            TokenKind::RightParen | TokenKind::RightBrace | TokenKind::EOF => Ok(()),
+            _ => Err(self.unexpected(";")),
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        self.tokens.unexpected(expected)
+    }
+
+    /// The span from `start` to the end of the last consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.tokens.prev_span())
+    }
+
+    /// Runs `parse`, and if it fails, records the error and skips ahead to where parsing can resume: just past a `;`,
+    /// at a keyword starting a declaration, or at the `}` closing the enclosing block (or the next clause of the
+    /// enclosing switch or select) when `in_block` is set.
+    ///
+    /// Braces opened while skipping are skipped along with everything up to their closing `}`.
+    fn recovering<T>(&mut self, in_block: bool, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> Option<T> {
+        let error = match parse(self) {
+            Ok(result) => return Some(result),
+            Err(error) => error,
+        };
+        self.errors.push(error);
+        let mut depth = 0;
+        loop {
+            match self.current().kind {
+                TokenKind::EOF => break,
+                TokenKind::Semicolon if depth == 0 => {
+                    self.advance();
+                    break;
+                }
+                TokenKind::FuncKeyword | TokenKind::VarKeyword | TokenKind::ConstKeyword | TokenKind::TypeKeyword
+                    if depth == 0 => break,
+                TokenKind::RightBrace | TokenKind::CaseKeyword | TokenKind::DefaultKeyword
+                    if depth == 0 && in_block => break,
+                TokenKind::RightBrace if depth > 0 => depth -= 1,
+                TokenKind::LeftBrace => depth += 1,
+                _ => {}
+            }
+            self.advance();
+        }
+        None
+    }
+
+    /// Runs `parse` on the header of a case or default clause, and if it fails, records the error and skips to the
+    /// next clause or to the `}` closing the switch or select, so one bad clause doesn't take the whole statement
+    /// with it.
+    fn recovering_clause<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> Option<T> {
+        let error = match parse(self) {
+            Ok(result) => return Some(result),
+            Err(error) => error,
+        };
+        self.errors.push(error);
+        let mut depth = 0;
+        loop {
+            match self.current().kind {
+                TokenKind::EOF => break,
+                TokenKind::CaseKeyword | TokenKind::DefaultKeyword | TokenKind::RightBrace if depth == 0 => break,
+                TokenKind::RightBrace => depth -= 1,
+                TokenKind::LeftBrace => depth += 1,
+                _ => {}
+            }
+            self.advance();
+        }
+        None
+    }
+
+    /// Runs `parse` with composite literals on or off, restoring the old setting afterwards.
+    fn with_composite_lit<T>(&mut self, allowed: bool, parse: impl FnOnce(&mut Self) -> T) -> T {
+        let saved = self.no_composite_lit;
+        self.no_composite_lit = !allowed;
+        let result = parse(self);
+        self.no_composite_lit = saved;
+        result
+    }
+
+    fn parse_program(&mut self) -> Program {
+        let start = self.current().span;
+        let package = self.recovering(false, |parser| {
+            parser.expect(&TokenKind::PackageKeyword)?;
+            let package = parser.expect_ident()?;
+            parser.expect_semicolon()?;
+            Ok(package)
+        });
+        // without a package clause, the package is left unnamed.
+        let package = package.unwrap_or(Ident {
+            name: String::new(),
+            span: start,
+        });
+
+        let mut imports = vec![];
+        while self.at(&TokenKind::ImportKeyword) {
+            self.advance();
+            let specs = self.recovering(false, |parser| {
+                let specs = parser.parse_group(Parser::parse_import_spec)?;
+                parser.expect_semicolon()?;
+                Ok(specs)
+            });
+            imports.extend(specs.unwrap_or_default());
+        }
+
+        let mut decls = vec![];
+        while !self.at(&TokenKind::EOF) {
+            let decl_start = self.current().span;
+            let decl = self.recovering(false, |parser| {
+                let decl = parser.parse_top_level_decl()?;
+                if !parser.at(&TokenKind::EOF) {
+                    parser.expect(&TokenKind::Semicolon)?;
+                }
+                Ok(decl)
+            });
+            decls.push(decl.unwrap_or_else(|| TopLevelDecl::Bad(self.span_from(decl_start))));
+        }
+        // the span ends at the last declaration rather than at its (possibly implicit) semicolon.
+        let end = decls.last().map(TopLevelDecl::span).unwrap_or(package.span);
+        Program {
+            package,
+            imports,
+            decls,
+            span: start.to(end),
+        }
+    }
+
+    fn parse_import_spec(&mut self) -> ParseResult<ImportSpec> {
+        let start = self.current().span;
+        let alias = match self.current().kind {
+            TokenKind::Identifier(_) => Some(self.expect_ident()?),
+            TokenKind::Dot => {
+                self.advance();
+                Some(Ident {
+                    name: ".".to_string(),
+                    span: start,
+                })
+            }
+            _ => None,
+        };
+        match self.string_literal() {
+            Some((_, path)) => {
+                let path = String::from_utf8_lossy(&path).into_owned();
+                self.advance();
+                Ok(ImportSpec {
+                    alias,
+                    path,
+                    span: self.span_from(start),
+                })
+            }
+            None => Err(self.unexpected("import path")),
+        }
+    }
+
+    /// Parses either a single spec or a parenthesized group of specs.
+    fn parse_group<T>(&mut self, parse_spec: fn(&mut Self) -> ParseResult<T>) -> ParseResult<Vec<T>> {
+        if !self.eat(&TokenKind::LeftParen) {
+            return Ok(vec![parse_spec(self)?]);
+        }
+        let mut specs = vec![];
+        while !self.at(&TokenKind::RightParen) {
+            specs.push(parse_spec(self)?);
+            self.expect_semicolon()?;
+        }
+        self.expect(&TokenKind::RightParen)?;
+        Ok(specs)
+    }
+
+    fn parse_top_level_decl(&mut self) -> ParseResult<TopLevelDecl> {
+        match self.current().kind {
+            TokenKind::FuncKeyword => Ok(TopLevelDecl::Func(self.parse_func_decl()?)),
+            TokenKind::ConstKeyword | TokenKind::TypeKeyword | TokenKind::VarKeyword => {
+                Ok(TopLevelDecl::Decl(self.parse_decl()?))
+            }
+            _ => Err(self.unexpected("declaration")),
         }
     }
-}
\ No newline at end of file
+
+    fn parse_decl(&mut self) -> ParseResult<Decl> {
+        let start = self.current().span;
+        match self.advance().kind {
+            TokenKind::ConstKeyword => {
+                let specs = self.parse_group(Parser::parse_const_spec)?;
+                Ok(Decl::Const(specs, self.span_from(start)))
+            }
+            TokenKind::TypeKeyword => {
+                let specs = self.parse_group(Parser::parse_type_spec)?;
+                Ok(Decl::Type(specs, self.span_from(start)))
+            }
+            TokenKind::VarKeyword => {
+                let specs = self.parse_group(Parser::parse_var_spec)?;
+                Ok(Decl::Var(specs, self.span_from(start)))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn parse_const_spec(&mut self) -> ParseResult<ConstSpec> {
+        let start = self.current().span;
+        let names = self.parse_ident_list()?;
+        let mut ty = None;
+        let mut values = vec![];
+        if !matches!(self.current().kind, TokenKind::EqOp | TokenKind::Semicolon | TokenKind::RightParen) {
+            ty = Some(self.parse_type()?);
+        }
+        if ty.is_some() || self.at(&TokenKind::EqOp) {
+            self.expect(&TokenKind::EqOp)?;
+            values = self.parse_expr_list()?;
+        }
+        Ok(ConstSpec {
+            names,
+            ty,
+            values,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_var_spec(&mut self) -> ParseResult<VarSpec> {
+        let start = self.current().span;
+        let names = self.parse_ident_list()?;
+        let mut ty = None;
+        let mut values = vec![];
+        if !self.at(&TokenKind::EqOp) {
+            ty = Some(self.parse_type()?);
+        }
+        if self.eat(&TokenKind::EqOp) {
+            values = self.parse_expr_list()?;
+        }
+        Ok(VarSpec {
+            names,
+            ty,
+            values,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_type_spec(&mut self) -> ParseResult<TypeSpec> {
+        let start = self.current().span;
+        let name = self.expect_ident()?;
+        let mut type_params = vec![];
+        if self.at(&TokenKind::LeftBracket) {
+            type_params = self.parse_optional_type_params();
+        }
+        let alias = self.eat(&TokenKind::EqOp);
+        let ty = self.parse_type()?;
+        Ok(TypeSpec {
+            name,
+            type_params,
+            alias,
+            ty,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Distinguishes `type T[P any] ...` from the array type in `type T [N]int` by trying to read type parameters,
+    /// and going back to the `[` if that fails. Like Go, `[P *C]` is read as an array length, not a constraint.
+    fn parse_optional_type_params(&mut self) -> Vec<TypeParam> {
+        let checkpoint = self.tokens.checkpoint();
+        let errors = self.errors.len();
+        match self.parse_type_params() {
+            Ok(params) if !is_array_length(&params) => {
+                self.tokens.release(checkpoint);
+                params
+            }
+            _ => {
+                // errors recovered from in a function literal in the "array length" will be found again.
+                self.tokens.rewind(checkpoint);
+                self.errors.truncate(errors);
+                vec![]
+            }
+        }
+    }
+
+    fn parse_type_params(&mut self) -> ParseResult<Vec<TypeParam>> {
+        self.expect(&TokenKind::LeftBracket)?;
+        let mut params = vec![];
+        while !self.at(&TokenKind::RightBracket) {
+            let start = self.current().span;
+            let names = self.parse_ident_list()?;
+            let constraint = self.parse_type_elem()?;
+            params.push(TypeParam {
+                names,
+                constraint,
+                span: self.span_from(start),
+            });
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+        }
+        self.expect(&TokenKind::RightBracket)?;
+        Ok(params)
+    }
+
+    /// TypeElem = TypeTerm { "|" TypeTerm } .
+    fn parse_type_elem(&mut self) -> ParseResult<Vec<TypeTerm>> {
+        let mut terms = vec![];
+        loop {
+            let underlying = self.eat(&TokenKind::BitNotOp);
+            let ty = self.parse_type()?;
+            terms.push(TypeTerm { underlying, ty });
+            if !self.eat(&TokenKind::BitOrOp) {
+                return Ok(terms);
+            }
+        }
+    }
+
+    fn parse_func_decl(&mut self) -> ParseResult<FuncDecl> {
+        let start = self.current().span;
+        self.expect(&TokenKind::FuncKeyword)?;
+        let mut receiver = None;
+        if self.at(&TokenKind::LeftParen) {
+            let receiver_start = self.current().span;
+            let mut params = self.parse_parameters()?;
+            if params.len() != 1 {
+                return Err(ParseError::Invalid(
+                    "method must have exactly one receiver".to_string(),
+                    self.span_from(receiver_start),
+                ));
+            }
+            receiver = params.pop();
+        }
+        let name = self.expect_ident()?;
+        let mut type_params = vec![];
+        if self.at(&TokenKind::LeftBracket) {
+            type_params = self.parse_type_params()?;
+        }
+        let signature = self.parse_signature()?;
+        let mut body = None;
+        if self.at(&TokenKind::LeftBrace) {
+            body = Some(self.with_composite_lit(true, Parser::parse_block)?);
+        }
+        Ok(FuncDecl {
+            receiver,
+            name,
+            type_params,
+            signature,
+            body,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Signature = Parameters [ Result ] .
+    fn parse_signature(&mut self) -> ParseResult<Signature> {
+        let start = self.current().span;
+        let params = self.parse_parameters()?;
+        let results = if self.at(&TokenKind::LeftParen) {
+            self.parse_parameters()?
+        } else if self.starts_type() {
+            let ty = self.parse_type()?;
+            vec![Param {
+                name: None,
+                span: ty.span,
+                ty,
+                variadic: false,
+            }]
+        } else {
+            vec![]
+        };
+        Ok(Signature {
+            params,
+            results,
+            span: self.span_from(start),
+        })
+    }
+
+    fn starts_type(&self) -> bool {
+        matches!(
+            self.current().kind,
+            TokenKind::Identifier(_)
+                | TokenKind::LeftBracket
+                | TokenKind::StructKeyword
+                | TokenKind::StarOp
+                | TokenKind::FuncKeyword
+                | TokenKind::InterfaceKeyword
+                | TokenKind::MapKeyword
+                | TokenKind::ChanKeyword
+                | TokenKind::DirectionOp
+                | TokenKind::LeftParen
+        )
+    }
+
+    /// Parameters = "(" [ ParameterList [ "," ] ] ")" .
+    ///
+    /// Either every parameter is named (`a, b int, c string`) or none are (`int, string`),
+    /// which can only be told apart once the whole list has been read.
+    fn parse_parameters(&mut self) -> ParseResult<Vec<Param>> {
+        self.expect(&TokenKind::LeftParen)?;
+        // (name, type, variadic, span). A lone identifier could be a name or a type, so it has neither yet.
+        let mut entries: Vec<(Option<Ident>, Option<Type>, bool, Span)> = vec![];
+        while !self.at(&TokenKind::RightParen) {
+            let start = self.current().span;
+            let is_ident = matches!(self.current().kind, TokenKind::Identifier(_));
+            if is_ident && matches!(self.peek(1), TokenKind::Comma | TokenKind::RightParen) {
+                let ident = self.expect_ident()?;
+                entries.push((Some(ident), None, false, start));
+            } else if is_ident {
+                let name = self.expect_ident()?;
+                let variadic = self.eat(&TokenKind::Elipses);
+                let ty = self.parse_type()?;
+                entries.push((Some(name), Some(ty), variadic, self.span_from(start)));
+            } else {
+                let variadic = self.eat(&TokenKind::Elipses);
+                let ty = self.parse_type()?;
+                entries.push((None, Some(ty), variadic, self.span_from(start)));
+            }
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+        }
+        self.expect(&TokenKind::RightParen)?;
+
+        let named = entries.iter().any(|(name, ty, _, _)| name.is_some() && ty.is_some());
+        if !named {
+            return Ok(entries
+                    .into_iter()
+                    .map(|(name, ty, variadic, span)| Param {
+                        name: None,
+                        ty: ty.unwrap_or_else(|| {
+                            let ident = name.unwrap();
+                            Type {
+                                span: ident.span,
+                                kind: TypeKind::Name(ident, vec![]),
+                            }
+                        }),
+                        variadic,
+                        span,
+                    })
+                    .collect());
+        }
+
+        // `a, b int` gives both a and b the type int.
+        let mut params = vec![];
+        let mut untyped: Vec<(Ident, Span)> = vec![];
+        for (name, ty, variadic, span) in entries {
+            match (name, ty) {
+                (Some(name), None) => untyped.push((name, span)),
+                (Some(name), Some(ty)) => {
+                    for (untyped_name, untyped_span) in untyped.drain(..) {
+                        params.push(Param {
+                            name: Some(untyped_name),
+                            ty: ty.clone(),
+                            variadic: false,
+                            span: untyped_span,
+                        });
+                    }
+                    params.push(Param {
+                        name: Some(name),
+                        ty,
+                        variadic,
+                        span,
+                    });
+                }
+                (None, _) => {
+                    return Err(ParseError::Invalid("mixed named and unnamed parameters".to_string(), span));
+                }
+            }
+        }
+        if let Some((_, span)) = untyped.first() {
+            return Err(ParseError::Invalid("mixed named and unnamed parameters".to_string(), *span));
+        }
+        Ok(params)
+    }
+
+    fn parse_ident_list(&mut self) -> ParseResult<Vec<Ident>> {
+        let mut idents = vec![self.expect_ident()?];
+        while self.eat(&TokenKind::Comma) {
+            idents.push(self.expect_ident()?);
+        }
+        Ok(idents)
+    }
+
+    fn parse_expr_list(&mut self) -> ParseResult<Vec<Expr>> {
+        let mut exprs = vec![self.parse_expr()?];
+        while self.eat(&TokenKind::Comma) {
+            exprs.push(self.parse_expr()?);
+        }
+        Ok(exprs)
+    }
+
+    fn parse_type(&mut self) -> ParseResult<Type> {
+        let start = self.current().span;
+        let kind = match self.current().kind {
+            TokenKind::Identifier(_) => {
+                let name = self.expect_ident()?;
+                let mut args = vec![];
+                if self.eat(&TokenKind::LeftBracket) {
+                    while !self.at(&TokenKind::RightBracket) {
+                        args.push(self.parse_type()?);
+                        if !self.eat(&TokenKind::Comma) {
+                            break;
+                        }
+                    }
+                    self.expect(&TokenKind::RightBracket)?;
+                }
+                TypeKind::Name(name, args)
+            }
+            TokenKind::LeftParen => {
+                self.advance();
+                let ty = self.parse_type()?;
+                self.expect(&TokenKind::RightParen)?;
+                return Ok(Type {
+                    kind: ty.kind,
+                    span: self.span_from(start),
+                });
+            }
+            TokenKind::LeftBracket => {
+                self.advance();
+                if self.eat(&TokenKind::RightBracket) {
+                    TypeKind::Slice(Box::new(self.parse_type()?))
+                } else if self.eat(&TokenKind::Elipses) {
+                    self.expect(&TokenKind::RightBracket)?;
+                    TypeKind::Array(None, Box::new(self.parse_type()?))
+                } else {
+                    let len = self.with_composite_lit(true, Parser::parse_expr)?;
+                    self.expect(&TokenKind::RightBracket)?;
+                    TypeKind::Array(Some(Box::new(len)), Box::new(self.parse_type()?))
+                }
+            }
+            TokenKind::StructKeyword => self.parse_struct_type()?,
+            TokenKind::StarOp => {
+                self.advance();
+                TypeKind::Pointer(Box::new(self.parse_type()?))
+            }
+            TokenKind::FuncKeyword => {
+                self.advance();
+                TypeKind::Function(self.parse_signature()?)
+            }
+            TokenKind::InterfaceKeyword => self.parse_interface_type()?,
+            TokenKind::MapKeyword => {
+                self.advance();
+                self.expect(&TokenKind::LeftBracket)?;
+                let key = self.parse_type()?;
+                self.expect(&TokenKind::RightBracket)?;
+                TypeKind::Map(Box::new(key), Box::new(self.parse_type()?))
+            }
+            TokenKind::ChanKeyword => {
+                self.advance();
+                let dir = if self.eat(&TokenKind::DirectionOp) {
+                    ChanDir::Send
+                } else {
+                    ChanDir::Both
+                };
+                TypeKind::Channel(dir, Box::new(self.parse_type()?))
+            }
+            TokenKind::DirectionOp => {
+                self.advance();
+                self.expect(&TokenKind::ChanKeyword)?;
+                TypeKind::Channel(ChanDir::Recv, Box::new(self.parse_type()?))
+            }
+            _ => return Err(self.unexpected("type")),
+        };
+        Ok(Type {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_struct_type(&mut self) -> ParseResult<TypeKind> {
+        self.expect(&TokenKind::StructKeyword)?;
+        self.expect(&TokenKind::LeftBrace)?;
+        let mut fields = vec![];
+        while !self.at(&TokenKind::RightBrace) {
+            let start = self.current().span;
+            let is_ident = matches!(self.current().kind, TokenKind::Identifier(_));
+            let embedded = self.at(&TokenKind::StarOp)
+                || (is_ident
+                    && matches!(
+                        self.peek(1),
+                        TokenKind::Semicolon
+                            | TokenKind::RightBrace
+                            | TokenKind::StringLiteral(..)
+                            | TokenKind::RawStringLiteral(_)
+                    ));
+            let (names, ty) = if embedded {
+                (vec![], self.parse_type()?)
+            } else {
+                let names = self.parse_ident_list()?;
+                (names, self.parse_type()?)
+            };
+            let tag = self.string_literal().map(|(_, tag)| String::from_utf8_lossy(&tag).into_owned());
+            if tag.is_some() {
+                self.advance();
+            }
+            fields.push(FieldDecl {
+                names,
+                ty,
+                embedded,
+                tag,
+                span: self.span_from(start),
+            });
+            self.expect_semicolon()?;
+        }
+        self.expect(&TokenKind::RightBrace)?;
+        Ok(TypeKind::Struct(fields))
+    }
+
+    fn parse_interface_type(&mut self) -> ParseResult<TypeKind> {
+        self.expect(&TokenKind::InterfaceKeyword)?;
+        self.expect(&TokenKind::LeftBrace)?;
+        let mut elems = vec![];
+        while !self.at(&TokenKind::RightBrace) {
+            let is_ident = matches!(self.current().kind, TokenKind::Identifier(_));
+            if is_ident && *self.peek(1) == TokenKind::LeftParen {
+                let name = self.expect_ident()?;
+                elems.push(InterfaceElem::Method(name, self.parse_signature()?));
+            } else {
+                elems.push(InterfaceElem::Union(self.parse_type_elem()?));
+            }
+            self.expect_semicolon()?;
+        }
+        self.expect(&TokenKind::RightBrace)?;
+        Ok(TypeKind::Interface(elems))
+    }
+
+    fn parse_block(&mut self) -> ParseResult<Block> {
+        let start = self.current().span;
+        self.expect(&TokenKind::LeftBrace)?;
+        let stmts = self.parse_stmt_list();
+        self.expect(&TokenKind::RightBrace)?;
+        Ok(Block {
+            stmts,
+            span: self.span_from(start),
+        })
+    }
+
+    /// StatementList = { Statement ";" } .
+    ///
+    /// A statement with a syntax error becomes a `Bad` statement, and parsing resumes after it.
+    fn parse_stmt_list(&mut self) -> Vec<Stmt> {
+        let mut stmts = vec![];
+        while !matches!(
+            self.current().kind,
+            TokenKind::RightBrace | TokenKind::CaseKeyword | TokenKind::DefaultKeyword | TokenKind::EOF
+        ) {
+            if self.eat(&TokenKind::Semicolon) {
+                continue;
+            }
+            let start = self.current().span;
+            let stmt = self.recovering(true, |parser| {
+                let stmt = parser.parse_stmt()?;
+                if !matches!(parser.current().kind, TokenKind::CaseKeyword | TokenKind::DefaultKeyword) {
+                    parser.expect_semicolon()?;
+                }
+                Ok(stmt)
+            });
+            stmts.push(stmt.unwrap_or_else(|| Stmt {
+                kind: StmtKind::Bad,
+                span: self.span_from(start),
+            }));
+        }
+        stmts
+    }
+
+    fn parse_stmt(&mut self) -> ParseResult<Stmt> {
+        let start = self.current().span;
+        let is_label = matches!(self.current().kind, TokenKind::Identifier(_)) && *self.peek(1) == TokenKind::Colon;
+        let kind = match self.current().kind {
+            TokenKind::ConstKeyword | TokenKind::TypeKeyword | TokenKind::VarKeyword => {
+                StmtKind::Decl(self.parse_decl()?)
+            }
+            TokenKind::Identifier(_) if is_label => {
+                let label = self.expect_ident()?;
+                self.advance();
+                let stmt = if self.at(&TokenKind::RightBrace) {
+                    Stmt {
+                        kind: StmtKind::Empty,
+                        span: self.current().span,
+                    }
+                } else {
+                    self.parse_stmt()?
+                };
+                StmtKind::Labeled(label, Box::new(stmt))
+            }
+            TokenKind::GoKeyword => {
+                self.advance();
+                StmtKind::Go(self.parse_expr()?)
+            }
+            TokenKind::DeferKeyword => {
+                self.advance();
+                StmtKind::Defer(self.parse_expr()?)
+            }
+            TokenKind::ReturnKeyword => {
+                self.advance();
+                if matches!(self.current().kind, TokenKind::Semicolon | TokenKind::RightBrace) {
+                    StmtKind::Return(vec![])
+                } else {
+                    StmtKind::Return(self.parse_expr_list()?)
+                }
+            }
+            TokenKind::BreakKeyword => {
+                self.advance();
+                StmtKind::Break(self.parse_optional_label()?)
+            }
+            TokenKind::ContinueKeyword => {
+                self.advance();
+                StmtKind::Continue(self.parse_optional_label()?)
+            }
+            TokenKind::GotoKeyword => {
+                self.advance();
+                StmtKind::Goto(self.expect_ident()?)
+            }
+            TokenKind::FallThroughKeyword => {
+                self.advance();
+                StmtKind::Fallthrough
+            }
+            TokenKind::LeftBrace => StmtKind::Block(self.parse_block()?),
+            TokenKind::IfKeyword => return self.parse_if_stmt(),
+            TokenKind::SwitchKeyword => self.parse_switch_stmt()?,
+            TokenKind::SelectKeyword => self.parse_select_stmt()?,
+            TokenKind::ForKeyword => self.parse_for_stmt()?,
+            _ => return self.parse_simple_stmt(),
+        };
+        Ok(Stmt {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_optional_label(&mut self) -> ParseResult<Option<Ident>> {
+        match self.current().kind {
+            TokenKind::Identifier(_) => Ok(Some(self.expect_ident()?)),
+            _ => Ok(None),
+        }
+    }
+
+    fn parse_simple_stmt(&mut self) -> ParseResult<Stmt> {
+        match self.parse_simple_or_range_stmt(false)? {
+            SimpleStmt::Stmt(stmt) => Ok(stmt),
+            SimpleStmt::Range(..) => unreachable!(),
+        }
+    }
+
+    /// SimpleStmt = EmptyStmt | Expression | SendStmt | IncDecStmt | Assignment | ShortVarDecl .
+    ///
+    /// When `range_ok` is set, this also accepts the RangeClause of a for statement.
+    fn parse_simple_or_range_stmt(&mut self, range_ok: bool) -> ParseResult<SimpleStmt> {
+        let start = self.current().span;
+        if range_ok && self.eat(&TokenKind::RangeKeyword) {
+            let expr = self.parse_expr()?;
+            return Ok(SimpleStmt::Range(None, None, false, expr));
+        }
+
+        let mut lhs = self.parse_expr_list()?;
+        let kind = match self.current().kind {
+            TokenKind::AssignOp | TokenKind::EqOp => {
+                let define = self.advance().kind == TokenKind::AssignOp;
+                if range_ok && self.eat(&TokenKind::RangeKeyword) {
+                    if lhs.len() > 2 {
+                        return Err(ParseError::Invalid(
+                            "range clause permits at most two iteration variables".to_string(),
+                            self.span_from(start),
+                        ));
+                    }
+                    let expr = self.parse_expr()?;
+                    let value = if lhs.len() == 2 { lhs.pop() } else { None };
+                    let key = lhs.pop();
+                    return Ok(SimpleStmt::Range(key, value, define, expr));
+                }
+                let rhs = self.parse_expr_list()?;
+                if define {
+                    let mut names = vec![];
+                    for expr in lhs {
+                        match expr.kind {
+                            ExprKind::Ident(name) => names.push(Ident {
+                                name,
+                                span: expr.span,
+                            }),
+                            _ => {
+                                return Err(ParseError::Invalid(
+                                    "non-name on left side of :=".to_string(),
+                                    expr.span,
+                                ))
+                            }
+                        }
+                    }
+                    StmtKind::ShortVarDecl(names, rhs)
+                } else {
+                    StmtKind::Assign(lhs, None, rhs)
+                }
+            }
+            _ => {
+                if let Some(op) = compound_assign_op(&self.current().kind) {
+                    self.advance();
+                    let rhs = self.parse_expr_list()?;
+                    StmtKind::Assign(lhs, Some(op), rhs)
+                } else {
+                    if lhs.len() > 1 {
+                        return Err(self.unexpected(":= or = or comma"));
+                    }
+                    let expr = lhs.pop().unwrap();
+                    match self.current().kind {
+                        TokenKind::IncrementOp | TokenKind::DecrementOp => {
+                            let increment = self.advance().kind == TokenKind::IncrementOp;
+                            StmtKind::IncDec(expr, increment)
+                        }
+                        TokenKind::DirectionOp => {
+                            self.advance();
+                            StmtKind::Send(expr, self.parse_expr()?)
+                        }
+                        _ => StmtKind::Expr(expr),
+                    }
+                }
+            }
+        };
+        Ok(SimpleStmt::Stmt(Stmt {
+            kind,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn parse_if_stmt(&mut self) -> ParseResult<Stmt> {
+        let start = self.current().span;
+        self.expect(&TokenKind::IfKeyword)?;
+        let (init, cond) = self.with_composite_lit(false, |parser| -> ParseResult<_> {
+            if parser.at(&TokenKind::LeftBrace) {
+                return Err(ParseError::Invalid("missing condition in if statement".to_string(), parser.current().span));
+            }
+            let mut init = None;
+            if !parser.eat(&TokenKind::Semicolon) {
+                let stmt = parser.parse_simple_stmt()?;
+                if !parser.eat(&TokenKind::Semicolon) {
+                    return Ok((None, parser.stmt_to_condition(stmt)?));
+                }
+                init = Some(Box::new(stmt));
+            }
+            if parser.at(&TokenKind::LeftBrace) {
+                return Err(ParseError::Invalid("missing condition in if statement".to_string(), parser.current().span));
+            }
+            Ok((init, parser.parse_expr()?))
+        })?;
+        let then = self.parse_block()?;
+        let mut otherwise = None;
+        if self.eat(&TokenKind::ElseKeyword) {
+            otherwise = Some(Box::new(match self.current().kind {
+                TokenKind::IfKeyword => self.parse_if_stmt()?,
+                TokenKind::LeftBrace => {
+                    let block = self.parse_block()?;
+                    Stmt {
+                        span: block.span,
+                        kind: StmtKind::Block(block),
+                    }
+                }
+                _ => return Err(self.unexpected("if statement or block")),
+            }));
+        }
+        Ok(Stmt {
+            kind: StmtKind::If(IfStmt {
+                init,
+                cond,
+                then,
+                otherwise,
+            }),
+            span: self.span_from(start),
+        })
+    }
+
+    fn stmt_to_condition(&self, stmt: Stmt) -> ParseResult<Expr> {
+        match stmt.kind {
+            StmtKind::Expr(expr) => Ok(expr),
+            _ => Err(ParseError::Invalid("expected boolean expression, found statement".to_string(), stmt.span)),
+        }
+    }
+
+    fn parse_switch_stmt(&mut self) -> ParseResult<StmtKind> {
+        self.expect(&TokenKind::SwitchKeyword)?;
+        let (init, tag) = self.with_composite_lit(false, |parser| -> ParseResult<_> {
+            let mut init = None;
+            let mut tag = None;
+            if !parser.at(&TokenKind::LeftBrace) {
+                if !parser.at(&TokenKind::Semicolon) {
+                    tag = Some(parser.parse_simple_stmt()?);
+                }
+                if parser.eat(&TokenKind::Semicolon) {
+                    init = tag.take().map(Box::new);
+                    if !parser.at(&TokenKind::LeftBrace) {
+                        tag = Some(parser.parse_simple_stmt()?);
+                    }
+                }
+            }
+            Ok((init, tag))
+        })?;
+
+        // switch x := y.(type) or switch y.(type)
+        let type_switch = match tag.as_ref().map(|stmt| &stmt.kind) {
+            Some(StmtKind::ShortVarDecl(names, values)) => {
+                names.len() == 1 && values.len() == 1 && matches!(values[0].kind, ExprKind::TypeAssert(_, None))
+            }
+            Some(StmtKind::Expr(expr)) => matches!(expr.kind, ExprKind::TypeAssert(_, None)),
+            _ => false,
+        };
+
+        self.expect(&TokenKind::LeftBrace)?;
+        let kind = if type_switch {
+            let (binding, expr) = match tag.unwrap().kind {
+                StmtKind::ShortVarDecl(mut names, mut values) => (names.pop(), values.pop().unwrap()),
+                StmtKind::Expr(expr) => (None, expr),
+                _ => unreachable!(),
+            };
+            let mut clauses = vec![];
+            while !self.at(&TokenKind::RightBrace) && !self.at(&TokenKind::EOF) {
+                let start = self.current().span;
+                let header = self.recovering_clause(|parser| {
+                    let mut types = vec![];
+                    let is_default = parser.eat(&TokenKind::DefaultKeyword);
+                    if !is_default {
+                        parser.expect(&TokenKind::CaseKeyword)?;
+                        types.push(parser.parse_type()?);
+                        while parser.eat(&TokenKind::Comma) {
+                            types.push(parser.parse_type()?);
+                        }
+                    }
+                    parser.expect(&TokenKind::Colon)?;
+                    Ok((types, is_default))
+                });
+                let (types, is_default) = match header {
+                    Some(header) => header,
+                    None => continue,
+                };
+                let body = self.parse_stmt_list();
+                clauses.push(TypeCaseClause {
+                    types,
+                    is_default,
+                    body,
+                    span: self.span_from(start),
+                });
+            }
+            StmtKind::TypeSwitch(TypeSwitchStmt {
+                init,
+                binding,
+                expr,
+                clauses,
+            })
+        } else {
+            let tag = match tag {
+                Some(stmt) => Some(self.stmt_to_condition(stmt)?),
+                None => None,
+            };
+            let mut clauses = vec![];
+            while !self.at(&TokenKind::RightBrace) && !self.at(&TokenKind::EOF) {
+                let start = self.current().span;
+                let header = self.recovering_clause(|parser| {
+                    let mut exprs = vec![];
+                    let is_default = parser.eat(&TokenKind::DefaultKeyword);
+                    if !is_default {
+                        parser.expect(&TokenKind::CaseKeyword)?;
+                        exprs = parser.parse_expr_list()?;
+                    }
+                    parser.expect(&TokenKind::Colon)?;
+                    Ok((exprs, is_default))
+                });
+                let (exprs, is_default) = match header {
+                    Some(header) => header,
+                    None => continue,
+                };
+                let body = self.parse_stmt_list();
+                clauses.push(CaseClause {
+                    exprs,
+                    is_default,
+                    body,
+                    span: self.span_from(start),
+                });
+            }
+            StmtKind::Switch(SwitchStmt { init, tag, clauses })
+        };
+        self.expect(&TokenKind::RightBrace)?;
+        Ok(kind)
+    }
+
+    fn parse_select_stmt(&mut self) -> ParseResult<StmtKind> {
+        self.expect(&TokenKind::SelectKeyword)?;
+        self.expect(&TokenKind::LeftBrace)?;
+        let mut clauses = vec![];
+        while !self.at(&TokenKind::RightBrace) && !self.at(&TokenKind::EOF) {
+            let start = self.current().span;
+            let comm = self.recovering_clause(|parser| {
+                let comm = if parser.eat(&TokenKind::DefaultKeyword) {
+                    None
+                } else {
+                    parser.expect(&TokenKind::CaseKeyword)?;
+                    Some(Box::new(parser.parse_simple_stmt()?))
+                };
+                parser.expect(&TokenKind::Colon)?;
+                Ok(comm)
+            });
+            let comm = match comm {
+                Some(comm) => comm,
+                None => continue,
+            };
+            let body = self.parse_stmt_list();
+            clauses.push(CommClause {
+                comm,
+                body,
+                span: self.span_from(start),
+            });
+        }
+        self.expect(&TokenKind::RightBrace)?;
+        Ok(StmtKind::Select(clauses))
+    }
+
+    fn parse_for_stmt(&mut self) -> ParseResult<StmtKind> {
+        self.expect(&TokenKind::ForKeyword)?;
+        let header = self.with_composite_lit(false, |parser| -> ParseResult<_> {
+            let mut init = None;
+            let mut cond = None;
+            let mut post = None;
+            if parser.at(&TokenKind::LeftBrace) {
+                return Ok(Ok((None, None, None)));
+            }
+            if !parser.at(&TokenKind::Semicolon) {
+                match parser.parse_simple_or_range_stmt(true)? {
+                    SimpleStmt::Range(key, value, define, expr) => {
+                        return Ok(Err((key, value, define, expr)));
+                    }
+                    SimpleStmt::Stmt(stmt) => init = Some(stmt),
+                }
+            }
+            if !parser.at(&TokenKind::Semicolon) {
+                // for cond { ... }
+                let cond = parser.stmt_to_condition(init.unwrap())?;
+                return Ok(Ok((None, Some(cond), None)));
+            }
+            parser.advance();
+            if !parser.at(&TokenKind::Semicolon) {
+                cond = Some(parser.parse_expr()?);
+            }
+            parser.expect(&TokenKind::Semicolon)?;
+            if !parser.at(&TokenKind::LeftBrace) {
+                post = Some(Box::new(parser.parse_simple_stmt()?));
+            }
+            Ok(Ok((init.map(Box::new), cond, post)))
+        })?;
+        let body = self.parse_block()?;
+        Ok(match header {
+            Ok((init, cond, post)) => StmtKind::For(ForStmt { init, cond, post, body }),
+            Err((key, value, define, expr)) => StmtKind::Range(RangeStmt {
+                key,
+                value,
+                define,
+                expr,
+                body,
+            }),
+        })
+    }
+
+    pub fn parse_expr(&mut self) -> ParseResult<Expr> {
+        self.parse_binary_expr(1)
+    }
+
+    /// Precedence climbing over the five binary operator levels.
+    fn parse_binary_expr(&mut self, min_precedence: u8) -> ParseResult<Expr> {
+        let mut lhs = self.parse_unary_expr()?;
+        while let Some(op) = binary_op(&self.current().kind) {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.advance();
+            let rhs = self.parse_binary_expr(op.precedence() + 1)?;
+            lhs = Expr {
+                span: lhs.span.to(rhs.span),
+                kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.current().span;
+        let chan_type = self.at(&TokenKind::DirectionOp) && *self.peek(1) == TokenKind::ChanKeyword;
+        let op = match self.current().kind {
+            TokenKind::PlusOp => UnaryOp::Plus,
+            TokenKind::MinusOp => UnaryOp::Neg,
+            TokenKind::LogicNotOp => UnaryOp::Not,
+            TokenKind::BitXorOp => UnaryOp::BitNot,
+            TokenKind::StarOp => UnaryOp::Deref,
+            TokenKind::BitAndOp => UnaryOp::AddrOf,
+            TokenKind::DirectionOp if !chan_type => UnaryOp::Receive,
+            _ => return self.parse_primary_expr(),
+        };
+        self.advance();
+        let operand = self.parse_unary_expr()?;
+        Ok(Expr {
+            kind: ExprKind::Unary(op, Box::new(operand)),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_primary_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.current().span;
+        let mut expr = self.parse_operand()?;
+        loop {
+            let kind = match self.current().kind {
+                TokenKind::Dot => {
+                    self.advance();
+                    if self.eat(&TokenKind::LeftParen) {
+                        let ty = if self.eat(&TokenKind::TypeKeyword) {
+                            None
+                        } else {
+                            Some(Box::new(self.parse_type()?))
+                        };
+                        self.expect(&TokenKind::RightParen)?;
+                        ExprKind::TypeAssert(Box::new(expr), ty)
+                    } else {
+                        ExprKind::Selector(Box::new(expr), self.expect_ident()?)
+                    }
+                }
+                TokenKind::LeftBracket => {
+                    self.advance();
+                    self.with_composite_lit(true, |parser| parser.parse_index_or_slice(expr))?
+                }
+                TokenKind::LeftParen => {
+                    self.advance();
+                    self.with_composite_lit(true, |parser| parser.parse_call(expr))?
+                }
+                TokenKind::LeftBrace if !self.no_composite_lit && is_type_name(&expr) => {
+                    let ty = expr_to_type(&expr).unwrap();
+                    let elements = self.parse_literal_value()?;
+                    ExprKind::CompositeLit(Some(Box::new(ty)), elements)
+                }
+                _ => return Ok(expr),
+            };
+            expr = Expr {
+                kind,
+                span: self.span_from(start),
+            };
+        }
+    }
+
+    /// Parses what follows the `[` after `expr`.
+    fn parse_index_or_slice(&mut self, expr: Expr) -> ParseResult<ExprKind> {
+        let mut low = None;
+        if !self.at(&TokenKind::Colon) {
+            let first = self.parse_expr()?;
+            if self.eat(&TokenKind::RightBracket) {
+                return Ok(ExprKind::Index(Box::new(expr), Box::new(first)));
+            }
+            if self.at(&TokenKind::Comma) {
+                let mut args = vec![first];
+                while self.eat(&TokenKind::Comma) && !self.at(&TokenKind::RightBracket) {
+                    args.push(self.parse_expr()?);
+                }
+                self.expect(&TokenKind::RightBracket)?;
+                return Ok(ExprKind::Instantiation(Box::new(expr), args));
+            }
+            low = Some(Box::new(first));
+        }
+        self.expect(&TokenKind::Colon)?;
+        let mut high = None;
+        let mut max = None;
+        if !matches!(self.current().kind, TokenKind::RightBracket | TokenKind::Colon) {
+            high = Some(Box::new(self.parse_expr()?));
+        }
+        if self.eat(&TokenKind::Colon) {
+            if high.is_none() {
+                return Err(ParseError::Invalid("middle index required in 3-index slice".to_string(), self.current().span));
+            }
+            max = Some(Box::new(self.parse_expr()?));
+        }
+        self.expect(&TokenKind::RightBracket)?;
+        Ok(ExprKind::Slice(Box::new(expr), low, high, max))
+    }
+
+    /// Parses the arguments following the `(` after `callee`.
+    fn parse_call(&mut self, callee: Expr) -> ParseResult<ExprKind> {
+        let mut args = vec![];
+        let mut spread = false;
+        while !self.at(&TokenKind::RightParen) {
+            args.push(self.parse_expr()?);
+            if self.eat(&TokenKind::Elipses) {
+                spread = true;
+            }
+            if !self.eat(&TokenKind::Comma) {
+                break;
+            }
+        }
+        self.expect(&TokenKind::RightParen)?;
+        Ok(ExprKind::Call(Box::new(callee), args, spread))
+    }
+
+    fn parse_operand(&mut self) -> ParseResult<Expr> {
+        let start = self.current().span;
+        let kind = match &self.current().kind {
+            TokenKind::IntLiteral(text, value) => {
+                let kind = ExprKind::IntLit(text.clone(), value.clone());
+                self.advance();
+                kind
+            }
+            TokenKind::FloatLiteral(text, value) => {
+                let kind = ExprKind::FloatLit(text.clone(), value.clone());
+                self.advance();
+                kind
+            }
+            TokenKind::CharLiteral(raw, value) => {
+                let kind = ExprKind::CharLit(raw.clone(), *value);
+                self.advance();
+                kind
+            }
+            TokenKind::StringLiteral(..) | TokenKind::RawStringLiteral(_) => {
+                let (raw, value) = self.string_literal().unwrap();
+                self.advance();
+                ExprKind::StringLit(raw, value)
+            }
+            TokenKind::Identifier(name) => {
+                let name = name.clone();
+                self.advance();
+                ExprKind::Ident(name)
+            }
+            TokenKind::LeftParen => {
+                self.advance();
+                let expr = self.with_composite_lit(true, Parser::parse_expr)?;
+                self.expect(&TokenKind::RightParen)?;
+                ExprKind::Paren(Box::new(expr))
+            }
+            TokenKind::FuncKeyword => {
+                self.advance();
+                let signature = self.parse_signature()?;
+                if self.at(&TokenKind::LeftBrace) {
+                    let body = self.with_composite_lit(true, Parser::parse_block)?;
+                    ExprKind::FuncLit(signature, body)
+                } else {
+                    ExprKind::Type(Box::new(Type {
+                        kind: TypeKind::Function(signature),
+                        span: self.span_from(start),
+                    }))
+                }
+            }
+            TokenKind::LeftBracket
+            | TokenKind::StructKeyword
+            | TokenKind::MapKeyword
+            | TokenKind::ChanKeyword
+            | TokenKind::InterfaceKeyword
+            | TokenKind::DirectionOp => {
+                let ty = self.parse_type()?;
+                if self.at(&TokenKind::LeftBrace) {
+                    let elements = self.parse_literal_value()?;
+                    ExprKind::CompositeLit(Some(Box::new(ty)), elements)
+                } else {
+                    ExprKind::Type(Box::new(ty))
+                }
+            }
+            _ => return Err(self.unexpected("expression")),
+        };
+        Ok(Expr {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    /// LiteralValue = "{" [ ElementList [ "," ] ] "}" .
+    fn parse_literal_value(&mut self) -> ParseResult<Vec<KeyedElement>> {
+        self.expect(&TokenKind::LeftBrace)?;
+        let elements = self.with_composite_lit(true, |parser| -> ParseResult<_> {
+            let mut elements = vec![];
+            while !parser.at(&TokenKind::RightBrace) {
+                let mut key = None;
+                let mut value = parser.parse_element()?;
+                if parser.eat(&TokenKind::Colon) {
+                    key = Some(value);
+                    value = parser.parse_element()?;
+                }
+                elements.push(KeyedElement { key, value });
+                if !parser.eat(&TokenKind::Comma) {
+                    break;
+                }
+            }
+            Ok(elements)
+        })?;
+        self.expect(&TokenKind::RightBrace)?;
+        Ok(elements)
+    }
+
+    /// Element = Expression | LiteralValue .
+    fn parse_element(&mut self) -> ParseResult<Expr> {
+        if self.at(&TokenKind::LeftBrace) {
+            let start = self.current().span;
+            let elements = self.parse_literal_value()?;
+            Ok(Expr {
+                kind: ExprKind::CompositeLit(None, elements),
+                span: self.span_from(start),
+            })
+        } else {
+            self.parse_expr()
+        }
+    }
+}
+
+fn binary_op(kind: &TokenKind) -> Option<BinaryOp> {
+    Some(match kind {
+        TokenKind::LogicOrOp => BinaryOp::LogicOr,
+        TokenKind::LogicAndOp => BinaryOp::LogicAnd,
+        TokenKind::LogicEqOp => BinaryOp::Eq,
+        TokenKind::LogicNotEqOp => BinaryOp::NotEq,
+        TokenKind::LogicLessOp => BinaryOp::Less,
+        TokenKind::LogicLessEqOp => BinaryOp::LessEq,
+        TokenKind::LogicGreaterOp => BinaryOp::Greater,
+        TokenKind::LogicGreaterEqOp => BinaryOp::GreaterEq,
+        TokenKind::PlusOp => BinaryOp::Add,
+        TokenKind::MinusOp => BinaryOp::Sub,
+        TokenKind::BitOrOp => BinaryOp::BitOr,
+        TokenKind::BitXorOp => BinaryOp::BitXor,
+        TokenKind::StarOp => BinaryOp::Mul,
+        TokenKind::DivOp => BinaryOp::Div,
+        TokenKind::ModOp => BinaryOp::Mod,
+        TokenKind::LShiftOp => BinaryOp::LShift,
+        TokenKind::RShiftOp => BinaryOp::RShift,
+        TokenKind::BitAndOp => BinaryOp::BitAnd,
+        TokenKind::BitClearOp => BinaryOp::BitClear,
+        _ => return None,
+    })
+}
+
+/// The binary operator applied by a compound assignment such as `+=`.
+fn compound_assign_op(kind: &TokenKind) -> Option<BinaryOp> {
+    Some(match kind {
+        TokenKind::PlusEqOp => BinaryOp::Add,
+        TokenKind::MinusEqOp => BinaryOp::Sub,
+        TokenKind::StarEqOp => BinaryOp::Mul,
+        TokenKind::DivEqOp => BinaryOp::Div,
+        TokenKind::ModEqOp => BinaryOp::Mod,
+        TokenKind::BitAndEqOp => BinaryOp::BitAnd,
+        TokenKind::BitOrEqOp => BinaryOp::BitOr,
+        TokenKind::BitXorEqOp => BinaryOp::BitXor,
+        TokenKind::BitClearEqOp => BinaryOp::BitClear,
+        TokenKind::LShiftEqOp => BinaryOp::LShift,
+        TokenKind::RShiftEqOp => BinaryOp::RShift,
+        _ => return None,
+    })
+}
+
+/// Whether `expr` can be the type of a composite literal written as `T{ ... }`.
+fn is_type_name(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Ident(_) => true,
+        ExprKind::Index(base, _) | ExprKind::Instantiation(base, _) => matches!(base.kind, ExprKind::Ident(_)),
+        _ => false,
+    }
+}
+
+/// Whether type parameters like `[N *M]` could be the multiplication `N * M` instead.
+fn is_array_length(params: &[TypeParam]) -> bool {
+    match params {
+        [param] => match &param.constraint[..] {
+            [term] => param.names.len() == 1 && !term.underlying && matches!(term.ty.kind, TypeKind::Pointer(_)),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Reinterprets an expression that was parsed before it was known to be a type.
+pub(crate) fn expr_to_type(expr: &Expr) -> Option<Type> {
+    let kind = match &expr.kind {
+        ExprKind::Ident(name) => TypeKind::Name(
+            Ident {
+                name: name.clone(),
+                span: expr.span,
+            },
+            vec![],
+        ),
+        ExprKind::Index(base, arg) => match expr_to_type(base)?.kind {
+            TypeKind::Name(name, _) => TypeKind::Name(name, vec![expr_to_type(arg)?]),
+            _ => return None,
+        },
+        ExprKind::Instantiation(base, args) => match expr_to_type(base)?.kind {
+            TypeKind::Name(name, _) => TypeKind::Name(
+                name,
+                args.iter().map(expr_to_type).collect::<Option<Vec<_>>>()?,
+            ),
+            _ => return None,
+        },
+        ExprKind::Unary(UnaryOp::Deref, inner) => TypeKind::Pointer(Box::new(expr_to_type(inner)?)),
+        ExprKind::Paren(inner) => return expr_to_type(inner),
+        ExprKind::Type(ty) => return Some((**ty).clone()),
+        _ => return None,
+    };
+    Some(Type {
+        kind,
+        span: expr.span,
+    })
+}
+
+#[cfg(test)]
+pub mod parser_test {
+    use super::ParseError;
+    use crate::ast::*;
+    use crate::lexer::{LexError, Lexer};
+
+    fn parse_file(file_name: &str) -> Result<Program, Vec<ParseError>> {
+        super::Parser::new(Lexer::new(file_name.to_string()).unwrap()).parse()
+    }
+
+    /// Renders an expression with every operation parenthesized, to make precedence visible.
+    fn render(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Ident(name) => name.clone(),
+            ExprKind::IntLit(text, _) => text.clone(),
+            ExprKind::Selector(base, field) => format!("{}.{}", render(base), field.name),
+            ExprKind::Index(base, index) => format!("{}[{}]", render(base), render(index)),
+            ExprKind::Slice(base, low, high, max) => {
+                let part = |e: &Option<Box<Expr>>| e.as_ref().map(|e| render(e)).unwrap_or_default();
+                match max {
+                    Some(_) => format!("{}[{}:{}:{}]", render(base), part(low), part(high), part(max)),
+                    None => format!("{}[{}:{}]", render(base), part(low), part(high)),
+                }
+            }
+            ExprKind::Call(callee, args, _) => {
+                let args = args.iter().map(render).collect::<Vec<_>>().join(", ");
+                format!("{}({})", render(callee), args)
+            }
+            ExprKind::Unary(op, operand) => format!("({}{})", op, render(operand)),
+            ExprKind::Binary(op, lhs, rhs) => format!("({} {} {})", render(lhs), op, render(rhs)),
+            _ => panic!("unexpected expression {:?}", expr),
+        }
+    }
+
+    fn var_value(decl: &TopLevelDecl) -> &Expr {
+        match decl {
+            TopLevelDecl::Decl(Decl::Var(specs, _)) => &specs[0].values[0],
+            _ => panic!("expected a var declaration"),
+        }
+    }
+
+    #[test]
+    pub fn given_valid_program_should_parse_all_declarations() {
+        let program = parse_file("testfiles/valid_program.test").unwrap();
+        assert_eq!(program.package.name, "main");
+        assert_eq!(program.imports.len(), 2);
+        assert_eq!(program.imports[1].alias.as_ref().unwrap().name, "s");
+        assert_eq!(program.imports[1].path, "strings");
+        assert_eq!(program.decls.len(), 12);
+
+        let funcs = program
+                .decls
+                .iter()
+                .filter_map(|decl| match decl {
+                    TopLevelDecl::Func(func) => Some(func),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+        assert_eq!(funcs.len(), 4);
+        assert_eq!(funcs[0].name.name, "Move");
+        assert!(funcs[0].receiver.is_some());
+        assert_eq!(funcs[0].signature.params.len(), 2);
+        assert_eq!(funcs[1].type_params.len(), 1);
+        assert!(funcs[1].signature.params[0].variadic);
+        assert_eq!(funcs[2].signature.results[1].name.as_ref().unwrap().name, "err");
+
+        let main_body = &funcs[3].body.as_ref().unwrap().stmts;
+        assert!(main_body.iter().any(|stmt| matches!(stmt.kind, StmtKind::Labeled(..))));
+        assert!(main_body.iter().any(|stmt| matches!(stmt.kind, StmtKind::TypeSwitch(..))));
+        assert!(main_body.iter().any(|stmt| matches!(stmt.kind, StmtKind::Select(..))));
+        assert!(main_body.iter().any(|stmt| matches!(stmt.kind, StmtKind::Send(..))));
+        assert_eq!(main_body.last().unwrap().span.start.line, 119);
+    }
+
+    #[test]
+    pub fn given_expressions_should_respect_precedence() {
+        let program = parse_file("testfiles/expressions.test").unwrap();
+        let rendered = program.decls.iter().map(|decl| render(var_value(decl))).collect::<Vec<_>>();
+        assert_eq!(rendered, vec![
+            "((1 + (2 * 3)) - 4)",
+            "(x || (y && ((!z) == w)))",
+            "(((-x.y[1](2)) << 3) &^ 1)",
+            "((<-ch) + ((*p) % 2))",
+            "(s[1:] > t[:2:3])",
+        ]);
+    }
+
+    #[test]
+    pub fn given_syntax_error_should_report_expected_and_found() {
+        let errors = parse_file("testfiles/illegal_syntax.test").unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].to_string(), "ERROR: expected ), found newline on line 4");
+        assert!(matches!(errors[1], ParseError::Lex(LexError::UnknownCharacter('@', _))));
+        assert_eq!(errors[2].to_string(), "ERROR: expected expression, found } on line 7");
+    }
+
+    #[test]
+    pub fn given_several_syntax_errors_should_recover_and_report_each() {
+        let lexer = Lexer::new("testfiles/syntax_recovery.test".to_string()).unwrap();
+        let (program, errors) = super::Parser::new(lexer).parse_recovering();
+        let messages = errors.iter().map(ParseError::to_string).collect::<Vec<_>>();
+        assert_eq!(messages, vec![
+            "ERROR: expected type, found { on line 3",
+            "ERROR: expected ;, found Int 2 on line 7",
+            "ERROR: expected expression, found ) on line 10",
+            "ERROR: expected expression, found for on line 12",
+            "ERROR: expected declaration, found } on line 17",
+            "ERROR: expected expression, found ] on line 21",
+        ]);
+
+        let kinds = program
+                .decls
+                .iter()
+                .map(|decl| match decl {
+                    TopLevelDecl::Bad(_) => "bad",
+                    TopLevelDecl::Func(_) => "func",
+                    TopLevelDecl::Decl(_) => "decl",
+                })
+                .collect::<Vec<_>>();
+        assert_eq!(kinds, vec!["bad", "bad", "func", "bad", "decl", "bad"]);
+        assert_eq!(program.decls[0].span().start.line, 3);
+        assert_eq!(program.decls[0].span().end.line, 6);
+
+        let body = &match &program.decls[2] {
+            TopLevelDecl::Func(func) => func.body.as_ref().unwrap(),
+            _ => unreachable!(),
+        }
+        .stmts;
+        let if_body = match &body[0].kind {
+            StmtKind::If(if_stmt) => &if_stmt.then,
+            kind => panic!("expected an if statement, got {:?}", kind),
+        };
+        assert_eq!(if_body.stmts[0].kind, StmtKind::Bad);
+        assert_eq!(body[1].kind, StmtKind::Bad);
+        assert!(matches!(body[2].kind, StmtKind::Expr(_)));
+    }
+
+    #[test]
+    pub fn given_bad_case_clauses_should_recover_at_the_next_clause() {
+        let lexer = Lexer::new("testfiles/clause_recovery.test".to_string()).unwrap();
+        let (program, errors) = super::Parser::new(lexer).parse_recovering();
+        let messages = errors.iter().map(ParseError::to_string).collect::<Vec<_>>();
+        assert_eq!(messages, vec![
+            "ERROR: expected case, found Identifier cas on line 6",
+            "ERROR: expected expression, found ) on line 9",
+            "ERROR: expected expression, found : on line 10",
+            "ERROR: expected :, found newline on line 16",
+            "ERROR: expected type, found : on line 21",
+        ]);
+
+        // the closing braces of the switches still close them, so both functions are intact.
+        assert!(program.decls.iter().all(|decl| matches!(decl, TopLevelDecl::Func(_))));
+        let body = &match &program.decls[0] {
+            TopLevelDecl::Func(func) => func.body.as_ref().unwrap(),
+            _ => unreachable!(),
+        }
+        .stmts;
+        let clause_counts = body
+                .iter()
+                .filter_map(|stmt| match &stmt.kind {
+                    StmtKind::Switch(switch) => Some(switch.clauses.len()),
+                    StmtKind::Select(clauses) => Some(clauses.len()),
+                    StmtKind::TypeSwitch(switch) => Some(switch.clauses.len()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+        assert_eq!(clause_counts, vec![2, 1, 1]);
+        assert!(matches!(body.last().unwrap().kind, StmtKind::Expr(_)));
+    }
+
+    #[test]
+    pub fn given_bracket_after_type_name_should_tell_type_params_from_array_length() {
+        let source = "package main\ntype A [N]int\ntype B [N * M]int\ntype C[T any, U ~int | ~string] []T\ntype D [N * 2]int\n";
+        let program = super::Parser::new(Lexer::from_source(source)).parse().unwrap();
+        let specs = program
+                .decls
+                .iter()
+                .map(|decl| match decl {
+                    TopLevelDecl::Decl(Decl::Type(specs, _)) => &specs[0],
+                    _ => panic!("expected a type declaration"),
+                })
+                .collect::<Vec<_>>();
+        let lengths = specs
+                .iter()
+                .map(|spec| match &spec.ty.kind {
+                    TypeKind::Array(Some(length), _) => Some(render(length)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+        assert_eq!(lengths, vec![Some("N".to_string()), Some("(N * M)".to_string()), None, Some("(N * 2)".to_string())]);
+        assert_eq!(specs[2].type_params.len(), 2);
+        assert_eq!(specs[2].type_params[1].constraint.len(), 2);
+    }
+}
+
+#[cfg(test)]
+pub mod parser_proptest {
+    use super::Parser;
+    use crate::ast::TopLevelDecl;
+    use crate::lexer::Lexer;
+    use proptest::prelude::*;
+    use proptest::sample::select;
+
+    /// Token soup, mostly made of what the parser recovers at or nests with, so recovery is exercised a lot.
+    fn source() -> impl Strategy<Value = String> {
+        let token = select(vec![
+            "package", "main", "func", "var", "const", "type", "import", "if", "for", "switch", "case", "default",
+            "return", "struct", "x", "1", "\"s\"", "(", ")", "{", "}", "[", "]", ";", ",", ":", ":=", "=", "+", "*",
+            ".", "\n",
+        ]);
+        prop::collection::vec(token, 0..48).prop_map(|tokens| tokens.join(" "))
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig { timeout: 5000, ..ProptestConfig::default() })]
+
+        #[test]
+        fn given_arbitrary_tokens_should_parse_to_eof_with_sorted_errors(source in source()) {
+            let (program, errors) = Parser::new(Lexer::from_source(&source)).parse_recovering();
+            let offsets = errors.iter().map(|error| error.span().start.offset).collect::<Vec<_>>();
+            prop_assert!(offsets.windows(2).all(|pair| pair[0] <= pair[1]), "{:?} in {:?}", errors, source);
+            // recovering always consumes a token, so a bad declaration is never empty.
+            for decl in &program.decls {
+                if let TopLevelDecl::Bad(span) = decl {
+                    prop_assert!(span.start.offset < span.end.offset, "{:?} in {:?}", span, source);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,799 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::ast::*;
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::token::Span;
+
+/// Resolves every identifier in `program` to its declaration.
+///
+/// Reports undeclared and redeclared identifiers, unused local variables and unused imports,
+/// sorted by position.
+pub fn analyze(program: &Program) -> Result<SymbolTable, Vec<SemaError>> {
+    let mut resolver = Resolver::new();
+    resolver.program(program);
+    resolver.finish()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SemaError {
+    Undeclared(String, Span),
+    Redeclared { name: String, span: Span, previous: Span },
+    UnusedVariable(String, Span),
+    UnusedImport(String, Span),
+    NoNewVariables(Span),
+}
+
+impl SemaError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Undeclared(_, span)
+            | Self::Redeclared { span, .. }
+            | Self::UnusedVariable(_, span)
+            | Self::UnusedImport(_, span)
+            | Self::NoNewVariables(span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for SemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Undeclared(name, _) => write!(f, "ERROR: undefined: {}", name)?,
+            Self::Redeclared { name, previous, .. } => write!(
+                f,
+                "ERROR: {} redeclared in this block (previous declaration on line {})",
+                name, previous.start.line
+            )?,
+            Self::UnusedVariable(name, _) => write!(f, "ERROR: declared and not used: {}", name)?,
+            Self::UnusedImport(path, _) => write!(f, "ERROR: \"{}\" imported and not used", path)?,
+            Self::NoNewVariables(_) => write!(f, "ERROR: no new variables on left side of :=")?,
+        }
+        write!(f, " on line {}", self.span().start.line)
+    }
+}
+
+impl ToDiagnostic for SemaError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let span = self.span();
+        match self {
+            Self::Undeclared(name, _) => Diagnostic::error("E0301", format!("undefined: {}", name), span)
+                    .with_primary_label("not found in this scope"),
+            Self::Redeclared { name, previous, .. } => {
+                Diagnostic::error("E0302", format!("{} redeclared in this block", name), span)
+                        .with_primary_label(format!("{} redeclared here", name))
+                        .with_label(*previous, "previous declaration")
+            }
+            Self::UnusedVariable(name, _) => Diagnostic::error("E0303", format!("declared and not used: {}", name), span)
+                    .with_help(format!("use {} or remove it; assign to _ to discard a value", name)),
+            Self::UnusedImport(path, _) => {
+                Diagnostic::error("E0304", format!("\"{}\" imported and not used", path), span)
+                        .with_help("remove the import, or import it as _ for its side effects")
+            }
+            Self::NoNewVariables(_) => Diagnostic::error("E0305", "no new variables on left side of :=", span)
+                    .with_help("use = to assign to existing variables"),
+        }
+    }
+}
+
+pub type SymbolId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    Universe,
+    Package,
+    File,
+    Function,
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolKind {
+    Package(String), // the import path
+    Const,
+    Type,
+    Var,
+    Param, // parameters, named results and receivers
+    Func,
+    Builtin,
+    Nil,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub span: Option<Span>, // None for predeclared identifiers
+    pub scope: ScopeKind,
+    pub used: bool,
+}
+
+/// Every declaration in a program, and which declaration each identifier refers to.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    pub symbols: Vec<Symbol>,
+    pub uses: HashMap<Span, SymbolId>, // identifier uses, keyed by the span of the identifier
+    pub defs: HashMap<Span, SymbolId>, // declaring identifiers
+}
+
+impl SymbolTable {
+    /// The declaration that the identifier at `span` refers to or declares.
+    pub fn lookup(&self, span: Span) -> Option<SymbolId> {
+        self.uses.get(&span).or_else(|| self.defs.get(&span)).copied()
+    }
+
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id]
+    }
+}
+
+const UNIVERSE_TYPES: [&str; 22] = [
+    "any", "bool", "byte", "comparable", "complex64", "complex128", "error", "float32", "float64", "int", "int8",
+    "int16", "int32", "int64", "rune", "string", "uint", "uint8", "uint16", "uint32", "uint64", "uintptr",
+];
+const UNIVERSE_CONSTS: [&str; 3] = ["true", "false", "iota"];
+const UNIVERSE_FUNCS: [&str; 18] = [
+    "append", "cap", "clear", "close", "complex", "copy", "delete", "imag", "len", "make", "max", "min", "new",
+    "panic", "print", "println", "real", "recover",
+];
+
+struct Scope {
+    kind: ScopeKind,
+    names: HashMap<String, SymbolId>,
+}
+
+struct Resolver {
+    table: SymbolTable,
+    scopes: Vec<Scope>,
+    errors: Vec<SemaError>,
+}
+
+impl Resolver {
+    fn new() -> Resolver {
+        let mut resolver = Resolver {
+            table: SymbolTable::default(),
+            scopes: vec![],
+            errors: vec![],
+        };
+        resolver.push_scope(ScopeKind::Universe);
+        let predeclared = UNIVERSE_TYPES
+                .iter()
+                .map(|name| (name, SymbolKind::Type))
+                .chain(UNIVERSE_CONSTS.iter().map(|name| (name, SymbolKind::Const)))
+                .chain(UNIVERSE_FUNCS.iter().map(|name| (name, SymbolKind::Builtin)))
+                .chain([(&"nil", SymbolKind::Nil)]);
+        for (name, kind) in predeclared {
+            let id = resolver.add_symbol(name, kind, None);
+            resolver.scopes[0].names.insert(name.to_string(), id);
+        }
+        resolver
+    }
+
+    fn finish(mut self) -> Result<SymbolTable, Vec<SemaError>> {
+        for symbol in &self.table.symbols {
+            let local = matches!(symbol.scope, ScopeKind::Function | ScopeKind::Block);
+            match (&symbol.kind, symbol.span) {
+                (SymbolKind::Var, Some(span)) if local && !symbol.used && symbol.name != "_" => {
+                    self.errors.push(SemaError::UnusedVariable(symbol.name.clone(), span));
+                }
+                // blank imports are only there for their side effects, and dot imports aren't used by name.
+                (SymbolKind::Package(path), Some(span)) if !symbol.used && symbol.name != "_" && symbol.name != "." => {
+                    self.errors.push(SemaError::UnusedImport(path.clone(), span));
+                }
+                _ => {}
+            }
+        }
+        if self.errors.is_empty() {
+            Ok(self.table)
+        } else {
+            self.errors.sort_by_key(|error| error.span().start.offset);
+            Err(self.errors)
+        }
+    }
+
+    fn push_scope(&mut self, kind: ScopeKind) {
+        self.scopes.push(Scope {
+            kind,
+            names: HashMap::new(),
+        });
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn add_symbol(&mut self, name: &str, kind: SymbolKind, span: Option<Span>) -> SymbolId {
+        let scope = self.scopes.last().unwrap().kind;
+        self.table.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            span,
+            scope,
+            used: false,
+        });
+        self.table.symbols.len() - 1
+    }
+
+    /// Declares `ident` in the innermost scope. The blank identifier, and the `.` of a dot import, get a symbol
+    /// but are never in scope.
+    fn declare(&mut self, ident: &Ident, kind: SymbolKind) -> SymbolId {
+        let id = self.add_symbol(&ident.name, kind, Some(ident.span));
+        self.table.defs.insert(ident.span, id);
+        if ident.name != "_" && ident.name != "." {
+            self.insert(&ident.name, id);
+        }
+        id
+    }
+
+    /// Puts an existing symbol in the innermost scope, reporting a clash with anything already there.
+    fn insert(&mut self, name: &str, id: SymbolId) {
+        let span = self.table.symbols[id].span.unwrap();
+        let scope = self.scopes.len() - 1;
+        // package level names also clash with imports in the file scope, and the other way around.
+        let clash = match self.scopes[scope].kind {
+            ScopeKind::Package | ScopeKind::File => self.scopes[1..].iter().find_map(|scope| scope.names.get(name)),
+            _ => self.scopes[scope].names.get(name),
+        };
+        if let Some(&previous) = clash {
+            self.errors.push(SemaError::Redeclared {
+                name: name.to_string(),
+                span,
+                previous: self.table.symbols[previous].span.unwrap(),
+            });
+        } else {
+            self.scopes[scope].names.insert(name.to_string(), id);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<SymbolId> {
+        self.scopes.iter().rev().find_map(|scope| scope.names.get(name)).copied()
+    }
+
+    /// Records a use of `name`. Assigning to a variable doesn't count as using it, so `mark_used` is false then.
+    fn resolve(&mut self, name: &str, span: Span, mark_used: bool) {
+        if name == "_" {
+            return;
+        }
+        match self.lookup(name) {
+            Some(id) => {
+                self.table.uses.insert(span, id);
+                if mark_used {
+                    self.table.symbols[id].used = true;
+                }
+            }
+            None => self.errors.push(SemaError::Undeclared(name.to_string(), span)),
+        }
+    }
+
+    fn program(&mut self, program: &Program) {
+        // package level names are visible in the whole package, so declare them all before resolving anything.
+        self.push_scope(ScopeKind::Package);
+        let package_scope = self.scopes.len() - 1;
+        self.push_scope(ScopeKind::File);
+        for import in &program.imports {
+            let name = match &import.alias {
+                Some(alias) => alias.clone(),
+                None => Ident {
+                    name: import.path.rsplit('/').next().unwrap().to_string(),
+                    span: import.span,
+                },
+            };
+            self.declare(&name, SymbolKind::Package(import.path.clone()));
+        }
+        // swap the scopes so package level declarations go into the package scope.
+        let file_scope = self.scopes.pop().unwrap();
+        for decl in &program.decls {
+            match decl {
+                TopLevelDecl::Decl(Decl::Const(specs, _)) => {
+                    for name in specs.iter().flat_map(|spec| &spec.names) {
+                        self.declare(name, SymbolKind::Const);
+                    }
+                }
+                TopLevelDecl::Decl(Decl::Var(specs, _)) => {
+                    for name in specs.iter().flat_map(|spec| &spec.names) {
+                        self.declare(name, SymbolKind::Var);
+                    }
+                }
+                TopLevelDecl::Decl(Decl::Type(specs, _)) => {
+                    for spec in specs {
+                        self.declare(&spec.name, SymbolKind::Type);
+                    }
+                }
+                TopLevelDecl::Func(func) if func.receiver.is_none() && func.name.name != "init" => {
+                    self.declare(&func.name, SymbolKind::Func);
+                }
+                TopLevelDecl::Func(func) => {
+                    // methods and init functions aren't in scope anywhere.
+                    let id = self.add_symbol(&func.name.name, SymbolKind::Func, Some(func.name.span));
+                    self.table.defs.insert(func.name.span, id);
+                }
+                TopLevelDecl::Bad(_) => {}
+            }
+        }
+        for (name, &id) in &file_scope.names {
+            if let Some(&previous) = self.scopes[package_scope].names.get(name) {
+                self.errors.push(SemaError::Redeclared {
+                    name: name.clone(),
+                    span: self.table.symbols[previous].span.unwrap(),
+                    previous: self.table.symbols[id].span.unwrap(),
+                });
+            }
+        }
+        self.scopes.push(file_scope);
+
+        for decl in &program.decls {
+            match decl {
+                TopLevelDecl::Decl(Decl::Const(specs, _)) => {
+                    for spec in specs {
+                        self.value_spec(&spec.ty, &spec.values);
+                    }
+                }
+                TopLevelDecl::Decl(Decl::Var(specs, _)) => {
+                    for spec in specs {
+                        self.value_spec(&spec.ty, &spec.values);
+                    }
+                }
+                TopLevelDecl::Decl(Decl::Type(specs, _)) => {
+                    for spec in specs {
+                        self.type_spec_body(spec);
+                    }
+                }
+                TopLevelDecl::Func(func) => self.func_decl(func),
+                TopLevelDecl::Bad(_) => {}
+            }
+        }
+        self.pop_scope();
+        self.pop_scope();
+    }
+
+    fn value_spec(&mut self, ty: &Option<Type>, values: &[Expr]) {
+        if let Some(ty) = ty {
+            self.ty(ty);
+        }
+        for value in values {
+            self.expr(value);
+        }
+    }
+
+    fn type_spec_body(&mut self, spec: &TypeSpec) {
+        self.push_scope(ScopeKind::Block);
+        self.type_params(&spec.type_params);
+        self.ty(&spec.ty);
+        self.pop_scope();
+    }
+
+    fn type_params(&mut self, params: &[TypeParam]) {
+        for param in params {
+            for name in &param.names {
+                self.declare(name, SymbolKind::Type);
+            }
+        }
+        for term in params.iter().flat_map(|param| &param.constraint) {
+            self.ty(&term.ty);
+        }
+    }
+
+    fn func_decl(&mut self, func: &FuncDecl) {
+        self.push_scope(ScopeKind::Function);
+        self.type_params(&func.type_params);
+        if let Some(receiver) = &func.receiver {
+            // the type arguments of a generic receiver, as in `func (l *List[T])`, declare type parameters.
+            let base = match &receiver.ty.kind {
+                TypeKind::Pointer(inner) => inner,
+                _ => &receiver.ty,
+            };
+            match &base.kind {
+                TypeKind::Name(name, args) => {
+                    self.resolve(&name.name, name.span, true);
+                    for arg in args {
+                        match &arg.kind {
+                            TypeKind::Name(param, param_args) if param_args.is_empty() => {
+                                self.declare(param, SymbolKind::Type);
+                            }
+                            _ => self.ty(arg),
+                        }
+                    }
+                }
+                _ => self.ty(base),
+            }
+        }
+        self.signature(&func.signature);
+        if let Some(receiver) = &func.receiver {
+            if let Some(name) = &receiver.name {
+                self.declare(name, SymbolKind::Param);
+            }
+        }
+        self.params(&func.signature);
+        if let Some(body) = &func.body {
+            self.stmts(&body.stmts);
+        }
+        self.pop_scope();
+    }
+
+    /// Resolves the types in a signature, without declaring the parameter names.
+    fn signature(&mut self, signature: &Signature) {
+        for param in signature.params.iter().chain(&signature.results) {
+            self.ty(&param.ty);
+        }
+    }
+
+    fn params(&mut self, signature: &Signature) {
+        for param in signature.params.iter().chain(&signature.results) {
+            if let Some(name) = &param.name {
+                self.declare(name, SymbolKind::Param);
+            }
+        }
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        match &ty.kind {
+            TypeKind::Name(name, args) => {
+                self.resolve(&name.name, name.span, true);
+                for arg in args {
+                    self.ty(arg);
+                }
+            }
+            TypeKind::Array(len, elem) => {
+                if let Some(len) = len {
+                    self.expr(len);
+                }
+                self.ty(elem);
+            }
+            TypeKind::Slice(elem) | TypeKind::Pointer(elem) | TypeKind::Channel(_, elem) => self.ty(elem),
+            TypeKind::Struct(fields) => {
+                for field in fields {
+                    self.ty(&field.ty);
+                }
+            }
+            TypeKind::Function(signature) => self.signature(signature),
+            TypeKind::Interface(elems) => {
+                for elem in elems {
+                    match elem {
+                        InterfaceElem::Method(_, signature) => self.signature(signature),
+                        InterfaceElem::Union(terms) => {
+                            for term in terms {
+                                self.ty(&term.ty);
+                            }
+                        }
+                    }
+                }
+            }
+            TypeKind::Map(key, value) => {
+                self.ty(key);
+                self.ty(value);
+            }
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.push_scope(ScopeKind::Block);
+        self.stmts(stmts);
+        self.pop_scope();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Decl(Decl::Const(specs, _)) => {
+                for spec in specs {
+                    // the scope of a local constant or variable starts after its spec.
+                    self.value_spec(&spec.ty, &spec.values);
+                    for name in &spec.names {
+                        self.declare(name, SymbolKind::Const);
+                    }
+                }
+            }
+            StmtKind::Decl(Decl::Var(specs, _)) => {
+                for spec in specs {
+                    self.value_spec(&spec.ty, &spec.values);
+                    for name in &spec.names {
+                        self.declare(name, SymbolKind::Var);
+                    }
+                }
+            }
+            StmtKind::Decl(Decl::Type(specs, _)) => {
+                for spec in specs {
+                    // unlike variables, a local type is in scope in its own definition.
+                    self.declare(&spec.name, SymbolKind::Type);
+                    self.type_spec_body(spec);
+                }
+            }
+            StmtKind::Labeled(_, inner) => self.stmt(inner),
+            StmtKind::Expr(expr) | StmtKind::Go(expr) | StmtKind::Defer(expr) => self.expr(expr),
+            StmtKind::Send(channel, value) => {
+                self.expr(channel);
+                self.expr(value);
+            }
+            StmtKind::IncDec(target, _) => self.assign_target(target),
+            StmtKind::Assign(lhs, _, rhs) => {
+                for value in rhs {
+                    self.expr(value);
+                }
+                for target in lhs {
+                    self.assign_target(target);
+                }
+            }
+            StmtKind::ShortVarDecl(names, values) => {
+                for value in values {
+                    self.expr(value);
+                }
+                self.short_var_decl(names, stmt.span);
+            }
+            StmtKind::Return(values) => {
+                for value in values {
+                    self.expr(value);
+                }
+            }
+            StmtKind::Break(_) | StmtKind::Continue(_) | StmtKind::Goto(_) | StmtKind::Fallthrough | StmtKind::Empty | StmtKind::Bad => {}
+            StmtKind::Block(block) => self.block(&block.stmts),
+            StmtKind::If(if_stmt) => {
+                self.push_scope(ScopeKind::Block);
+                if let Some(init) = &if_stmt.init {
+                    self.stmt(init);
+                }
+                self.expr(&if_stmt.cond);
+                self.block(&if_stmt.then.stmts);
+                if let Some(otherwise) = &if_stmt.otherwise {
+                    self.stmt(otherwise);
+                }
+                self.pop_scope();
+            }
+            StmtKind::Switch(switch) => {
+                self.push_scope(ScopeKind::Block);
+                if let Some(init) = &switch.init {
+                    self.stmt(init);
+                }
+                if let Some(tag) = &switch.tag {
+                    self.expr(tag);
+                }
+                for clause in &switch.clauses {
+                    for expr in &clause.exprs {
+                        self.expr(expr);
+                    }
+                    self.block(&clause.body);
+                }
+                self.pop_scope();
+            }
+            StmtKind::TypeSwitch(switch) => {
+                self.push_scope(ScopeKind::Block);
+                if let Some(init) = &switch.init {
+                    self.stmt(init);
+                }
+                self.expr(&switch.expr);
+                // the binding is declared once but is in scope in every clause, and is used if any clause uses it.
+                let binding = switch.binding.as_ref().map(|binding| {
+                    self.push_scope(ScopeKind::Block);
+                    let id = self.add_symbol(&binding.name, SymbolKind::Var, Some(binding.span));
+                    self.pop_scope();
+                    self.table.defs.insert(binding.span, id);
+                    id
+                });
+                for clause in &switch.clauses {
+                    for ty in &clause.types {
+                        self.ty(ty);
+                    }
+                    self.push_scope(ScopeKind::Block);
+                    if let (Some(id), Some(binding)) = (binding, &switch.binding) {
+                        if binding.name != "_" {
+                            self.insert(&binding.name, id);
+                        }
+                    }
+                    self.stmts(&clause.body);
+                    self.pop_scope();
+                }
+                self.pop_scope();
+            }
+            StmtKind::Select(clauses) => {
+                for clause in clauses {
+                    self.push_scope(ScopeKind::Block);
+                    if let Some(comm) = &clause.comm {
+                        self.stmt(comm);
+                    }
+                    self.stmts(&clause.body);
+                    self.pop_scope();
+                }
+            }
+            StmtKind::For(for_stmt) => {
+                self.push_scope(ScopeKind::Block);
+                if let Some(init) = &for_stmt.init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = &for_stmt.cond {
+                    self.expr(cond);
+                }
+                if let Some(post) = &for_stmt.post {
+                    self.stmt(post);
+                }
+                self.block(&for_stmt.body.stmts);
+                self.pop_scope();
+            }
+            StmtKind::Range(range) => {
+                self.push_scope(ScopeKind::Block);
+                self.expr(&range.expr);
+                for target in range.key.iter().chain(&range.value) {
+                    match &target.kind {
+                        ExprKind::Ident(name) if range.define => {
+                            let ident = Ident {
+                                name: name.clone(),
+                                span: target.span,
+                            };
+                            self.declare(&ident, SymbolKind::Var);
+                        }
+                        _ => self.assign_target(target),
+                    }
+                }
+                self.block(&range.body.stmts);
+                self.pop_scope();
+            }
+        }
+    }
+
+    /// `a, b := ...` declares the names that are new to the innermost scope and assigns to the rest.
+    fn short_var_decl(&mut self, names: &[Ident], span: Span) {
+        let mut any_new = false;
+        for name in names {
+            let existing = match name.name.as_str() {
+                "_" => None,
+                _ => self.scopes.last().unwrap().names.get(&name.name).copied(),
+            };
+            match existing {
+                Some(id) => {
+                    self.table.uses.insert(name.span, id);
+                }
+                None if name.name == "_" => {
+                    self.declare(name, SymbolKind::Var);
+                }
+                None => {
+                    any_new = true;
+                    self.declare(name, SymbolKind::Var);
+                }
+            }
+        }
+        if !any_new {
+            self.errors.push(SemaError::NoNewVariables(span));
+        }
+    }
+
+    /// The left hand side of an assignment. Assigning to a plain variable doesn't use it.
+    fn assign_target(&mut self, target: &Expr) {
+        match &target.kind {
+            ExprKind::Ident(name) => self.resolve(name, target.span, false),
+            ExprKind::Paren(inner) => self.assign_target(inner),
+            _ => self.expr(target),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Ident(name) => self.resolve(name, expr.span, true),
+            ExprKind::IntLit(..) | ExprKind::FloatLit(..) | ExprKind::CharLit(..) | ExprKind::StringLit(..) => {}
+            ExprKind::CompositeLit(ty, elements) => {
+                if let Some(ty) = ty {
+                    self.ty(ty);
+                }
+                self.elements(elements);
+            }
+            ExprKind::FuncLit(signature, body) => {
+                self.push_scope(ScopeKind::Function);
+                self.signature(signature);
+                self.params(signature);
+                self.stmts(&body.stmts);
+                self.pop_scope();
+            }
+            ExprKind::Paren(inner) => self.expr(inner),
+            // field and method names need types to resolve, which is left to the type checker.
+            ExprKind::Selector(base, _) => self.expr(base),
+            ExprKind::Index(base, index) => {
+                self.expr(base);
+                self.expr(index);
+            }
+            ExprKind::Instantiation(base, args) => {
+                self.expr(base);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Slice(base, low, high, max) => {
+                self.expr(base);
+                for bound in [low, high, max].into_iter().flatten() {
+                    self.expr(bound);
+                }
+            }
+            ExprKind::TypeAssert(base, ty) => {
+                self.expr(base);
+                if let Some(ty) = ty {
+                    self.ty(ty);
+                }
+            }
+            ExprKind::Call(callee, args, _) => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Unary(_, operand) => self.expr(operand),
+            ExprKind::Binary(_, lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::Type(ty) => self.ty(ty),
+        }
+    }
+
+    fn elements(&mut self, elements: &[KeyedElement]) {
+        for element in elements {
+            match element.key.as_ref().map(|key| &key.kind) {
+                // a bare identifier key is a struct field name unless it's in scope (e.g. a map key). Whether
+                // it's undefined depends on the literal's type, so that's left to the type checker.
+                Some(ExprKind::Ident(name)) if self.lookup(name).is_none() => {}
+                Some(ExprKind::CompositeLit(None, nested)) => self.elements(nested),
+                Some(_) => self.expr(element.key.as_ref().unwrap()),
+                None => {}
+            }
+            match &element.value.kind {
+                ExprKind::CompositeLit(None, nested) => self.elements(nested),
+                _ => self.expr(&element.value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod sema_test {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use super::{ScopeKind, SemaError, SymbolKind, SymbolTable};
+
+    fn analyze(file_name: &str) -> Result<SymbolTable, Vec<SemaError>> {
+        let program = Parser::new(Lexer::new(file_name.to_string()).unwrap()).parse().unwrap();
+        super::analyze(&program)
+    }
+
+    /// The line of the declaration used by the identifier at `line`:`column`.
+    fn declaration_line(table: &SymbolTable, line: u32, column: u32) -> Option<u32> {
+        let (_, &id) = table.uses.iter().find(|(span, _)| span.start.line == line && span.start.column == column)?;
+        table.symbol(id).span.map(|span| span.start.line)
+    }
+
+    #[test]
+    pub fn given_valid_program_should_resolve_every_identifier() {
+        let table = analyze("testfiles/scopes.test").unwrap();
+        // `limit` is used before it's declared at package level.
+        assert_eq!(declaration_line(&table, 8, 20), Some(10));
+        // `x + 1` in the if header refers to the outer x, `x > 1` to the one it declares.
+        assert_eq!(declaration_line(&table, 22, 13), Some(21));
+        assert_eq!(declaration_line(&table, 22, 20), Some(22));
+        // `x, y := 2, 3` assigns to the existing x.
+        assert_eq!(declaration_line(&table, 25, 5), Some(21));
+        // the type switch binding, and a predeclared type.
+        assert_eq!(declaration_line(&table, 32, 18), Some(30));
+        assert_eq!(declaration_line(&table, 31, 10), None);
+
+        let str_import = table.symbols.iter().find(|symbol| symbol.name == "str").unwrap();
+        assert_eq!(str_import.kind, SymbolKind::Package("strings".to_string()));
+        assert_eq!(str_import.scope, ScopeKind::File);
+        assert!(str_import.used);
+    }
+
+    #[test]
+    pub fn given_scope_errors_should_report_all_of_them() {
+        let errors = analyze("testfiles/illegal_scopes.test").unwrap_err();
+        let messages = errors.iter().map(|error| error.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "ERROR: \"os\" imported and not used on line 4",
+                "ERROR: count redeclared in this block (previous declaration on line 8) on line 9",
+                "ERROR: declared and not used: a on line 12",
+                "ERROR: undefined: missing on line 13",
+                "ERROR: no new variables on left side of := on line 14",
+                "ERROR: declared and not used: c on line 15",
+                "ERROR: declared and not used: d on line 18",
+            ]
+        );
+    }
+}
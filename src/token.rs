@@ -1,13 +1,85 @@
-#[derive(PartialEq)]
+use std::fmt;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+
+/// A point in a source file.
+///
+/// Lines and columns are 1-based, offset is the 0-based byte offset into the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+    pub offset: usize,
+}
+
+impl Position {
+    pub fn new(line: u32, column: u32, offset: usize) -> Position {
+        Position { line, column, offset }
+    }
+}
+
+/// The region of a source file covered by a token.
+///
+/// `end` is exclusive, i.e. it points just past the last character of the token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file_id: usize,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// The span starting at the start of `self` and ending at the end of `other`.
+    pub fn to(&self, other: Span) -> Span {
+        Span {
+            file_id: self.file_id,
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}-{}:{}", self.start.line, self.start.column, self.end.line, self.end.column)
+    }
+}
+
+/// A token produced by the lexer, along with where it was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+    pub implicit: bool, // true if inserted by the lexer rather than written in the source (automatic semicolons)
+    pub leading: Vec<Trivia>,  // comments between the previous token's line and this token
+    pub trailing: Vec<Trivia>, // comments after this token on the same line
+}
+
+/// A comment, kept alongside the token next to it instead of being returned as a token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String, // the full comment, including the `//` or `/* */`
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    LineComment,  // `// ...`, up to but not including the newline
+    BlockComment, // `/* ... */`
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     // Identifiers
     Identifier(String),
 
     // Literals
-    IntLiteral(i32),
-    FloatLiteral(f32),
-    CharLiteral(String), // make it a string so it prints a bit nicer.
-    StringLiteral(String),
+    IntLiteral(String, BigInt), // the literal as written, along with its exact value
+    FloatLiteral(String, BigRational),
+    CharLiteral(String, char), // the literal as written, quotes included, along with its value
+    StringLiteral(String, Vec<u8>), // escapes like \x can produce bytes that aren't valid UTF-8
+    RawStringLiteral(String), // a backtick string, with carriage returns already removed
 
     // Braces
     // paren = ( ), brace = { }, bracket = [ ]
@@ -93,20 +165,44 @@ pub enum TokenKind {
     ReturnKeyword,
     VarKeyword,
 
-    Comment,
     // End of file.
     EOF,
 }
 
-impl ToString for TokenKind {
-    fn to_string(&self) -> String {
+impl TokenKind {
+    /// Whether a semicolon should be inserted if this is the final token on a line.
+    pub fn triggers_semicolon(&self) -> bool {
+        matches!(
+            self,
+            Self::Identifier(_)
+                | Self::IntLiteral(..)
+                | Self::FloatLiteral(..)
+                | Self::CharLiteral(..)
+                | Self::StringLiteral(..)
+                | Self::RawStringLiteral(_)
+                | Self::BreakKeyword
+                | Self::ContinueKeyword
+                | Self::FallThroughKeyword
+                | Self::ReturnKeyword
+                | Self::IncrementOp
+                | Self::DecrementOp
+                | Self::RightParen
+                | Self::RightBracket
+                | Self::RightBrace
+        )
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::Identifier(name) => format!("Identifier {}", name.clone()),
-            Self::IntLiteral(number) => format!("Int {}", number),
-            Self::FloatLiteral(number) => format!("Float {}", number),
-            Self::CharLiteral(character) => format!("Char {}", character.to_string()),
-            Self::StringLiteral(str) => format!("String \"{}\"",str.clone()),
-            _ => match self {
+            Self::Identifier(name) => write!(f, "Identifier {}", name),
+            Self::IntLiteral(text, _) => write!(f, "Int {}", text),
+            Self::FloatLiteral(text, _) => write!(f, "Float {}", text),
+            Self::CharLiteral(raw, _) => write!(f, "Char {}", raw),
+            Self::StringLiteral(raw, _) => write!(f, "String {}", raw),
+            Self::RawStringLiteral(str) => write!(f, "RawString `{}`", str),
+            _ => f.write_str(match self {
                 Self::LeftParen => "(",
                 Self::RightParen => ")",
                 Self::LeftBrace => "{",
@@ -182,11 +278,9 @@ impl ToString for TokenKind {
                 Self::ImportKeyword => "import",
                 Self::ReturnKeyword => "return",
                 Self::VarKeyword => "var",
-                Self::Comment => "comment",
                 Self::EOF => "EOF",
                 _ => unreachable!(),
-            }
-            .to_string(),
+            }),
         }
     }
 }
\ No newline at end of file
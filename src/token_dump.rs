@@ -0,0 +1,173 @@
+use std::io::{self, Write};
+use num_traits::ToPrimitive;
+use crate::argparse::TokenFormat;
+use crate::lexer::{LexError, Lexer};
+use crate::token::{Position, Span, Token, TokenKind, Trivia};
+
+/// Lexes to EOF, writing one line per token to `out`.
+///
+/// Returns the lexical errors, in order, for the caller to report like any other diagnostics.
+pub fn dump_tokens(lexer: &mut Lexer, format: TokenFormat, out: &mut impl Write) -> io::Result<Vec<LexError>> {
+    let mut errors = vec![];
+    loop {
+        match lexer.lex() {
+            Ok(token) => {
+                match format {
+                    TokenFormat::Text => writeln!(out, "{}", token_text(&token))?,
+                    TokenFormat::Json => writeln!(out, "{}", token_json(&token))?,
+                }
+                if token.kind == TokenKind::EOF {
+                    return Ok(errors);
+                }
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+}
+
+fn token_text(token: &Token) -> String {
+    let mut text = format!("{}\t{}", token.span, token.kind);
+    if token.implicit {
+        text.push_str(" (implicit)");
+    }
+    text
+}
+
+fn token_json(token: &Token) -> String {
+    // the Debug output of a variant starts with its name, e.g. `Identifier("x")`.
+    let debug = format!("{:?}", token.kind);
+    let kind = debug.split('(').next().unwrap();
+    let value = match &token.kind {
+        TokenKind::Identifier(value) | TokenKind::RawStringLiteral(value) => json_string(value),
+        TokenKind::StringLiteral(_, value) => json_string(&String::from_utf8_lossy(value)),
+        TokenKind::CharLiteral(_, value) => (*value as u32).to_string(),
+        TokenKind::IntLiteral(_, value) => value.to_string(),
+        // JSON has no infinity, the literal is still in "text".
+        TokenKind::FloatLiteral(_, value) => match value.to_f64() {
+            Some(value) if value.is_finite() => value.to_string(),
+            _ => "null".to_string(),
+        },
+        _ => "null".to_string(),
+    };
+    format!(
+        "{{\"kind\":{},\"value\":{},\"text\":{},\"implicit\":{},\"leading\":{},\"trailing\":{},{}}}",
+        json_string(kind),
+        value,
+        json_string(&token.kind.to_string()),
+        token.implicit,
+        trivia_json(&token.leading),
+        trivia_json(&token.trailing),
+        span_json(token.span)
+    )
+}
+
+fn trivia_json(trivia: &[Trivia]) -> String {
+    let comments = trivia
+            .iter()
+            .map(|comment| {
+                format!(
+                    "{{\"kind\":\"{:?}\",\"text\":{},{}}}",
+                    comment.kind,
+                    json_string(&comment.text),
+                    span_json(comment.span)
+                )
+            })
+            .collect::<Vec<_>>();
+    format!("[{}]", comments.join(","))
+}
+
+fn span_json(span: Span) -> String {
+    format!(
+        "\"file_id\":{},\"start\":{},\"end\":{}",
+        span.file_id,
+        position_json(span.start),
+        position_json(span.end)
+    )
+}
+
+fn position_json(position: Position) -> String {
+    format!(
+        "{{\"line\":{},\"column\":{},\"offset\":{}}}",
+        position.line, position.column, position.offset
+    )
+}
+
+/// Quotes and escapes `text` as a JSON string.
+pub fn json_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+pub mod token_dump_test {
+    use crate::argparse::TokenFormat;
+    use crate::lexer::Lexer;
+
+    fn dump(file_name: &str, format: TokenFormat) -> (Vec<String>, Vec<String>) {
+        let mut lexer = Lexer::new(file_name.to_string()).unwrap();
+        let mut out = vec![];
+        let errors = super::dump_tokens(&mut lexer, format, &mut out).unwrap();
+        let lines = String::from_utf8(out).unwrap().lines().map(str::to_string).collect();
+        (lines, errors.iter().map(|error| error.to_string()).collect())
+    }
+
+    #[test]
+    pub fn given_text_format_should_print_one_token_per_line_with_position() {
+        let (lines, errors) = dump("testfiles/semicolons.test", TokenFormat::Text);
+        assert!(errors.is_empty());
+        assert_eq!(lines[0], "1:1-1:8\tpackage");
+        assert_eq!(lines[1], "1:9-1:13\tIdentifier main");
+        assert_eq!(lines[2], "1:13-2:1\t; (implicit)");
+        assert_eq!(lines.last().unwrap(), "9:16-9:16\tEOF");
+    }
+
+    #[test]
+    pub fn given_json_format_should_print_one_object_per_line() {
+        let (lines, errors) = dump("testfiles/strings_and_chars.test", TokenFormat::Json);
+        assert!(errors.is_empty());
+        assert_eq!(
+            lines[0],
+            "{\"kind\":\"StringLiteral\",\"value\":\"asdf\\n\\t\\\\\\\"'\\r\",\
+             \"text\":\"String \\\"asdf\\\\n\\\\t\\\\\\\\\\\\\\\"\\\\'\\\\r\\\"\",\"implicit\":false,\"leading\":[],\"trailing\":[],\
+             \"file_id\":0,\"start\":{\"line\":1,\"column\":1,\"offset\":0},\"end\":{\"line\":1,\"column\":19,\"offset\":18}}"
+        );
+        assert!(lines[1].starts_with("{\"kind\":\"Semicolon\",\"value\":null,\"text\":\";\",\"implicit\":true,"));
+    }
+
+    #[test]
+    pub fn given_float_too_large_for_f64_should_print_null_value() {
+        let (lines, _) = dump("testfiles/illegal_constants.test", TokenFormat::Json);
+        let line = lines.iter().find(|line| line.contains("\"text\":\"Float 1e400\"")).unwrap();
+        assert!(line.starts_with("{\"kind\":\"FloatLiteral\",\"value\":null,"));
+    }
+
+    #[test]
+    pub fn given_comments_should_print_them_with_their_tokens() {
+        let (lines, _) = dump("testfiles/semicolons.test", TokenFormat::Json);
+        assert!(lines[4].starts_with(
+            "{\"kind\":\"IncrementOp\",\"value\":null,\"text\":\"++\",\"implicit\":false,\"leading\":[],\
+             \"trailing\":[{\"kind\":\"LineComment\",\"text\":\"// comment\",\"file_id\":0,\
+             \"start\":{\"line\":2,\"column\":5,\"offset\":17},\"end\":{\"line\":2,\"column\":15,\"offset\":27}}],"
+        ));
+    }
+
+    #[test]
+    pub fn given_lexical_errors_should_return_them_in_order() {
+        let (lines, errors) = dump("testfiles/illegal_tokens.test", TokenFormat::Text);
+        assert_eq!(lines[0], "1:1-1:2\t/");
+        assert_eq!(errors[0], "ERROR: Unknown character '@' found on line 2");
+        assert_eq!(errors[1], "ERROR: Unknown character '#' found on line 3");
+    }
+}
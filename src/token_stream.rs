@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use crate::lexer::{LexError, Lexer};
+use crate::parser::ParseError;
+use crate::token::{Position, Span, Token, TokenKind};
+
+/// A saved position in a `TokenStream`, handed back to `rewind` or `release`.
+#[must_use]
+pub struct Checkpoint {
+    position: usize,
+    prev_span: Span,
+}
+
+/// Buffers the tokens of a lexer so the parser can look any number of tokens ahead and backtrack.
+///
+/// Lexical errors are collected as they're found, so the parser only sees tokens.
+pub struct TokenStream {
+    lexer: Lexer,
+    buffer: VecDeque<Token>, // buffer[position] is the current token
+    position: usize,         // only non-zero while a checkpoint needs the tokens before it
+    checkpoints: usize,      // checkpoints that haven't been rewound to or released yet
+    prev_span: Span,         // span of the last consumed token
+    errors: Vec<LexError>,
+}
+
+impl TokenStream {
+    pub fn new(lexer: Lexer) -> TokenStream {
+        let mut stream = TokenStream {
+            lexer,
+            buffer: VecDeque::new(),
+            position: 0,
+            checkpoints: 0,
+            prev_span: Span {
+                file_id: 0,
+                start: Position::new(1, 1, 0),
+                end: Position::new(1, 1, 0),
+            },
+            errors: vec![],
+        };
+        stream.fill(0);
+        stream.prev_span = stream.buffer[0].span;
+        stream
+    }
+
+    /// Lexes until the token `n` tokens after the current one is buffered.
+    fn fill(&mut self, n: usize) {
+        while self.buffer.len() <= self.position + n {
+            match self.lexer.lex() {
+                Ok(token) => self.buffer.push_back(token),
+                Err(error) => self.errors.push(error),
+            }
+        }
+    }
+
+    /// The current token, i.e. the one `next` returns.
+    pub fn current(&self) -> &Token {
+        &self.buffer[self.position]
+    }
+
+    /// Returns the token `n` tokens after the current one. Past the end of the file, every token is EOF.
+    pub fn peek(&mut self, n: usize) -> &Token {
+        self.fill(n);
+        &self.buffer[self.position + n]
+    }
+
+    /// Consumes the current token and returns it.
+    #[allow(clippy::should_implement_trait)] // the stream never ends, it keeps returning EOF
+    pub fn next(&mut self) -> Token {
+        let token = if self.checkpoints == 0 {
+            self.buffer.pop_front().unwrap()
+        } else {
+            self.position += 1;
+            self.buffer[self.position - 1].clone()
+        };
+        self.prev_span = token.span;
+        self.fill(0);
+        token
+    }
+
+    /// Consumes the current token if it's of the given kind, or reports what was found instead.
+    pub fn expect(&mut self, kind: &TokenKind) -> Result<Token, ParseError> {
+        if self.current().kind == *kind {
+            Ok(self.next())
+        } else {
+            Err(self.unexpected(&kind.to_string()))
+        }
+    }
+
+    /// An error saying that `expected` was wanted where the current token is.
+    pub fn unexpected(&self, expected: &str) -> ParseError {
+        let current = self.current();
+        let found = if current.implicit {
+            "newline".to_string()
+        } else {
+            current.kind.to_string()
+        };
+        ParseError::UnexpectedToken {
+            expected: expected.to_string(),
+            found,
+            span: current.span,
+        }
+    }
+
+    /// The span of the last consumed token.
+    pub fn prev_span(&self) -> Span {
+        self.prev_span
+    }
+
+    /// Saves the current position, so the tokens read after it can be read again with `rewind`.
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        self.checkpoints += 1;
+        Checkpoint {
+            position: self.position,
+            prev_span: self.prev_span,
+        }
+    }
+
+    /// Goes back to the position saved in `checkpoint`.
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        self.position = checkpoint.position;
+        self.prev_span = checkpoint.prev_span;
+        self.release(checkpoint);
+    }
+
+    /// Keeps the current position, letting go of the tokens held for `checkpoint`.
+    pub fn release(&mut self, _checkpoint: Checkpoint) {
+        self.checkpoints -= 1;
+        if self.checkpoints == 0 {
+            self.buffer.drain(..self.position);
+            self.position = 0;
+        }
+    }
+
+    /// Takes the lexical errors found so far.
+    pub fn take_errors(&mut self) -> Vec<LexError> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+#[cfg(test)]
+pub mod token_stream_test {
+    use super::TokenStream;
+    use crate::lexer::{LexError, Lexer};
+    use crate::parser::ParseError;
+    use crate::token::TokenKind;
+
+    fn ident(name: &str) -> TokenKind {
+        TokenKind::Identifier(name.to_string())
+    }
+
+    #[test]
+    pub fn given_tokens_should_peek_without_consuming() {
+        let mut stream = TokenStream::new(Lexer::from_source("a /* skipped */ b c"));
+        assert_eq!(stream.peek(2).kind, ident("c"));
+        assert_eq!(stream.peek(1).kind, ident("b"));
+        assert_eq!(stream.current().kind, ident("a"));
+        assert_eq!(stream.next().kind, ident("a"));
+        let b = stream.next();
+        assert_eq!(b.kind, ident("b"));
+        assert_eq!(stream.prev_span(), b.span);
+        assert_eq!(stream.peek(5).kind, TokenKind::EOF);
+    }
+
+    #[test]
+    pub fn given_rewind_should_replay_tokens_from_checkpoint() {
+        let mut stream = TokenStream::new(Lexer::from_source("a b c d"));
+        stream.next();
+        let outer = stream.checkpoint();
+        stream.next();
+        let inner = stream.checkpoint();
+        stream.next();
+        stream.release(inner);
+        assert_eq!(stream.current().kind, ident("d"));
+        stream.rewind(outer);
+        assert_eq!(stream.prev_span().start.column, 1);
+        assert_eq!(stream.next().kind, ident("b"));
+        assert_eq!(stream.next().kind, ident("c"));
+        assert_eq!(stream.buffer.len(), 1);
+    }
+
+    #[test]
+    pub fn given_unexpected_token_should_report_expected_and_found() {
+        let mut stream = TokenStream::new(Lexer::from_source("a @\n"));
+        assert_eq!(stream.expect(&ident("a")).unwrap().kind, ident("a"));
+        match stream.expect(&TokenKind::Comma) {
+            Err(ParseError::UnexpectedToken { expected, found, .. }) => {
+                assert_eq!(expected, ",");
+                assert_eq!(found, "newline");
+            }
+            result => panic!("expected an error, got {:?}", result),
+        }
+        assert!(matches!(stream.take_errors()[..], [LexError::UnknownCharacter('@', _)]));
+    }
+}
@@ -0,0 +1,3417 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use crate::ast::{self, *};
+use crate::constant::{self, Inexact, Value};
+use crate::diagnostics::{Diagnostic, ToDiagnostic};
+use crate::sema::{SymbolId, SymbolKind, SymbolTable};
+use crate::token::Span;
+
+/// Computes the type of every expression in `program` and checks that they are used correctly.
+///
+/// `table` is the result of `sema::analyze` on the same program. All type errors are reported, sorted by position.
+pub fn check(program: &Program, table: &SymbolTable) -> Result<TypeInfo, Vec<TypeError>> {
+    let mut checker = Checker::new(table);
+    checker.program(program);
+    if checker.errors.is_empty() {
+        Ok(checker.info)
+    } else {
+        checker.errors.sort_by_key(|error| error.span.start.offset);
+        Err(checker.errors)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ERROR: {} on line {}", self.message, self.span.start.line)
+    }
+}
+
+impl ToDiagnostic for TypeError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error("E0501", self.message.clone(), self.span)
+    }
+}
+
+/// The results of type checking.
+#[derive(Debug, Clone, Default)]
+pub struct TypeInfo {
+    pub types: HashMap<Span, Ty>,        // the type of every expression, keyed by its span
+    pub symbols: HashMap<SymbolId, Ty>, // the type of every variable, constant and function
+    pub constants: HashMap<Span, Value>, // the value of every constant expression, after conversion to its type
+    pub init_order: Vec<SymbolId>,      // package level variables with initializers, in the order they're initialized
+}
+
+/// A GoLite type, following the spec's type grammar.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ty {
+    Invalid, // the type of an erroneous expression, which is compatible with everything to avoid follow-on errors
+    Basic(BasicKind),
+    Untyped(UntypedKind), // untyped constants and nil
+    Named { id: SymbolId, name: String, args: Vec<Ty> },
+    TypeParam { id: SymbolId, name: String },
+    Array(u64, Box<Ty>),
+    Slice(Box<Ty>),
+    Struct(Vec<Field>),
+    Pointer(Box<Ty>),
+    Func(FuncTy),
+    Interface(InterfaceTy),
+    Map(Box<Ty>, Box<Ty>),
+    Chan(ChanDir, Box<Ty>),
+    Tuple(Vec<Ty>), // the results of a call that doesn't return exactly one value
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BasicKind {
+    Bool,
+    Int,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Uint,
+    Uint8,
+    Uint16,
+    Uint32,
+    Uint64,
+    Uintptr,
+    Float32,
+    Float64,
+    Complex64,
+    Complex128,
+    String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UntypedKind {
+    Bool,
+    Int,
+    Rune,
+    Float,
+    String,
+    Nil,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub ty: Ty,
+    pub embedded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuncTy {
+    pub params: Vec<Ty>,
+    pub results: Vec<Ty>,
+    pub variadic: bool, // the last param is a slice of the variadic type
+}
+
+/// An interface type. Embedded types are kept as written and flattened when the method or type set is needed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InterfaceTy {
+    pub methods: Vec<(String, FuncTy)>,
+    pub embedded: Vec<Ty>,
+    pub terms: Option<Vec<Term>>, // a union restricting the type set, None if any type is allowed
+    pub comparable: bool,
+}
+
+/// A union term, `T` or `~T`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub tilde: bool,
+    pub ty: Ty,
+}
+
+impl BasicKind {
+    pub(crate) fn from_name(name: &str) -> Option<BasicKind> {
+        Some(match name {
+            "bool" => Self::Bool,
+            "int" => Self::Int,
+            "int8" => Self::Int8,
+            "int16" => Self::Int16,
+            "int32" | "rune" => Self::Int32,
+            "int64" => Self::Int64,
+            "uint" => Self::Uint,
+            "uint8" | "byte" => Self::Uint8,
+            "uint16" => Self::Uint16,
+            "uint32" => Self::Uint32,
+            "uint64" => Self::Uint64,
+            "uintptr" => Self::Uintptr,
+            "float32" => Self::Float32,
+            "float64" => Self::Float64,
+            "complex64" => Self::Complex64,
+            "complex128" => Self::Complex128,
+            "string" => Self::String,
+            _ => return None,
+        })
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Self::Int
+                | Self::Int8
+                | Self::Int16
+                | Self::Int32
+                | Self::Int64
+                | Self::Uint
+                | Self::Uint8
+                | Self::Uint16
+                | Self::Uint32
+                | Self::Uint64
+                | Self::Uintptr
+        )
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::Float32 | Self::Float64)
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Self::Complex64 | Self::Complex128)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float() || self.is_complex()
+    }
+}
+
+impl fmt::Display for BasicKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = format!("{:?}", self).to_lowercase();
+        f.write_str(&name)
+    }
+}
+
+impl fmt::Display for UntypedKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Bool => "untyped bool",
+            Self::Int => "untyped int",
+            Self::Rune => "untyped rune",
+            Self::Float => "untyped float",
+            Self::String => "untyped string",
+            Self::Nil => "untyped nil",
+        })
+    }
+}
+
+fn join(types: &[Ty]) -> String {
+    types.iter().map(Ty::to_string).collect::<Vec<_>>().join(", ")
+}
+
+impl FuncTy {
+    /// The parameter list as written in a signature, e.g. `(int, ...string)`.
+    pub fn params_string(&self) -> String {
+        let mut params = self.params.iter().map(Ty::to_string).collect::<Vec<_>>();
+        if self.variadic {
+            if let Some(Ty::Slice(elem)) = self.params.last() {
+                *params.last_mut().unwrap() = format!("...{}", elem);
+            }
+        }
+        format!("({})", params.join(", "))
+    }
+}
+
+impl fmt::Display for FuncTy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "func{}", self.params_string())?;
+        match self.results.as_slice() {
+            [] => Ok(()),
+            [result] => write!(f, " {}", result),
+            results => write!(f, " ({})", join(results)),
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Invalid => f.write_str("invalid type"),
+            Self::Basic(kind) => write!(f, "{}", kind),
+            Self::Untyped(kind) => write!(f, "{}", kind),
+            Self::Named { name, args, .. } if args.is_empty() => f.write_str(name),
+            Self::Named { name, args, .. } => write!(f, "{}[{}]", name, join(args)),
+            Self::TypeParam { name, .. } => f.write_str(name),
+            Self::Array(len, elem) => write!(f, "[{}]{}", len, elem),
+            Self::Slice(elem) => write!(f, "[]{}", elem),
+            Self::Struct(fields) => {
+                let fields = fields
+                        .iter()
+                        .map(|field| if field.embedded { field.ty.to_string() } else { format!("{} {}", field.name, field.ty) })
+                        .collect::<Vec<_>>();
+                write!(f, "struct{{{}}}", fields.join("; "))
+            }
+            Self::Pointer(elem) => write!(f, "*{}", elem),
+            Self::Func(func) => write!(f, "{}", func),
+            Self::Interface(iface) => {
+                let mut elems = iface.embedded.iter().map(Ty::to_string).collect::<Vec<_>>();
+                elems.extend(iface.methods.iter().map(|(name, func)| format!("{}{}", name, &func.to_string()[4..])));
+                if let Some(terms) = &iface.terms {
+                    let terms = terms
+                            .iter()
+                            .map(|term| format!("{}{}", if term.tilde { "~" } else { "" }, term.ty))
+                            .collect::<Vec<_>>();
+                    elems.push(terms.join(" | "));
+                }
+                if iface.comparable {
+                    return f.write_str("comparable");
+                }
+                write!(f, "interface{{{}}}", elems.join("; "))
+            }
+            Self::Map(key, value) => write!(f, "map[{}]{}", key, value),
+            Self::Chan(ChanDir::Both, elem) => write!(f, "chan {}", elem),
+            Self::Chan(ChanDir::Send, elem) => write!(f, "chan<- {}", elem),
+            Self::Chan(ChanDir::Recv, elem) => write!(f, "<-chan {}", elem),
+            Self::Tuple(types) => write!(f, "({})", join(types)),
+        }
+    }
+}
+
+impl Ty {
+    fn is_untyped(&self) -> bool {
+        matches!(self, Self::Untyped(_))
+    }
+
+    /// Whether the type has a name. Predeclared types like int count as named.
+    fn is_named(&self) -> bool {
+        matches!(self, Self::Basic(_) | Self::Named { .. } | Self::TypeParam { .. })
+    }
+
+    /// The type an untyped constant gets when there's nothing else to decide it.
+    pub fn default_type(&self) -> Ty {
+        match self {
+            Self::Untyped(UntypedKind::Bool) => Self::Basic(BasicKind::Bool),
+            Self::Untyped(UntypedKind::Int) => Self::Basic(BasicKind::Int),
+            Self::Untyped(UntypedKind::Rune) => Self::Basic(BasicKind::Int32),
+            Self::Untyped(UntypedKind::Float) => Self::Basic(BasicKind::Float64),
+            Self::Untyped(UntypedKind::String) => Self::Basic(BasicKind::String),
+            _ => self.clone(),
+        }
+    }
+}
+
+fn bool_ty() -> Ty {
+    Ty::Basic(BasicKind::Bool)
+}
+
+fn int_ty() -> Ty {
+    Ty::Basic(BasicKind::Int)
+}
+
+fn void() -> Ty {
+    Ty::Tuple(vec![])
+}
+
+fn empty_interface() -> Ty {
+    Ty::Interface(InterfaceTy::default())
+}
+
+/// Replaces the type parameters in `ty` with their bound types.
+fn subst(ty: &Ty, bindings: &HashMap<SymbolId, Ty>) -> Ty {
+    let each = |types: &[Ty]| types.iter().map(|ty| subst(ty, bindings)).collect::<Vec<_>>();
+    let boxed = |ty: &Ty| Box::new(subst(ty, bindings));
+    match ty {
+        Ty::TypeParam { id, .. } => bindings.get(id).cloned().unwrap_or_else(|| ty.clone()),
+        Ty::Named { id, name, args } => Ty::Named {
+            id: *id,
+            name: name.clone(),
+            args: each(args),
+        },
+        Ty::Array(len, elem) => Ty::Array(*len, boxed(elem)),
+        Ty::Slice(elem) => Ty::Slice(boxed(elem)),
+        Ty::Pointer(elem) => Ty::Pointer(boxed(elem)),
+        Ty::Struct(fields) => Ty::Struct(
+            fields
+                    .iter()
+                    .map(|field| Field {
+                        ty: subst(&field.ty, bindings),
+                        ..field.clone()
+                    })
+                    .collect(),
+        ),
+        Ty::Func(func) => Ty::Func(subst_func(func, bindings)),
+        Ty::Interface(iface) => Ty::Interface(InterfaceTy {
+            methods: iface.methods.iter().map(|(name, func)| (name.clone(), subst_func(func, bindings))).collect(),
+            embedded: each(&iface.embedded),
+            terms: iface.terms.as_ref().map(|terms| {
+                terms
+                        .iter()
+                        .map(|term| Term {
+                            tilde: term.tilde,
+                            ty: subst(&term.ty, bindings),
+                        })
+                        .collect()
+            }),
+            comparable: iface.comparable,
+        }),
+        Ty::Map(key, value) => Ty::Map(boxed(key), boxed(value)),
+        Ty::Chan(dir, elem) => Ty::Chan(*dir, boxed(elem)),
+        Ty::Tuple(types) => Ty::Tuple(each(types)),
+        Ty::Invalid | Ty::Basic(_) | Ty::Untyped(_) => ty.clone(),
+    }
+}
+
+fn subst_func(func: &FuncTy, bindings: &HashMap<SymbolId, Ty>) -> FuncTy {
+    FuncTy {
+        params: func.params.iter().map(|ty| subst(ty, bindings)).collect(),
+        results: func.results.iter().map(|ty| subst(ty, bindings)).collect(),
+        variadic: func.variadic,
+    }
+}
+
+/// A declared (non-alias) type.
+struct NamedDecl {
+    type_params: Vec<SymbolId>,
+    underlying: Ty,
+    methods: Vec<Method>,
+}
+
+struct Method {
+    name: String,
+    ty: FuncTy,
+    pointer_receiver: bool,
+    receiver_params: Vec<SymbolId>, // the type parameters named in the receiver, e.g. T in `func (l *List[T])`
+}
+
+/// What a selector like `x.f` selects.
+enum Member {
+    Field(Ty),
+    Method(FuncTy, bool), // true for pointer receivers
+}
+
+/// A package level constant or variable, checked the first time it's needed.
+#[derive(Clone, Copy)]
+enum Global<'a> {
+    Const { ty: Option<&'a ast::Type>, value: Option<&'a Expr>, iota: i64 },
+    Var(&'a VarSpec),
+}
+
+struct FuncContext {
+    results: Vec<Ty>,
+    named_results: bool,
+}
+
+const NOT_USED_BUILTINS: [&str; 10] = ["append", "cap", "complex", "imag", "len", "make", "max", "min", "new", "real"];
+
+struct Checker<'a> {
+    table: &'a SymbolTable,
+    info: TypeInfo,
+    errors: Vec<TypeError>,
+    named: HashMap<SymbolId, NamedDecl>,
+    aliases: HashMap<SymbolId, ast::Type>,
+    constraints: HashMap<SymbolId, Ty>, // the constraint of every type parameter
+    generic_funcs: HashMap<SymbolId, Vec<SymbolId>>,
+    globals: HashMap<SymbolId, Global<'a>>,
+    resolving: HashSet<SymbolId>, // aliases and globals being resolved, to catch cycles
+    const_values: HashMap<SymbolId, Value>,
+    iota: Option<i64>,
+    funcs: Vec<FuncContext>,
+    references: Vec<(Span, SymbolId)>, // every use of a variable or function, for working out the init order
+}
+
+impl<'a> Checker<'a> {
+    fn new(table: &'a SymbolTable) -> Checker<'a> {
+        let mut checker = Checker {
+            table,
+            info: TypeInfo::default(),
+            errors: vec![],
+            named: HashMap::new(),
+            aliases: HashMap::new(),
+            constraints: HashMap::new(),
+            generic_funcs: HashMap::new(),
+            globals: HashMap::new(),
+            resolving: HashSet::new(),
+            const_values: HashMap::new(),
+            iota: None,
+            funcs: vec![],
+            references: vec![],
+        };
+        // error is the only predeclared type with methods.
+        if let Some(id) = table.symbols.iter().position(|symbol| symbol.name == "error" && symbol.span.is_none()) {
+            let error_method = FuncTy {
+                params: vec![],
+                results: vec![Ty::Basic(BasicKind::String)],
+                variadic: false,
+            };
+            checker.named.insert(
+                id,
+                NamedDecl {
+                    type_params: vec![],
+                    underlying: Ty::Interface(InterfaceTy {
+                        methods: vec![("Error".to_string(), error_method)],
+                        ..InterfaceTy::default()
+                    }),
+                    methods: vec![],
+                },
+            );
+        }
+        checker
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: Span) {
+        self.errors.push(TypeError {
+            message: message.into(),
+            span,
+        });
+    }
+
+    fn record(&mut self, expr: &Expr, ty: &Ty) {
+        self.info.types.insert(expr.span, ty.clone());
+    }
+
+    fn symbol_id(&self, span: Span) -> Option<SymbolId> {
+        self.table.lookup(span)
+    }
+
+    fn set_symbol_type(&mut self, ident: &Ident, ty: Ty) {
+        if let Some(id) = self.symbol_id(ident.span) {
+            self.info.symbols.insert(id, ty);
+        }
+    }
+
+    // ---- declarations ----
+
+    fn program(&mut self, program: &'a Program) {
+        let mut type_specs = vec![];
+        for decl in &program.decls {
+            match decl {
+                TopLevelDecl::Decl(Decl::Type(specs, _)) => type_specs.extend(specs),
+                TopLevelDecl::Decl(Decl::Const(specs, _)) => {
+                    for (spec, ty, values, iota) in const_group(specs) {
+                        for (i, name) in spec.names.iter().enumerate() {
+                            if let Some(id) = self.symbol_id(name.span) {
+                                self.globals.insert(id, Global::Const { ty, value: values.get(i), iota });
+                            }
+                        }
+                    }
+                }
+                TopLevelDecl::Decl(Decl::Var(specs, _)) => {
+                    for spec in specs {
+                        for name in &spec.names {
+                            if let Some(id) = self.symbol_id(name.span) {
+                                self.globals.insert(id, Global::Var(spec));
+                            }
+                        }
+                    }
+                }
+                TopLevelDecl::Func(_) | TopLevelDecl::Bad(_) => {}
+            }
+        }
+
+        // types are declared before any are resolved, so they can refer to each other in any order.
+        for spec in &type_specs {
+            self.declare_type(spec);
+        }
+        for spec in &type_specs {
+            self.define_type(spec);
+        }
+        for decl in &program.decls {
+            if let TopLevelDecl::Func(func) = decl {
+                self.declare_func(func);
+            }
+        }
+        for decl in &program.decls {
+            match decl {
+                TopLevelDecl::Decl(Decl::Const(specs, _)) => {
+                    for name in specs.iter().flat_map(|spec| &spec.names) {
+                        self.check_global(name);
+                    }
+                }
+                TopLevelDecl::Decl(Decl::Var(specs, _)) => {
+                    for name in specs.iter().flat_map(|spec| &spec.names) {
+                        self.check_global(name);
+                    }
+                }
+                _ => {}
+            }
+        }
+        for decl in &program.decls {
+            if let TopLevelDecl::Func(func) = decl {
+                self.func_body(func);
+            }
+        }
+        self.init_order(program);
+    }
+
+    /// Works out the order package level variables are initialized in, like Go: repeatedly the earliest declared
+    /// variable that doesn't depend on one still uninitialized. A variable depends on the variables its initializer
+    /// refers to, directly or through the functions it refers to.
+    fn init_order(&mut self, program: &Program) {
+        let mut vars = vec![];
+        let mut bodies = HashMap::new();
+        for decl in &program.decls {
+            match decl {
+                TopLevelDecl::Decl(Decl::Var(specs, _)) => {
+                    for spec in specs {
+                        for (i, name) in spec.names.iter().enumerate() {
+                            // a multi-valued initializer initializes all the variables at once.
+                            let value = if spec.values.len() == spec.names.len() { spec.values.get(i) } else { spec.values.first() };
+                            if let (Some(id), Some(value)) = (self.symbol_id(name.span), value) {
+                                vars.push((id, value.span));
+                            }
+                        }
+                    }
+                }
+                TopLevelDecl::Func(func) if func.receiver.is_none() => {
+                    if let (Some(id), Some(body)) = (self.symbol_id(func.name.span), &func.body) {
+                        bodies.insert(id, body.span);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let dependencies = vars
+                .iter()
+                .map(|(id, span)| (*id, self.dependencies(*span, &bodies)))
+                .collect::<HashMap<_, _>>();
+        let mut remaining = vars.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        while !remaining.is_empty() {
+            // with an initialization cycle, which has already been reported, nothing is ready.
+            let ready = remaining
+                    .iter()
+                    .position(|id| dependencies[id].iter().all(|dependency| dependency == id || !remaining.contains(dependency)))
+                    .unwrap_or(0);
+            self.info.init_order.push(remaining.remove(ready));
+        }
+    }
+
+    /// The package level variables referred to in `span`, directly or through the functions referred to there.
+    fn dependencies(&self, span: Span, bodies: &HashMap<SymbolId, Span>) -> HashSet<SymbolId> {
+        let mut variables = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut spans = vec![span];
+        while let Some(span) = spans.pop() {
+            let inside = |use_span: &Span| {
+                use_span.file_id == span.file_id
+                        && span.start.offset <= use_span.start.offset
+                        && use_span.end.offset <= span.end.offset
+            };
+            for (_, id) in self.references.iter().filter(|(use_span, _)| inside(use_span)) {
+                match bodies.get(id) {
+                    Some(body) if visited.insert(*id) => spans.push(*body),
+                    Some(_) => {}
+                    None if matches!(self.globals.get(id), Some(Global::Var(_))) => {
+                        variables.insert(*id);
+                    }
+                    None => {}
+                }
+            }
+        }
+        variables
+    }
+
+    fn declare_type(&mut self, spec: &TypeSpec) {
+        let id = match self.symbol_id(spec.name.span) {
+            Some(id) => id,
+            None => return,
+        };
+        if spec.alias {
+            self.aliases.insert(id, spec.ty.clone());
+            return;
+        }
+        let type_params = self.declare_type_params(&spec.type_params);
+        self.named.insert(
+            id,
+            NamedDecl {
+                type_params,
+                underlying: Ty::Invalid,
+                methods: vec![],
+            },
+        );
+    }
+
+    fn define_type(&mut self, spec: &TypeSpec) {
+        if spec.alias {
+            return;
+        }
+        if let Some(id) = self.symbol_id(spec.name.span) {
+            self.define_type_params(&spec.type_params);
+            let underlying = self.resolve_type(&spec.ty);
+            self.named.get_mut(&id).unwrap().underlying = underlying;
+        }
+    }
+
+    /// Registers type parameters so they resolve to `Ty::TypeParam`. Their constraints are resolved later.
+    fn declare_type_params(&mut self, params: &[TypeParam]) -> Vec<SymbolId> {
+        let mut ids = vec![];
+        for name in params.iter().flat_map(|param| &param.names) {
+            if let Some(id) = self.symbol_id(name.span) {
+                self.constraints.insert(id, empty_interface());
+                ids.push(id);
+            }
+        }
+        ids
+    }
+
+    fn define_type_params(&mut self, params: &[TypeParam]) {
+        for param in params {
+            let constraint = self.constraint(&param.constraint);
+            for name in &param.names {
+                if let Some(id) = self.symbol_id(name.span) {
+                    self.constraints.insert(id, constraint.clone());
+                }
+            }
+        }
+    }
+
+    fn constraint(&mut self, terms: &[TypeTerm]) -> Ty {
+        match terms {
+            [term] if !term.underlying => self.resolve_type(&term.ty),
+            _ => {
+                let terms = terms
+                        .iter()
+                        .map(|term| Term {
+                            tilde: term.underlying,
+                            ty: self.resolve_type(&term.ty),
+                        })
+                        .collect();
+                Ty::Interface(InterfaceTy {
+                    terms: Some(terms),
+                    ..InterfaceTy::default()
+                })
+            }
+        }
+    }
+
+    fn declare_func(&mut self, func: &'a FuncDecl) {
+        let type_params = self.declare_type_params(&func.type_params);
+        self.define_type_params(&func.type_params);
+        let mut receiver_info = None;
+        if let Some(receiver) = &func.receiver {
+            receiver_info = self.receiver(receiver);
+        }
+        let ty = self.signature(&func.signature);
+        let id = match self.symbol_id(func.name.span) {
+            Some(id) => id,
+            None => return,
+        };
+        self.info.symbols.insert(id, Ty::Func(ty.clone()));
+        if !type_params.is_empty() {
+            self.generic_funcs.insert(id, type_params);
+        }
+        if let Some((named_id, pointer_receiver, receiver_params)) = receiver_info {
+            let decl = self.named.get_mut(&named_id).unwrap();
+            if decl.methods.iter().any(|method| method.name == func.name.name) {
+                self.error(format!("method {} already declared", func.name.name), func.name.span);
+                return;
+            }
+            decl.methods.push(Method {
+                name: func.name.name.clone(),
+                ty,
+                pointer_receiver,
+                receiver_params,
+            });
+        }
+    }
+
+    /// Resolves a method receiver, returning the receiver's base type, whether it's a pointer and its type parameters.
+    fn receiver(&mut self, receiver: &Param) -> Option<(SymbolId, bool, Vec<SymbolId>)> {
+        let (base, pointer) = match &receiver.ty.kind {
+            TypeKind::Pointer(inner) => (&**inner, true),
+            _ => (&receiver.ty, false),
+        };
+        let (name, args) = match &base.kind {
+            TypeKind::Name(name, args) => (name, args),
+            _ => {
+                self.error("invalid receiver type", receiver.ty.span);
+                return None;
+            }
+        };
+        let named_id = match self.symbol_id(name.span) {
+            Some(id) if self.named.contains_key(&id) && self.table.symbol(id).span.is_some() => id,
+            Some(_) => {
+                self.error(format!("cannot define new methods on non-local type {}", name.name), name.span);
+                return None;
+            }
+            None => return None,
+        };
+        let type_params = self.named[&named_id].type_params.clone();
+        if args.len() != type_params.len() {
+            self.error(
+                format!("receiver declares {} type parameters, but type {} has {}", args.len(), name.name, type_params.len()),
+                base.span,
+            );
+            return None;
+        }
+        // `func (l *List[T])` declares T, constrained like the type's own parameter.
+        let mut receiver_params = vec![];
+        for (arg, param) in args.iter().zip(type_params) {
+            if let TypeKind::Name(arg_name, _) = &arg.kind {
+                if let Some(id) = self.symbol_id(arg_name.span) {
+                    let constraint = self.constraints.get(&param).cloned().unwrap_or_else(empty_interface);
+                    self.constraints.insert(id, constraint);
+                    receiver_params.push(id);
+                }
+            }
+        }
+        let ty = self.resolve_type(&receiver.ty);
+        if let Some(name) = &receiver.name {
+            self.set_symbol_type(name, ty);
+        }
+        Some((named_id, pointer, receiver_params))
+    }
+
+    fn signature(&mut self, signature: &Signature) -> FuncTy {
+        let mut params = vec![];
+        let mut variadic = false;
+        for (i, param) in signature.params.iter().enumerate() {
+            let mut ty = self.resolve_type(&param.ty);
+            if param.variadic {
+                if i + 1 != signature.params.len() {
+                    self.error("can only use ... with final parameter in list", param.span);
+                }
+                ty = Ty::Slice(Box::new(ty));
+                variadic = true;
+            }
+            params.push(ty);
+        }
+        let results = signature.results.iter().map(|result| self.resolve_type(&result.ty)).collect();
+        FuncTy {
+            params,
+            results,
+            variadic,
+        }
+    }
+
+    fn check_global(&mut self, name: &Ident) {
+        if let Some(id) = self.symbol_id(name.span) {
+            self.global(id);
+        }
+    }
+
+    /// Checks a package level constant or variable if it hasn't been already.
+    fn global(&mut self, id: SymbolId) {
+        if self.info.symbols.contains_key(&id) {
+            return;
+        }
+        let global = match self.globals.get(&id) {
+            Some(global) => *global,
+            None => return,
+        };
+        if !self.resolving.insert(id) {
+            let symbol = self.table.symbol(id);
+            self.error(format!("initialization cycle or invalid recursive reference to {}", symbol.name), symbol.span.unwrap());
+            self.info.symbols.insert(id, Ty::Invalid);
+            return;
+        }
+        match global {
+            Global::Const { ty, value, iota } => {
+                let symbol = self.table.symbol(id);
+                let name = Ident {
+                    name: symbol.name.clone(),
+                    span: symbol.span.unwrap(),
+                };
+                self.const_decl(&name, ty, value, iota);
+            }
+            Global::Var(spec) => self.var_spec(&spec.names, spec.ty.as_ref(), &spec.values),
+        }
+        self.resolving.remove(&id);
+    }
+
+    fn const_decl(&mut self, name: &Ident, ty: Option<&ast::Type>, value: Option<&Expr>, iota: i64) {
+        let value = match value {
+            Some(value) => value,
+            None => {
+                self.error(format!("missing init expr for const declaration {}", name.name), name.span);
+                self.set_symbol_type(name, Ty::Invalid);
+                return;
+            }
+        };
+        let outer_iota = self.iota.replace(iota);
+        let errors = self.errors.len();
+        let mut const_ty = self.single(value);
+        if !matches!(const_ty, Ty::Invalid) && !self.is_constant(value) {
+            self.error(format!("{} is not constant", describe(value)), value.span);
+            const_ty = Ty::Invalid;
+        } else if let Some(ty) = ty {
+            let target = self.resolve_type(ty);
+            if !matches!(self.underlying(&target), Ty::Basic(_) | Ty::Invalid) {
+                self.error(format!("invalid constant type {}", target), ty.span);
+            }
+            self.assign_value(value, const_ty, &target, "constant declaration");
+            const_ty = target;
+        }
+        match (self.info.constants.get(&value.span), self.symbol_id(name.span)) {
+            (Some(value), Some(id)) => {
+                self.const_values.insert(id, value.clone());
+            }
+            // codegen needs every constant's value, so one that can't be worked out must be an error here.
+            (None, _) if self.errors.len() == errors => {
+                self.error(format!("cannot evaluate constant {}", describe(value)), value.span);
+                const_ty = Ty::Invalid;
+            }
+            _ => {}
+        }
+        self.iota = outer_iota;
+        self.set_symbol_type(name, const_ty);
+    }
+
+    fn var_spec(&mut self, names: &[Ident], ty: Option<&ast::Type>, values: &[Expr]) {
+        let target = ty.map(|ty| self.resolve_type(ty));
+        let types = self.values(names.len(), values, "variable declaration");
+        for (i, name) in names.iter().enumerate() {
+            let ty = match (&target, types.get(i)) {
+                (Some(target), Some(ty)) => {
+                    if let Some(value) = values.get(i).filter(|_| values.len() == names.len()) {
+                        self.assign_value(value, ty.clone(), target, "variable declaration");
+                    } else if !self.assignable(None, ty, target) {
+                        self.error(format!("cannot use value of type {} as {} value in assignment", ty, target), name.span);
+                    }
+                    target.clone()
+                }
+                (Some(target), None) => target.clone(),
+                (None, Some(ty)) => self.infer_var_type(values.get(i).filter(|_| values.len() == names.len()), ty, name.span),
+                (None, None) => Ty::Invalid,
+            };
+            self.set_symbol_type(name, ty);
+        }
+    }
+
+    /// The type of a variable declared without a type, from the value it's initialized with.
+    fn infer_var_type(&mut self, value: Option<&Expr>, ty: &Ty, span: Span) -> Ty {
+        match ty {
+            Ty::Untyped(UntypedKind::Nil) => {
+                self.error("use of untyped nil in assignment", span);
+                Ty::Invalid
+            }
+            Ty::Tuple(_) => Ty::Invalid,
+            _ => {
+                let ty = ty.default_type();
+                if let Some(value) = value {
+                    self.set_untyped(value, &ty);
+                }
+                ty
+            }
+        }
+    }
+
+    /// Evaluates the right hand side of an assignment or declaration to `count` variables.
+    ///
+    /// A single call, map index, type assertion or receive can provide several values. Returns no types
+    /// (after reporting an error) if the counts don't match.
+    fn values(&mut self, count: usize, values: &[Expr], context: &str) -> Vec<Ty> {
+        if values.is_empty() {
+            return vec![];
+        }
+        if values.len() == 1 && count > 1 {
+            let value = &values[0];
+            let ty = self.expr(value);
+            let types = match (&ty, &value.kind) {
+                (Ty::Tuple(types), _) => types.clone(),
+                (Ty::Invalid, _) => vec![Ty::Invalid; count],
+                // comma-ok forms
+                (_, ExprKind::Index(..) | ExprKind::TypeAssert(..) | ExprKind::Unary(UnaryOp::Receive, _)) if count == 2 => {
+                    let comma_ok = match &value.kind {
+                        ExprKind::Index(base, _) => matches!(self.underlying(&self.type_of(base)), Ty::Map(..)),
+                        _ => true,
+                    };
+                    if comma_ok {
+                        vec![ty.clone(), bool_ty()]
+                    } else {
+                        vec![ty.clone()]
+                    }
+                }
+                _ => vec![ty.clone()],
+            };
+            if types.len() != count {
+                self.error(
+                    format!("assignment mismatch: {} variables but {} value{}", count, types.len(), plural(types.len())),
+                    value.span,
+                );
+                return vec![Ty::Invalid; count];
+            }
+            return types;
+        }
+        let types = values.iter().map(|value| self.single(value)).collect::<Vec<_>>();
+        if values.len() != count {
+            self.error(
+                format!("assignment mismatch: {} variable{} but {} value{} in {}", count, plural(count), values.len(), plural(values.len()), context),
+                values[0].span,
+            );
+            return vec![Ty::Invalid; count];
+        }
+        types
+    }
+
+    fn func_body(&mut self, func: &FuncDecl) {
+        let body = match &func.body {
+            Some(body) => body,
+            None => return,
+        };
+        let ty = match self.symbol_id(func.name.span).and_then(|id| self.info.symbols.get(&id)) {
+            Some(Ty::Func(ty)) => ty.clone(),
+            _ => return,
+        };
+        self.body(&func.signature, &ty, body);
+    }
+
+    /// Checks a function or function literal body, with the params declared.
+    fn body(&mut self, signature: &Signature, ty: &FuncTy, body: &Block) {
+        for (param, ty) in signature.params.iter().chain(&signature.results).zip(ty.params.iter().chain(&ty.results)) {
+            if let Some(name) = &param.name {
+                self.set_symbol_type(name, ty.clone());
+            }
+        }
+        self.funcs.push(FuncContext {
+            results: ty.results.clone(),
+            named_results: signature.results.iter().any(|result| result.name.is_some()),
+        });
+        self.stmts(&body.stmts);
+        self.funcs.pop();
+        if !ty.results.is_empty() && !is_terminating_list(&body.stmts) {
+            let end = Span {
+                start: body.span.end,
+                ..body.span
+            };
+            self.error("missing return", end);
+        }
+    }
+
+    // ---- types ----
+
+    fn resolve_type(&mut self, ty: &ast::Type) -> Ty {
+        match &ty.kind {
+            TypeKind::Name(name, args) => self.type_name(name, args, ty.span),
+            TypeKind::Array(len, elem) => {
+                let elem = self.resolve_type(elem);
+                let len = match len {
+                    Some(len) => len,
+                    None => {
+                        self.error("invalid use of [...] array (outside a composite literal)", ty.span);
+                        return Ty::Invalid;
+                    }
+                };
+                let len_ty = self.single(len);
+                match self.const_int(len) {
+                    Some(n) if n >= 0 => Ty::Array(n as u64, Box::new(elem)),
+                    Some(_) => {
+                        self.error("invalid array length", len.span);
+                        Ty::Invalid
+                    }
+                    None => {
+                        if len_ty != Ty::Invalid {
+                            self.error("array length must be a constant integer", len.span);
+                        }
+                        Ty::Invalid
+                    }
+                }
+            }
+            TypeKind::Slice(elem) => Ty::Slice(Box::new(self.resolve_type(elem))),
+            TypeKind::Pointer(elem) => Ty::Pointer(Box::new(self.resolve_type(elem))),
+            TypeKind::Struct(fields) => {
+                let mut result: Vec<Field> = vec![];
+                for field in fields {
+                    let field_ty = self.resolve_type(&field.ty);
+                    let names = if field.embedded {
+                        vec![Ident {
+                            name: embedded_name(&field_ty),
+                            span: field.ty.span,
+                        }]
+                    } else {
+                        field.names.clone()
+                    };
+                    for name in names {
+                        if name.name != "_" && result.iter().any(|existing| existing.name == name.name) {
+                            self.error(format!("{} redeclared", name.name), name.span);
+                        }
+                        result.push(Field {
+                            name: name.name,
+                            ty: field_ty.clone(),
+                            embedded: field.embedded,
+                        });
+                    }
+                }
+                Ty::Struct(result)
+            }
+            TypeKind::Function(signature) => Ty::Func(self.signature(signature)),
+            TypeKind::Interface(elems) => {
+                let mut iface = InterfaceTy::default();
+                for elem in elems {
+                    match elem {
+                        InterfaceElem::Method(name, signature) => {
+                            let method = self.signature(signature);
+                            if iface.methods.iter().any(|(existing, _)| existing == &name.name) {
+                                self.error(format!("duplicate method {}", name.name), name.span);
+                            }
+                            iface.methods.push((name.name.clone(), method));
+                        }
+                        InterfaceElem::Union(terms) if terms.len() == 1 && !terms[0].underlying => {
+                            let embedded = self.resolve_type(&terms[0].ty);
+                            iface.embedded.push(embedded);
+                        }
+                        InterfaceElem::Union(terms) => {
+                            let terms = terms
+                                    .iter()
+                                    .map(|term| Term {
+                                        tilde: term.underlying,
+                                        ty: self.resolve_type(&term.ty),
+                                    })
+                                    .collect::<Vec<_>>();
+                            iface.terms.get_or_insert_with(Vec::new).extend(terms);
+                        }
+                    }
+                }
+                iface.methods.sort_by(|a, b| a.0.cmp(&b.0));
+                Ty::Interface(iface)
+            }
+            TypeKind::Map(key, value) => {
+                let key_ty = self.resolve_type(key);
+                let value = self.resolve_type(value);
+                if !self.is_comparable(&key_ty) {
+                    self.error(format!("invalid map key type {}", key_ty), key.span);
+                }
+                Ty::Map(Box::new(key_ty), Box::new(value))
+            }
+            TypeKind::Channel(dir, elem) => Ty::Chan(*dir, Box::new(self.resolve_type(elem))),
+        }
+    }
+
+    fn type_name(&mut self, name: &Ident, args: &[ast::Type], span: Span) -> Ty {
+        let id = match self.symbol_id(name.span) {
+            Some(id) => id,
+            None => return Ty::Invalid,
+        };
+        let symbol = self.table.symbol(id);
+        if symbol.kind != SymbolKind::Type {
+            self.error(format!("{} is not a type", name.name), name.span);
+            return Ty::Invalid;
+        }
+        let args = args.iter().map(|arg| self.resolve_type(arg)).collect::<Vec<_>>();
+        if symbol.span.is_none() {
+            if !args.is_empty() {
+                self.error(format!("{} is not a generic type", name.name), span);
+            }
+            return match name.name.as_str() {
+                "any" => empty_interface(),
+                "comparable" => Ty::Interface(InterfaceTy {
+                    comparable: true,
+                    ..InterfaceTy::default()
+                }),
+                "error" => Ty::Named {
+                    id,
+                    name: name.name.clone(),
+                    args,
+                },
+                other => Ty::Basic(BasicKind::from_name(other).unwrap()),
+            };
+        }
+        if self.constraints.contains_key(&id) {
+            return Ty::TypeParam {
+                id,
+                name: name.name.clone(),
+            };
+        }
+        if let Some(aliased) = self.aliases.get(&id).cloned() {
+            if !self.resolving.insert(id) {
+                self.error(format!("invalid recursive type alias {}", name.name), name.span);
+                return Ty::Invalid;
+            }
+            let ty = self.resolve_type(&aliased);
+            self.resolving.remove(&id);
+            return ty;
+        }
+        let expected = self.named.get(&id).map(|decl| decl.type_params.clone()).unwrap_or_default();
+        if args.len() != expected.len() {
+            if args.is_empty() {
+                self.error(format!("cannot use generic type {} without instantiation", name.name), span);
+            } else {
+                self.error(
+                    format!("got {} type arguments but {} has {} type parameters", args.len(), name.name, expected.len()),
+                    span,
+                );
+            }
+            return Ty::Invalid;
+        }
+        for (arg, param) in args.iter().zip(&expected) {
+            self.check_constraint(arg, *param, span);
+        }
+        Ty::Named {
+            id,
+            name: name.name.clone(),
+            args,
+        }
+    }
+
+    /// The underlying type: the type a named type was defined with, or the type itself.
+    fn underlying(&self, ty: &Ty) -> Ty {
+        let mut ty = ty.clone();
+        // a chain of definitions like `type A B` ends within as many steps as there are types.
+        for _ in 0..=self.named.len() {
+            match &ty {
+                Ty::Named { id, args, .. } => match self.named.get(id) {
+                    Some(decl) => {
+                        let bindings = decl.type_params.iter().copied().zip(args.iter().cloned()).collect();
+                        ty = subst(&decl.underlying, &bindings);
+                    }
+                    None => return Ty::Invalid,
+                },
+                _ => return ty,
+            }
+        }
+        Ty::Invalid
+    }
+
+    /// All methods of an interface, including those of embedded interfaces.
+    fn interface_methods(&self, iface: &InterfaceTy) -> Vec<(String, FuncTy)> {
+        let mut methods = iface.methods.clone();
+        for embedded in &iface.embedded {
+            if let Ty::Interface(inner) = self.underlying(embedded) {
+                for method in self.interface_methods(&inner) {
+                    if !methods.iter().any(|existing| existing.0 == method.0) {
+                        methods.push(method);
+                    }
+                }
+            }
+        }
+        methods.sort_by(|a, b| a.0.cmp(&b.0));
+        methods
+    }
+
+    /// The union terms that restrict an interface's type set, or None if it allows any type.
+    fn interface_terms(&self, iface: &InterfaceTy) -> Option<Vec<Term>> {
+        let mut terms = iface.terms.clone();
+        for embedded in &iface.embedded {
+            let inner = match self.underlying(embedded) {
+                Ty::Interface(inner) => self.interface_terms(&inner),
+                _ => Some(vec![Term {
+                    tilde: false,
+                    ty: embedded.clone(),
+                }]),
+            };
+            if let Some(inner) = inner {
+                // an intersection of unions, simplified to the terms in both.
+                terms = Some(match terms {
+                    None => inner,
+                    Some(outer) => outer.into_iter().filter(|term| inner.contains(term)).collect(),
+                });
+            }
+        }
+        terms
+    }
+
+    /// The terms of a type parameter's constraint, or None if it allows any type.
+    fn type_param_terms(&self, id: SymbolId) -> Option<Vec<Term>> {
+        let constraint = self.constraints.get(&id)?;
+        match self.underlying(constraint) {
+            Ty::Interface(iface) => self.interface_terms(&iface),
+            _ => Some(vec![Term {
+                tilde: false,
+                ty: constraint.clone(),
+            }]),
+        }
+    }
+
+    /// Whether `pred` holds for `ty`, or for every type a type parameter can stand for.
+    fn all_types(&self, ty: &Ty, pred: &dyn Fn(&Ty) -> bool) -> bool {
+        match ty {
+            Ty::TypeParam { id, .. } => match self.type_param_terms(*id) {
+                Some(terms) => !terms.is_empty() && terms.iter().all(|term| pred(&self.underlying(&term.ty))),
+                None => false,
+            },
+            Ty::Invalid => true,
+            _ => pred(&self.underlying(ty)),
+        }
+    }
+
+    fn check_constraint(&mut self, arg: &Ty, param: SymbolId, span: Span) {
+        if !self.satisfies(arg, param) {
+            let constraint = self.constraints.get(&param).cloned().unwrap_or(Ty::Invalid);
+            self.error(format!("{} does not satisfy {}", arg, constraint), span);
+        }
+    }
+
+    fn satisfies(&self, arg: &Ty, param: SymbolId) -> bool {
+        let constraint = match self.constraints.get(&param) {
+            Some(constraint) => constraint.clone(),
+            None => return true,
+        };
+        if matches!(arg, Ty::Invalid) {
+            return true;
+        }
+        if let Some(terms) = self.type_param_terms(param) {
+            let in_type_set = terms.iter().any(|term| {
+                if term.tilde {
+                    self.underlying(arg) == self.underlying(&term.ty)
+                } else {
+                    *arg == term.ty
+                }
+            });
+            if !in_type_set {
+                return false;
+            }
+        }
+        match self.underlying(&constraint) {
+            Ty::Interface(iface) => (!iface.comparable || self.is_comparable(arg)) && self.implements(arg, &iface),
+            _ => true,
+        }
+    }
+
+    /// Whether `ty` has all the methods of `iface`.
+    fn implements(&self, ty: &Ty, iface: &InterfaceTy) -> bool {
+        self.missing_method(ty, iface).is_none()
+    }
+
+    fn missing_method(&self, ty: &Ty, iface: &InterfaceTy) -> Option<String> {
+        let is_pointer = matches!(ty, Ty::Pointer(_));
+        for (name, method) in self.interface_methods(iface) {
+            match self.lookup_member(ty, &name, 0) {
+                Some(Member::Method(found, pointer_receiver)) if found == method && (!pointer_receiver || is_pointer) => {}
+                _ => return Some(name),
+            }
+        }
+        None
+    }
+
+    /// Finds the field or method `name` of `ty`, including ones promoted from embedded fields.
+    fn lookup_member(&self, ty: &Ty, name: &str, depth: usize) -> Option<Member> {
+        let (base, is_pointer) = match ty {
+            Ty::Pointer(inner) => (&**inner, true),
+            _ => (ty, false),
+        };
+        if let Ty::Named { id, args, .. } = base {
+            if let Some(decl) = self.named.get(id) {
+                if let Some(method) = decl.methods.iter().find(|method| method.name == name) {
+                    let bindings = method.receiver_params.iter().copied().zip(args.iter().cloned()).collect();
+                    return Some(Member::Method(subst_func(&method.ty, &bindings), method.pointer_receiver));
+                }
+            }
+        }
+        let constraint = match base {
+            Ty::TypeParam { id, .. } => self.constraints.get(id).cloned(),
+            _ => None,
+        };
+        match self.underlying(constraint.as_ref().unwrap_or(base)) {
+            Ty::Struct(fields) => {
+                if let Some(field) = fields.iter().find(|field| field.name == name) {
+                    return Some(Member::Field(field.ty.clone()));
+                }
+                if depth < 8 {
+                    for field in fields.iter().filter(|field| field.embedded) {
+                        if let Some(member) = self.lookup_member(&field.ty, name, depth + 1) {
+                            return Some(member);
+                        }
+                    }
+                }
+                None
+            }
+            Ty::Interface(iface) if !is_pointer => self
+                    .interface_methods(&iface)
+                    .into_iter()
+                    .find(|(method, _)| method == name)
+                    .map(|(_, ty)| Member::Method(ty, false)),
+            _ => None,
+        }
+    }
+
+    fn is_comparable(&self, ty: &Ty) -> bool {
+        match ty {
+            Ty::TypeParam { id, .. } => match self.constraints.get(id).map(|constraint| self.underlying(constraint)) {
+                Some(Ty::Interface(iface)) => {
+                    iface.comparable
+                        || self.interface_terms(&iface).is_some_and(|terms| {
+                            !terms.is_empty() && terms.iter().all(|term| self.is_comparable(&term.ty))
+                        })
+                }
+                _ => true,
+            },
+            _ => match self.underlying(ty) {
+                Ty::Slice(_) | Ty::Map(..) | Ty::Func(_) => false,
+                Ty::Array(_, elem) => self.is_comparable(&elem),
+                Ty::Struct(fields) => fields.iter().all(|field| self.is_comparable(&field.ty)),
+                _ => true,
+            },
+        }
+    }
+
+    fn is_integer(&self, ty: &Ty) -> bool {
+        match ty {
+            Ty::Untyped(kind) => matches!(kind, UntypedKind::Int | UntypedKind::Rune),
+            _ => self.all_types(ty, &|ty| matches!(ty, Ty::Basic(kind) if kind.is_integer())),
+        }
+    }
+
+    fn is_numeric(&self, ty: &Ty) -> bool {
+        match ty {
+            Ty::Untyped(kind) => matches!(kind, UntypedKind::Int | UntypedKind::Rune | UntypedKind::Float),
+            _ => self.all_types(ty, &|ty| matches!(ty, Ty::Basic(kind) if kind.is_numeric())),
+        }
+    }
+
+    fn is_string(&self, ty: &Ty) -> bool {
+        match ty {
+            Ty::Untyped(kind) => *kind == UntypedKind::String,
+            _ => self.all_types(ty, &|ty| *ty == Ty::Basic(BasicKind::String)),
+        }
+    }
+
+    fn is_boolean(&self, ty: &Ty) -> bool {
+        match ty {
+            Ty::Untyped(kind) => *kind == UntypedKind::Bool,
+            _ => self.all_types(ty, &|ty| *ty == bool_ty()),
+        }
+    }
+
+    fn is_ordered(&self, ty: &Ty) -> bool {
+        match ty {
+            Ty::Untyped(kind) => !matches!(kind, UntypedKind::Bool | UntypedKind::Nil),
+            _ => self.all_types(ty, &|ty| {
+                matches!(ty, Ty::Basic(kind) if kind.is_integer() || kind.is_float() || *kind == BasicKind::String)
+            }),
+        }
+    }
+
+    fn is_nillable(&self, ty: &Ty) -> bool {
+        match ty {
+            Ty::TypeParam { .. } => false,
+            _ => matches!(
+                self.underlying(ty),
+                Ty::Pointer(_) | Ty::Func(_) | Ty::Slice(_) | Ty::Map(..) | Ty::Chan(..) | Ty::Interface(_) | Ty::Invalid
+            ),
+        }
+    }
+
+    /// Whether an untyped constant of `kind` can be represented by `target`.
+    fn representable(&self, value: Option<&Expr>, kind: UntypedKind, target: &Ty) -> bool {
+        if let Ty::TypeParam { id, .. } = target {
+            return match self.type_param_terms(*id) {
+                Some(terms) => !terms.is_empty() && terms.iter().all(|term| self.representable(value, kind, &term.ty)),
+                None => false,
+            };
+        }
+        let basic = match self.underlying(target) {
+            Ty::Basic(basic) => basic,
+            Ty::Invalid => return true,
+            Ty::Interface(iface) => return kind == UntypedKind::Nil || self.interface_methods(&iface).is_empty(),
+            _ => return kind == UntypedKind::Nil && self.is_nillable(target),
+        };
+        match kind {
+            UntypedKind::Bool => basic == BasicKind::Bool,
+            UntypedKind::String => basic == BasicKind::String,
+            UntypedKind::Int | UntypedKind::Rune => basic.is_numeric(),
+            UntypedKind::Float if basic.is_integer() => match value.and_then(|value| self.info.constants.get(&value.span)) {
+                Some(value) => constant::convert(value, basic) != Err(Inexact::Truncated),
+                None => true,
+            },
+            UntypedKind::Float => basic.is_float() || basic.is_complex(),
+            UntypedKind::Nil => false,
+        }
+    }
+
+    /// Whether a value of type `from` can be assigned to a variable of type `to`.
+    fn assignable(&self, value: Option<&Expr>, from: &Ty, to: &Ty) -> bool {
+        if matches!(from, Ty::Invalid) || matches!(to, Ty::Invalid) || from == to {
+            return true;
+        }
+        if let Ty::Untyped(kind) = from {
+            return self.representable(value, *kind, to);
+        }
+        let from_underlying = self.underlying(from);
+        let to_underlying = self.underlying(to);
+        let type_params = matches!(from, Ty::TypeParam { .. }) || matches!(to, Ty::TypeParam { .. });
+        if from_underlying == to_underlying && (!from.is_named() || !to.is_named()) && !type_params {
+            return true;
+        }
+        if let (Ty::Interface(iface), false) = (&to_underlying, matches!(to, Ty::TypeParam { .. })) {
+            return self.implements(from, iface);
+        }
+        if let (Ty::Chan(ChanDir::Both, from_elem), Ty::Chan(_, to_elem)) = (&from_underlying, &to_underlying) {
+            return from_elem == to_elem && (!from.is_named() || !to.is_named());
+        }
+        false
+    }
+
+    // ---- expressions ----
+
+    /// The type recorded for an expression that has already been checked.
+    fn type_of(&self, expr: &Expr) -> Ty {
+        self.info.types.get(&expr.span).cloned().unwrap_or(Ty::Invalid)
+    }
+
+    /// Checks an expression used as a single value.
+    fn single(&mut self, expr: &Expr) -> Ty {
+        match self.expr(expr) {
+            Ty::Tuple(types) if types.is_empty() => {
+                self.error(format!("{} (no value) used as value", describe(expr)), expr.span);
+                Ty::Invalid
+            }
+            Ty::Tuple(_) => {
+                self.error(format!("multiple-value {} in single-value context", describe(expr)), expr.span);
+                Ty::Invalid
+            }
+            ty => ty,
+        }
+    }
+
+    /// Checks that `expr` can be assigned to `target`, converting untyped constants.
+    fn assign(&mut self, expr: &Expr, target: &Ty, context: &str) {
+        let ty = self.single(expr);
+        self.assign_value(expr, ty, target, context);
+    }
+
+    /// Like `assign`, for an expression that has already been checked and has type `ty`.
+    fn assign_value(&mut self, expr: &Expr, ty: Ty, target: &Ty, context: &str) {
+        if ty == Ty::Untyped(UntypedKind::Nil) && !self.is_nillable(target) {
+            self.error(format!("cannot use nil as {} value in {}", target, context), expr.span);
+        } else if !self.assignable(Some(expr), &ty, target) {
+            let reason = match (&ty, self.underlying(target)) {
+                (Ty::Untyped(UntypedKind::Float), Ty::Basic(basic)) if basic.is_integer() => " (truncated)",
+                _ => "",
+            };
+            let missing = match self.underlying(target) {
+                Ty::Interface(iface) if !ty.is_untyped() => self
+                        .missing_method(&ty, &iface)
+                        .map(|method| format!(": {} does not implement {} (missing method {})", ty, target, method)),
+                _ => None,
+            };
+            self.error(
+                format!(
+                    "cannot use {} (type {}) as {} value in {}{}{}",
+                    describe(expr),
+                    ty,
+                    target,
+                    context,
+                    reason,
+                    missing.unwrap_or_default()
+                ),
+                expr.span,
+            );
+        } else if ty.is_untyped() {
+            let converted = match self.underlying(target) {
+                Ty::Interface(_) if !matches!(target, Ty::TypeParam { .. }) => ty.default_type(),
+                _ => target.clone(),
+            };
+            self.set_untyped(expr, &converted);
+        }
+    }
+
+    /// Records the type an untyped expression ended up converted to, and converts its value if it's constant.
+    fn set_untyped(&mut self, expr: &Expr, ty: &Ty) {
+        if !self.type_of(expr).is_untyped() {
+            return;
+        }
+        if let (Some(value), Ty::Basic(kind)) = (self.info.constants.remove(&expr.span), self.underlying(ty)) {
+            match constant::convert(&value, kind) {
+                Ok(converted) => {
+                    self.info.constants.insert(expr.span, converted);
+                }
+                Err(inexact) => self.inexact(&value, inexact, ty, expr.span),
+            }
+        }
+        self.retype(expr, ty);
+    }
+
+    /// Records the type of an untyped expression and its operands. Only the value of the whole expression
+    /// has to fit the type, so the operands' values are left alone.
+    fn retype(&mut self, expr: &Expr, ty: &Ty) {
+        if !self.type_of(expr).is_untyped() {
+            return;
+        }
+        self.record(expr, ty);
+        match &expr.kind {
+            ExprKind::Paren(inner) | ExprKind::Unary(_, inner) => self.retype(inner, ty),
+            ExprKind::Binary(op, lhs, rhs) => match op {
+                _ if is_comparison(*op) => {}
+                BinaryOp::LShift | BinaryOp::RShift => self.retype(lhs, ty),
+                _ => {
+                    self.retype(lhs, ty);
+                    self.retype(rhs, ty);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Checks an expression and records its type. Calls can return a tuple of zero or more values.
+    fn expr(&mut self, expr: &Expr) -> Ty {
+        let ty = self.expr_kind(expr);
+        self.record(expr, &ty);
+        self.fold(expr, &ty);
+        ty
+    }
+
+    fn expr_kind(&mut self, expr: &Expr) -> Ty {
+        match &expr.kind {
+            ExprKind::Ident(name) => self.ident(name, expr.span),
+            ExprKind::IntLit(..) => Ty::Untyped(UntypedKind::Int),
+            ExprKind::FloatLit(..) => Ty::Untyped(UntypedKind::Float),
+            ExprKind::CharLit(..) => Ty::Untyped(UntypedKind::Rune),
+            ExprKind::StringLit(..) => Ty::Untyped(UntypedKind::String),
+            ExprKind::CompositeLit(Some(ty), elements) => {
+                let ty = match &ty.kind {
+                    TypeKind::Array(None, elem) => {
+                        // the length depends on the keys, which are only known once they're checked.
+                        let elem = self.resolve_type(elem);
+                        self.composite(&Ty::Array(u64::MAX, Box::new(elem.clone())), elements, expr.span);
+                        return Ty::Array(self.array_literal_len(elements), Box::new(elem));
+                    }
+                    _ => self.resolve_type(ty),
+                };
+                self.composite(&ty, elements, expr.span);
+                ty
+            }
+            ExprKind::CompositeLit(None, _) => {
+                self.error("invalid composite literal type: missing type", expr.span);
+                Ty::Invalid
+            }
+            ExprKind::FuncLit(signature, body) => {
+                let ty = self.signature(signature);
+                self.body(signature, &ty, body);
+                Ty::Func(ty)
+            }
+            ExprKind::Paren(inner) => self.expr(inner),
+            ExprKind::Selector(base, name) => self.selector(base, name, expr.span),
+            ExprKind::Index(base, index) => {
+                if let Some(generic) = self.generic_func(base) {
+                    return self.instantiate_expr(generic, std::slice::from_ref(index), expr.span);
+                }
+                self.index(base, index)
+            }
+            ExprKind::Instantiation(base, args) => match self.generic_func(base) {
+                Some(generic) => self.instantiate_expr(generic, args, expr.span),
+                None => {
+                    self.error(format!("{} is not a generic function", describe(base)), base.span);
+                    Ty::Invalid
+                }
+            },
+            ExprKind::Slice(base, low, high, max) => self.slice(base, low, high, max, expr.span),
+            ExprKind::TypeAssert(base, ty) => {
+                let base_ty = self.single(base);
+                let ty = match ty {
+                    Some(ty) => self.resolve_type(ty),
+                    None => {
+                        self.error("use of .(type) outside type switch", expr.span);
+                        return Ty::Invalid;
+                    }
+                };
+                self.type_assertion(base, &base_ty, &ty, expr.span);
+                ty
+            }
+            ExprKind::Call(callee, args, spread) => self.call(expr, callee, args, *spread),
+            ExprKind::Unary(op, operand) => self.unary(*op, operand, expr.span),
+            ExprKind::Binary(op, lhs, rhs) => self.binary(*op, lhs, rhs, expr.span),
+            ExprKind::Type(ty) => {
+                let ty = self.resolve_type(ty);
+                self.error(format!("{} (type) is not an expression", ty), expr.span);
+                Ty::Invalid
+            }
+        }
+    }
+
+    fn ident(&mut self, name: &str, span: Span) -> Ty {
+        if name == "_" {
+            self.error("cannot use _ as value", span);
+            return Ty::Invalid;
+        }
+        let id = match self.symbol_id(span) {
+            Some(id) => id,
+            None => return Ty::Invalid,
+        };
+        let symbol = self.table.symbol(id);
+        match &symbol.kind {
+            SymbolKind::Const if symbol.span.is_none() => match name {
+                "iota" if self.iota.is_none() => {
+                    self.error("cannot use iota outside constant declaration", span);
+                    Ty::Invalid
+                }
+                "iota" => Ty::Untyped(UntypedKind::Int),
+                _ => Ty::Untyped(UntypedKind::Bool),
+            },
+            SymbolKind::Var | SymbolKind::Const | SymbolKind::Param | SymbolKind::Func => {
+                if self.generic_funcs.contains_key(&id) {
+                    self.error(format!("cannot use generic function {} without instantiation", name), span);
+                    return Ty::Invalid;
+                }
+                if matches!(symbol.kind, SymbolKind::Var | SymbolKind::Func) {
+                    self.references.push((span, id));
+                }
+                self.global(id);
+                self.info.symbols.get(&id).cloned().unwrap_or(Ty::Invalid)
+            }
+            SymbolKind::Nil => Ty::Untyped(UntypedKind::Nil),
+            SymbolKind::Builtin => {
+                self.error(format!("{} (built-in function) must be called", name), span);
+                Ty::Invalid
+            }
+            SymbolKind::Type => {
+                self.error(format!("{} (type) is not an expression", name), span);
+                Ty::Invalid
+            }
+            SymbolKind::Package(_) => {
+                self.error(format!("use of package {} without selector", name), span);
+                Ty::Invalid
+            }
+        }
+    }
+
+    /// Interprets an expression as a type, if it is one, e.g. the `[]byte` in `[]byte(s)` or `T` in `T(x)`.
+    fn expr_as_type(&mut self, expr: &Expr) -> Option<Ty> {
+        match &expr.kind {
+            ExprKind::Ident(name) => {
+                let id = self.symbol_id(expr.span)?;
+                if self.table.symbol(id).kind != SymbolKind::Type {
+                    return None;
+                }
+                let ident = Ident {
+                    name: name.clone(),
+                    span: expr.span,
+                };
+                Some(self.type_name(&ident, &[], expr.span))
+            }
+            ExprKind::Type(ty) => Some(self.resolve_type(ty)),
+            ExprKind::Paren(inner) => self.expr_as_type(inner),
+            ExprKind::Unary(UnaryOp::Deref, inner) => self.expr_as_type(inner).map(|ty| Ty::Pointer(Box::new(ty))),
+            ExprKind::Index(base, _) | ExprKind::Instantiation(base, _) => {
+                let is_type = matches!(&base.kind, ExprKind::Ident(_))
+                    && self.symbol_id(base.span).map(|id| self.table.symbol(id).kind == SymbolKind::Type) == Some(true);
+                if !is_type {
+                    return None;
+                }
+                let ty = crate::parser::expr_to_type(expr)?;
+                Some(self.resolve_type(&ty))
+            }
+            _ => None,
+        }
+    }
+
+    /// The symbol of a generic function named by `expr`.
+    fn generic_func(&self, expr: &Expr) -> Option<SymbolId> {
+        match &expr.kind {
+            ExprKind::Ident(_) => self.symbol_id(expr.span).filter(|id| self.generic_funcs.contains_key(id)),
+            _ => None,
+        }
+    }
+
+    /// `f[int]`, a generic function instantiated with explicit type arguments.
+    fn instantiate_expr(&mut self, func: SymbolId, args: &[Expr], span: Span) -> Ty {
+        let type_args = args
+                .iter()
+                .map(|arg| match self.expr_as_type(arg) {
+                    Some(ty) => ty,
+                    None => {
+                        self.error(format!("{} is not a type", describe(arg)), arg.span);
+                        Ty::Invalid
+                    }
+                })
+                .collect::<Vec<_>>();
+        let params = self.generic_funcs[&func].clone();
+        if type_args.len() != params.len() {
+            self.error(
+                format!("got {} type arguments but {} has {} type parameters", type_args.len(), self.table.symbol(func).name, params.len()),
+                span,
+            );
+            return Ty::Invalid;
+        }
+        let mut bindings = HashMap::new();
+        for (param, arg) in params.iter().zip(type_args) {
+            self.check_constraint(&arg, *param, span);
+            bindings.insert(*param, arg);
+        }
+        subst(&self.info.symbols[&func], &bindings)
+    }
+
+    fn selector(&mut self, base: &Expr, name: &Ident, span: Span) -> Ty {
+        if let ExprKind::Ident(_) = &base.kind {
+            if let Some(id) = self.symbol_id(base.span) {
+                if let SymbolKind::Package(_) = self.table.symbol(id).kind {
+                    // there's no type information for imported packages.
+                    return Ty::Invalid;
+                }
+            }
+        }
+        // a method expression, T.Method, takes the receiver as its first argument.
+        if let Some(ty) = self.expr_as_type(base) {
+            return match self.lookup_member(&ty, &name.name, 0) {
+                Some(Member::Method(method, _)) => {
+                    let mut params = vec![ty];
+                    params.extend(method.params);
+                    Ty::Func(FuncTy { params, ..method })
+                }
+                _ => {
+                    self.error(format!("{}.{} undefined (type {} has no method {})", ty, name.name, ty, name.name), span);
+                    Ty::Invalid
+                }
+            };
+        }
+        let base_ty = self.single(base);
+        if base_ty == Ty::Invalid {
+            return Ty::Invalid;
+        }
+        match self.lookup_member(&base_ty, &name.name, 0) {
+            Some(Member::Field(ty)) => ty,
+            Some(Member::Method(ty, _)) => Ty::Func(ty),
+            None => {
+                self.error(
+                    format!("{}.{} undefined (type {} has no field or method {})", describe(base), name.name, base_ty, name.name),
+                    span,
+                );
+                Ty::Invalid
+            }
+        }
+    }
+
+    fn index(&mut self, base: &Expr, index: &Expr) -> Ty {
+        let base_ty = self.single(base);
+        let container = match self.underlying(&base_ty) {
+            Ty::Pointer(inner) => match self.underlying(&inner) {
+                array @ Ty::Array(..) => array,
+                _ => Ty::Pointer(inner),
+            },
+            ty => ty,
+        };
+        match container {
+            Ty::Map(key, value) => {
+                self.assign(index, &key, "map index");
+                *value
+            }
+            Ty::Array(len, elem) => {
+                self.check_index(index, Some(len));
+                *elem
+            }
+            Ty::Slice(elem) => {
+                self.check_index(index, None);
+                *elem
+            }
+            Ty::Basic(BasicKind::String) | Ty::Untyped(UntypedKind::String) => {
+                self.check_index(index, None);
+                Ty::Basic(BasicKind::Uint8)
+            }
+            Ty::Invalid => {
+                self.expr(index);
+                Ty::Invalid
+            }
+            _ => {
+                self.expr(index);
+                self.error(format!("invalid operation: cannot index {} (type {})", describe(base), base_ty), base.span);
+                Ty::Invalid
+            }
+        }
+    }
+
+    /// Index and slice bounds must be integers, and constant ones must be in range. Returns whether it's an integer.
+    fn check_index(&mut self, index: &Expr, len: Option<u64>) -> bool {
+        let ty = self.single(index);
+        let integral = ty == Ty::Untyped(UntypedKind::Float) && self.assignable(Some(index), &ty, &int_ty());
+        if !self.is_integer(&ty) && !integral {
+            if ty != Ty::Invalid {
+                self.error(format!("invalid argument: index {} (type {}) must be integer", describe(index), ty), index.span);
+            }
+            return false;
+        }
+        if ty.is_untyped() {
+            self.set_untyped(index, &int_ty());
+        }
+        match (self.const_int(index), len) {
+            (Some(value), _) if value < 0 => {
+                self.error(format!("invalid argument: index {} (constant of type int) must not be negative", value), index.span);
+            }
+            (Some(value), Some(len)) if value as u64 >= len => {
+                self.error(format!("invalid argument: index {} out of bounds [0:{}]", value, len), index.span);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn slice(
+        &mut self,
+        base: &Expr,
+        low: &Option<Box<Expr>>,
+        high: &Option<Box<Expr>>,
+        max: &Option<Box<Expr>>,
+        span: Span,
+    ) -> Ty {
+        let base_ty = self.single(base);
+        for bound in [low, high, max].into_iter().flatten() {
+            self.check_index(bound, None);
+        }
+        let result = match self.underlying(&base_ty) {
+            Ty::Basic(BasicKind::String) => base_ty.clone(),
+            Ty::Untyped(UntypedKind::String) => Ty::Basic(BasicKind::String),
+            Ty::Slice(_) => base_ty.clone(),
+            Ty::Array(_, elem) => Ty::Slice(elem),
+            Ty::Pointer(inner) => match self.underlying(&inner) {
+                Ty::Array(_, elem) => Ty::Slice(elem),
+                _ => {
+                    self.error(format!("cannot slice {} (type {})", describe(base), base_ty), span);
+                    return Ty::Invalid;
+                }
+            },
+            Ty::Invalid => return Ty::Invalid,
+            _ => {
+                self.error(format!("cannot slice {} (type {})", describe(base), base_ty), span);
+                return Ty::Invalid;
+            }
+        };
+        if max.is_some() && self.is_string(&base_ty) {
+            self.error("invalid operation: 3-index slice of string", span);
+        }
+        result
+    }
+
+    fn type_assertion(&mut self, base: &Expr, base_ty: &Ty, ty: &Ty, span: Span) {
+        let iface = match self.underlying(base_ty) {
+            Ty::Interface(iface) if !matches!(base_ty, Ty::TypeParam { .. }) => iface,
+            Ty::Invalid => return,
+            _ => {
+                self.error(format!("invalid operation: {} (type {}) is not an interface", describe(base), base_ty), base.span);
+                return;
+            }
+        };
+        if !matches!(self.underlying(ty), Ty::Interface(_) | Ty::Invalid) {
+            if let Some(method) = self.missing_method(ty, &iface) {
+                self.error(
+                    format!("impossible type assertion: {} does not implement {} (missing method {})", ty, base_ty, method),
+                    span,
+                );
+            }
+        }
+    }
+
+    fn array_literal_len(&self, elements: &[KeyedElement]) -> u64 {
+        // with keys, the length is one past the largest index.
+        let mut len = 0;
+        let mut next = 0;
+        for element in elements {
+            if let Some(key) = &element.key {
+                next = self.const_int(key).unwrap_or(next as i64) as u64;
+            }
+            next += 1;
+            len = len.max(next);
+        }
+        len
+    }
+
+    fn composite(&mut self, ty: &Ty, elements: &[KeyedElement], span: Span) {
+        match self.underlying(ty) {
+            Ty::Struct(fields) => self.struct_literal(ty, &fields, elements, span),
+            Ty::Array(len, elem) => self.indexed_elements(elements, &elem, Some(len), "array or slice literal"),
+            Ty::Slice(elem) => self.indexed_elements(elements, &elem, None, "slice literal"),
+            Ty::Map(key_ty, value_ty) => {
+                for element in elements {
+                    match &element.key {
+                        Some(key) if self.undefined_key(key) => {}
+                        Some(key) => self.element(key, &key_ty, "map literal"),
+                        None => self.error("missing key in map literal", element.value.span),
+                    }
+                    self.element(&element.value, &value_ty, "map literal");
+                }
+            }
+            Ty::Invalid => {
+                for element in elements {
+                    if let ExprKind::CompositeLit(Some(_), _) = element.value.kind {
+                        self.expr(&element.value);
+                    }
+                }
+            }
+            _ => self.error(format!("invalid composite literal type {}", ty), span),
+        }
+    }
+
+    /// The elements of an array or slice literal. Each one's index is its key, or one past the previous one's.
+    fn indexed_elements(&mut self, elements: &[KeyedElement], elem: &Ty, len: Option<u64>, context: &str) {
+        let mut seen = HashSet::new();
+        // None after a key that isn't a valid index, until the next key.
+        let mut next = Some(0);
+        for element in elements {
+            let span = match &element.key {
+                Some(key) => {
+                    next = if self.undefined_key(key) { None } else { self.literal_index(key, len) };
+                    key.span
+                }
+                None => {
+                    match (next, len) {
+                        (Some(index), Some(len)) if index >= len => {
+                            self.error(format!("index {} out of bounds [0:{}]", index, len), element.value.span);
+                        }
+                        _ => {}
+                    }
+                    element.value.span
+                }
+            };
+            if let Some(index) = next {
+                if !seen.insert(index) {
+                    self.error(format!("duplicate index {} in array or slice literal", index), span);
+                }
+            }
+            self.element(&element.value, elem, context);
+            next = next.map(|index| index + 1);
+        }
+    }
+
+    /// The index a key in an array or slice literal stands for, which has to be a non-negative integer constant.
+    fn literal_index(&mut self, key: &Expr, len: Option<u64>) -> Option<u64> {
+        if !self.check_index(key, len) {
+            return None;
+        }
+        match self.const_int(key) {
+            Some(index) => u64::try_from(index).ok(), // check_index reported negative ones
+            None => {
+                self.error(format!("index {} must be integer constant", describe(key)), key.span);
+                None
+            }
+        }
+    }
+
+    /// Sema can't tell a bare identifier key it couldn't resolve from a struct field name, so it's left for
+    /// here, where it's known to be undefined once the literal turns out not to be a struct.
+    fn undefined_key(&mut self, key: &Expr) -> bool {
+        match &key.kind {
+            ExprKind::Ident(name) if name != "_" && self.symbol_id(key.span).is_none() => {
+                self.error(format!("undefined: {}", name), key.span);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn struct_literal(&mut self, ty: &Ty, fields: &[Field], elements: &[KeyedElement], span: Span) {
+        let keyed = elements.iter().filter(|element| element.key.is_some()).count();
+        if keyed > 0 && keyed < elements.len() {
+            self.error("mixture of field:value and value elements in struct literal", span);
+            return;
+        }
+        if keyed == 0 {
+            for (element, field) in elements.iter().zip(fields) {
+                self.element(&element.value, &field.ty, "struct literal");
+            }
+            if !elements.is_empty() && elements.len() < fields.len() {
+                self.error(format!("too few values in struct literal of type {}", ty), span);
+            } else if elements.len() > fields.len() {
+                self.error(format!("too many values in struct literal of type {}", ty), elements[fields.len()].value.span);
+            }
+            return;
+        }
+        let mut seen = HashSet::new();
+        for element in elements {
+            let key = element.key.as_ref().unwrap();
+            let name = match &key.kind {
+                ExprKind::Ident(name) => name,
+                _ => {
+                    self.error(format!("invalid field name {} in struct literal", describe(key)), key.span);
+                    continue;
+                }
+            };
+            match fields.iter().find(|field| &field.name == name) {
+                Some(field) => {
+                    if !seen.insert(name.clone()) {
+                        self.error(format!("duplicate field name {} in struct literal", name), key.span);
+                    }
+                    self.element(&element.value, &field.ty, "struct literal");
+                }
+                None => {
+                    self.error(format!("unknown field {} in struct literal of type {}", name, ty), key.span);
+                    self.expr(&element.value);
+                }
+            }
+        }
+    }
+
+    /// An element of a composite literal, which may itself be a literal with its type elided.
+    fn element(&mut self, value: &Expr, ty: &Ty, context: &str) {
+        match &value.kind {
+            ExprKind::CompositeLit(None, elements) => {
+                // `{...}` for a *T element means `&T{...}`.
+                let literal_ty = match self.underlying(ty) {
+                    Ty::Pointer(inner) => *inner,
+                    _ => ty.clone(),
+                };
+                self.composite(&literal_ty, elements, value.span);
+                self.record(value, ty);
+            }
+            _ => self.assign(value, ty, context),
+        }
+    }
+
+    fn call(&mut self, expr: &Expr, callee: &Expr, args: &[Expr], spread: bool) -> Ty {
+        if let Some(target) = self.expr_as_type(callee) {
+            self.record(callee, &target);
+            if args.len() != 1 || spread {
+                self.error(format!("wrong argument count in conversion to {}", target), expr.span);
+                for arg in args {
+                    self.expr(arg);
+                }
+                return target;
+            }
+            self.conversion(&args[0], &target);
+            return target;
+        }
+        if let ExprKind::Ident(name) = &callee.kind {
+            if let Some(id) = self.symbol_id(callee.span) {
+                if self.table.symbol(id).kind == SymbolKind::Builtin {
+                    return self.builtin(name, args, spread, expr.span);
+                }
+            }
+        }
+
+        let generic = self.generic_func(callee);
+        let func_ty = match generic {
+            Some(id) => self.info.symbols[&id].clone(),
+            None => self.single(callee),
+        };
+        let arg_types = self.args(args);
+        let mut func = match self.underlying(&func_ty) {
+            Ty::Func(func) => func,
+            Ty::Invalid => return Ty::Invalid,
+            _ => {
+                self.error(format!("invalid operation: cannot call non-function {} (type {})", describe(callee), func_ty), callee.span);
+                return Ty::Invalid;
+            }
+        };
+        if let Some(id) = generic {
+            match self.infer(id, &func, &arg_types, expr.span) {
+                Some(bindings) => func = subst_func(&func, &bindings),
+                None => return Ty::Invalid,
+            }
+            self.record(callee, &Ty::Func(func.clone()));
+        }
+        self.check_args(&describe(callee), &func, args, &arg_types, spread, expr.span);
+        match func.results.as_slice() {
+            [result] => result.clone(),
+            results => Ty::Tuple(results.to_vec()),
+        }
+    }
+
+    /// The types of a call's arguments. A single call returning several values spreads them over the params.
+    fn args(&mut self, args: &[Expr]) -> Vec<Ty> {
+        if let [arg] = args {
+            if let Ty::Tuple(types) = self.expr(arg) {
+                if types.len() > 1 {
+                    return types;
+                }
+                self.error(format!("{} (no value) used as value", describe(arg)), arg.span);
+                return vec![Ty::Invalid];
+            }
+            return vec![self.type_of(arg)];
+        }
+        args.iter().map(|arg| self.single(arg)).collect()
+    }
+
+    fn check_args(&mut self, name: &str, func: &FuncTy, args: &[Expr], arg_types: &[Ty], spread: bool, span: Span) {
+        let fixed = if func.variadic { func.params.len() - 1 } else { func.params.len() };
+        if spread && !func.variadic {
+            self.error(format!("have (...) arguments but {} is not variadic", name), span);
+            return;
+        }
+        let count_ok = if func.variadic && !spread { arg_types.len() >= fixed } else { arg_types.len() == func.params.len() };
+        if !count_ok {
+            let problem = if arg_types.len() < func.params.len() { "not enough" } else { "too many" };
+            self.error(
+                format!("{} arguments in call to {} (have ({}), want {})", problem, name, join(arg_types), func.params_string()),
+                span,
+            );
+            return;
+        }
+        for (i, arg_ty) in arg_types.iter().enumerate() {
+            let param = match &func.params.get(i.min(func.params.len() - 1)) {
+                Some(Ty::Slice(elem)) if func.variadic && i >= fixed && !spread => (**elem).clone(),
+                Some(param) => (*param).clone(),
+                None => continue,
+            };
+            let context = format!("argument to {}", name);
+            match args.get(i).filter(|_| args.len() == arg_types.len()) {
+                Some(arg) => self.assign_value(arg, arg_ty.clone(), &param, &context),
+                None if !self.assignable(None, arg_ty, &param) => {
+                    self.error(format!("cannot use value of type {} as {} value in {}", arg_ty, param, context), span);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Infers the type arguments of a generic function call from its arguments.
+    fn infer(&mut self, id: SymbolId, func: &FuncTy, arg_types: &[Ty], span: Span) -> Option<HashMap<SymbolId, Ty>> {
+        let params = self.generic_funcs[&id].clone();
+        let mut bindings = HashMap::new();
+        let param_types = (0..arg_types.len())
+                .filter_map(|i| match func.params.get(i.min(func.params.len().saturating_sub(1))) {
+                    Some(Ty::Slice(elem)) if func.variadic && i + 1 >= func.params.len() => Some((**elem).clone()),
+                    param => param.cloned(),
+                })
+                .collect::<Vec<_>>();
+        // typed arguments first, then untyped constants fill in what's left with their default types.
+        for (param, arg) in param_types.iter().zip(arg_types) {
+            if !arg.is_untyped() {
+                unify(param, arg, &mut bindings);
+            }
+        }
+        // several untyped arguments for the same parameter take the kind that can hold them all, e.g. float for 1 and 2.5.
+        let mut untyped: HashMap<SymbolId, UntypedKind> = HashMap::new();
+        for (param, arg) in param_types.iter().zip(arg_types) {
+            if let (Ty::TypeParam { id, .. }, Ty::Untyped(kind)) = (param, arg) {
+                if *kind != UntypedKind::Nil && !bindings.contains_key(id) {
+                    let widest = untyped.entry(*id).or_insert(*kind);
+                    *widest = (*widest).max(*kind);
+                }
+            }
+        }
+        for (id, kind) in untyped {
+            bindings.insert(id, Ty::Untyped(kind).default_type());
+        }
+        for param in &params {
+            match bindings.get(param).cloned() {
+                Some(arg) => self.check_constraint(&arg, *param, span),
+                None => {
+                    self.error(format!("in call to {}, cannot infer {}", self.table.symbol(id).name, self.table.symbol(*param).name), span);
+                    return None;
+                }
+            }
+        }
+        Some(bindings)
+    }
+
+    fn conversion(&mut self, arg: &Expr, target: &Ty) {
+        let ty = self.single(arg);
+        if ty == Ty::Invalid || *target == Ty::Invalid {
+            return;
+        }
+        if let Ty::Untyped(kind) = ty {
+            // constants convert to types that can represent them, and integers to strings.
+            let convertible = self.representable(Some(arg), kind, target)
+                || (matches!(kind, UntypedKind::Int | UntypedKind::Rune) && self.is_string(target));
+            if convertible {
+                self.set_untyped(arg, &self.conversion_type(&ty, target));
+            } else if !matches!(self.underlying(target), Ty::Basic(_)) && self.convertible(&ty.default_type(), target) {
+                // converting to a type constants can't have, like []byte("hi"), follows the rules for values.
+                self.set_untyped(arg, &ty.default_type());
+            } else {
+                self.error(format!("cannot convert {} (untyped {} constant) to type {}", describe(arg), untyped_name(kind), target), arg.span);
+            }
+            return;
+        }
+        if !self.convertible(&ty, target) {
+            self.error(format!("cannot convert {} (type {}) to type {}", describe(arg), ty, target), arg.span);
+        }
+    }
+
+    fn conversion_type(&self, ty: &Ty, target: &Ty) -> Ty {
+        if self.is_string(target) && ty != &Ty::Untyped(UntypedKind::String) {
+            ty.default_type()
+        } else {
+            target.clone()
+        }
+    }
+
+    fn convertible(&self, from: &Ty, to: &Ty) -> bool {
+        if self.assignable(None, from, to) {
+            return true;
+        }
+        let from_underlying = self.underlying(from);
+        let to_underlying = self.underlying(to);
+        if from_underlying == to_underlying {
+            return true;
+        }
+        if let (Ty::Pointer(a), Ty::Pointer(b)) = (&from_underlying, &to_underlying) {
+            if self.underlying(a) == self.underlying(b) {
+                return true;
+            }
+        }
+        let byte_or_rune_slice = |ty: &Ty| match ty {
+            Ty::Slice(elem) => matches!(self.underlying(elem), Ty::Basic(BasicKind::Uint8 | BasicKind::Int32)),
+            _ => false,
+        };
+        (self.is_numeric(from) && self.is_numeric(to))
+            || (self.is_integer(from) && self.is_string(to))
+            || (self.is_string(from) && byte_or_rune_slice(&to_underlying))
+            || (byte_or_rune_slice(&from_underlying) && self.is_string(to))
+    }
+
+    fn builtin(&mut self, name: &str, args: &[Expr], spread: bool, span: Span) -> Ty {
+        if spread && name != "append" {
+            self.error(format!("invalid operation: invalid use of ... with built-in {}", name), span);
+        }
+        let count = |min: usize, max: usize| args.len() >= min && args.len() <= max;
+        let arity_ok = match name {
+            "len" | "cap" | "close" | "clear" | "new" | "panic" | "real" | "imag" => count(1, 1),
+            "delete" | "copy" | "complex" => count(2, 2),
+            "make" => count(1, 3),
+            "append" | "min" | "max" => count(1, usize::MAX),
+            "recover" => count(0, 0),
+            _ => true, // print and println
+        };
+        if !arity_ok {
+            let problem = if args.is_empty() || (name == "delete" || name == "copy" || name == "complex") && args.len() < 2 {
+                "not enough"
+            } else {
+                "too many"
+            };
+            self.error(format!("{} arguments for {}", problem, name), span);
+            for arg in args {
+                self.expr(arg);
+            }
+            return Ty::Invalid;
+        }
+        match name {
+            "len" | "cap" => {
+                let ty = self.single(&args[0]);
+                let ok = match self.underlying(&ty) {
+                    Ty::Basic(BasicKind::String) | Ty::Untyped(UntypedKind::String) => name == "len",
+                    Ty::Map(..) => name == "len",
+                    Ty::Array(..) | Ty::Slice(_) | Ty::Chan(..) | Ty::Invalid => true,
+                    Ty::Pointer(inner) => matches!(self.underlying(&inner), Ty::Array(..)),
+                    _ => false,
+                };
+                if !ok {
+                    self.error(format!("invalid argument: {} (type {}) for built-in {}", describe(&args[0]), ty, name), args[0].span);
+                } else if ty.is_untyped() {
+                    self.set_untyped(&args[0], &Ty::Basic(BasicKind::String));
+                }
+                int_ty()
+            }
+            "append" => {
+                let ty = self.single(&args[0]);
+                let elem = match self.underlying(&ty) {
+                    Ty::Slice(elem) => *elem,
+                    Ty::Invalid => {
+                        self.args(&args[1..]);
+                        return Ty::Invalid;
+                    }
+                    _ => {
+                        self.error(format!("invalid argument: {} (type {}) is not a slice", describe(&args[0]), ty), args[0].span);
+                        return Ty::Invalid;
+                    }
+                };
+                if spread {
+                    if args.len() != 2 {
+                        self.error("can only use ... with final argument in list", span);
+                        return ty;
+                    }
+                    let rest = self.single(&args[1]);
+                    let bytes_from_string = elem == Ty::Basic(BasicKind::Uint8) && self.is_string(&rest);
+                    if !bytes_from_string {
+                        self.assign_value(&args[1], rest, &Ty::Slice(Box::new(elem)), "argument to append");
+                    }
+                } else {
+                    for arg in &args[1..] {
+                        self.assign(arg, &elem, "argument to append");
+                    }
+                }
+                ty
+            }
+            "make" => {
+                let ty = match self.expr_as_type(&args[0]) {
+                    Some(ty) => ty,
+                    None => {
+                        self.error(format!("{} is not a type", describe(&args[0])), args[0].span);
+                        return Ty::Invalid;
+                    }
+                };
+                let (min, max) = match self.underlying(&ty) {
+                    Ty::Slice(_) => (2, 3),
+                    Ty::Map(..) | Ty::Chan(..) => (1, 2),
+                    Ty::Invalid => (1, 3),
+                    _ => {
+                        self.error(format!("invalid argument: cannot make {}; type must be slice, map, or channel", ty), args[0].span);
+                        return Ty::Invalid;
+                    }
+                };
+                if args.len() < min || args.len() > max {
+                    self.error(format!("invalid operation: make expects {} or {} arguments; found {}", min, max, args.len()), span);
+                }
+                for size in &args[1..] {
+                    self.check_index(size, None);
+                }
+                ty
+            }
+            "new" => match self.expr_as_type(&args[0]) {
+                Some(ty) => Ty::Pointer(Box::new(ty)),
+                None => {
+                    self.error(format!("{} is not a type", describe(&args[0])), args[0].span);
+                    Ty::Invalid
+                }
+            },
+            "delete" => {
+                let ty = self.single(&args[0]);
+                match self.underlying(&ty) {
+                    Ty::Map(key, _) => self.assign(&args[1], &key, "argument to delete"),
+                    Ty::Invalid => {
+                        self.expr(&args[1]);
+                    }
+                    _ => {
+                        self.error(format!("invalid argument: {} (type {}) is not a map", describe(&args[0]), ty), args[0].span);
+                        self.expr(&args[1]);
+                    }
+                }
+                void()
+            }
+            "copy" => {
+                let dst = self.single(&args[0]);
+                let src = self.single(&args[1]);
+                let ok = match (self.underlying(&dst), self.underlying(&src)) {
+                    (Ty::Slice(a), Ty::Slice(b)) => a == b,
+                    (Ty::Slice(a), _) => *a == Ty::Basic(BasicKind::Uint8) && self.is_string(&src),
+                    (Ty::Invalid, _) | (_, Ty::Invalid) => true,
+                    _ => false,
+                };
+                if !ok {
+                    self.error(format!("invalid argument: copy expects slice arguments; found {} and {}", dst, src), span);
+                }
+                int_ty()
+            }
+            "close" => {
+                let ty = self.single(&args[0]);
+                match self.underlying(&ty) {
+                    Ty::Chan(ChanDir::Recv, _) => {
+                        self.error(format!("invalid operation: cannot close receive-only channel {}", describe(&args[0])), args[0].span);
+                    }
+                    Ty::Chan(..) | Ty::Invalid => {}
+                    _ => self.error(format!("invalid operation: cannot close non-channel {}", describe(&args[0])), args[0].span),
+                }
+                void()
+            }
+            "clear" => {
+                let ty = self.single(&args[0]);
+                if !matches!(self.underlying(&ty), Ty::Map(..) | Ty::Slice(_) | Ty::Invalid) {
+                    self.error(format!("invalid argument: {} (type {}) must be a map or slice", describe(&args[0]), ty), args[0].span);
+                }
+                void()
+            }
+            "panic" => {
+                self.assign(&args[0], &empty_interface(), "argument to panic");
+                void()
+            }
+            "print" | "println" => {
+                for arg in args {
+                    let ty = self.single(arg);
+                    if ty == Ty::Untyped(UntypedKind::Nil) {
+                        self.error("use of untyped nil in argument to built-in print", arg.span);
+                    } else if ty.is_untyped() {
+                        self.set_untyped(arg, &ty.default_type());
+                    }
+                }
+                void()
+            }
+            "min" | "max" => {
+                let mut result = self.single(&args[0]);
+                for arg in &args[1..] {
+                    let ty = self.single(arg);
+                    result = self.unify_operands(&args[0], result, arg, ty, name).unwrap_or(Ty::Invalid);
+                }
+                if !self.is_ordered(&result) {
+                    self.error(format!("invalid argument: {} cannot be ordered", result), span);
+                    return Ty::Invalid;
+                }
+                result
+            }
+            "real" | "imag" => {
+                let ty = self.single(&args[0]);
+                match self.underlying(&ty) {
+                    Ty::Basic(BasicKind::Complex64) => Ty::Basic(BasicKind::Float32),
+                    Ty::Untyped(kind) if kind != UntypedKind::Bool && kind != UntypedKind::String && kind != UntypedKind::Nil => {
+                        Ty::Untyped(UntypedKind::Float)
+                    }
+                    Ty::Basic(BasicKind::Complex128) | Ty::Invalid => Ty::Basic(BasicKind::Float64),
+                    _ => {
+                        self.error(format!("invalid argument: {} must be of complex type", describe(&args[0])), args[0].span);
+                        Ty::Invalid
+                    }
+                }
+            }
+            "complex" => {
+                let real = self.single(&args[0]);
+                let imag = self.single(&args[1]);
+                match self.unify_operands(&args[0], real, &args[1], imag, "complex") {
+                    Some(Ty::Basic(BasicKind::Float32)) => Ty::Basic(BasicKind::Complex64),
+                    Some(Ty::Untyped(_)) | Some(Ty::Basic(BasicKind::Float64)) | None => Ty::Basic(BasicKind::Complex128),
+                    Some(ty) => {
+                        self.error(format!("invalid operation: complex arguments have type {}, expected floating-point", ty), span);
+                        Ty::Invalid
+                    }
+                }
+            }
+            "recover" => empty_interface(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn unary(&mut self, op: UnaryOp, operand: &Expr, span: Span) -> Ty {
+        if op == UnaryOp::AddrOf {
+            let ty = match &operand.kind {
+                ExprKind::CompositeLit(..) => self.single(operand),
+                ExprKind::Paren(inner) if matches!(inner.kind, ExprKind::CompositeLit(..)) => self.single(operand),
+                _ => {
+                    let ty = self.single(operand);
+                    if ty != Ty::Invalid && !self.addressable(operand) {
+                        self.error(format!("invalid operation: cannot take address of {}", describe(operand)), span);
+                    }
+                    ty
+                }
+            };
+            return if ty == Ty::Invalid { ty } else { Ty::Pointer(Box::new(ty)) };
+        }
+        let ty = self.single(operand);
+        if ty == Ty::Invalid {
+            return ty;
+        }
+        let ok = match op {
+            UnaryOp::Plus | UnaryOp::Neg => self.is_numeric(&ty),
+            UnaryOp::Not => self.is_boolean(&ty),
+            UnaryOp::BitNot => self.is_integer(&ty),
+            UnaryOp::Deref => {
+                return match self.underlying(&ty) {
+                    Ty::Pointer(elem) => *elem,
+                    _ => {
+                        self.error(format!("invalid operation: cannot indirect {} (type {})", describe(operand), ty), span);
+                        Ty::Invalid
+                    }
+                };
+            }
+            UnaryOp::Receive => {
+                return match self.underlying(&ty) {
+                    Ty::Chan(ChanDir::Send, _) => {
+                        self.error(format!("invalid operation: cannot receive from send-only channel {}", describe(operand)), span);
+                        Ty::Invalid
+                    }
+                    Ty::Chan(_, elem) => *elem,
+                    _ => {
+                        self.error(format!("invalid operation: cannot receive from non-channel {}", describe(operand)), span);
+                        Ty::Invalid
+                    }
+                };
+            }
+            UnaryOp::AddrOf => unreachable!(),
+        };
+        if !ok {
+            self.error(format!("invalid operation: operator {} not defined on {} (type {})", op, describe(operand), ty), span);
+            return Ty::Invalid;
+        }
+        ty
+    }
+
+    fn addressable(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Ident(_) => self
+                    .symbol_id(expr.span)
+                    .map(|id| matches!(self.table.symbol(id).kind, SymbolKind::Var | SymbolKind::Param))
+                    .unwrap_or(false),
+            ExprKind::Paren(inner) => self.addressable(inner),
+            ExprKind::Unary(UnaryOp::Deref, _) => true,
+            ExprKind::Index(base, _) => match self.underlying(&self.type_of(base)) {
+                Ty::Slice(_) | Ty::Pointer(_) => true,
+                Ty::Array(..) => self.addressable(base),
+                _ => false,
+            },
+            ExprKind::Selector(base, _) => match self.underlying(&self.type_of(base)) {
+                Ty::Pointer(_) => true,
+                Ty::Struct(_) => self.addressable(base),
+                Ty::Invalid => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr, span: Span) -> Ty {
+        let lhs_ty = self.single(lhs);
+        let rhs_ty = self.single(rhs);
+        if lhs_ty == Ty::Invalid || rhs_ty == Ty::Invalid {
+            return Ty::Invalid;
+        }
+        if matches!(op, BinaryOp::LShift | BinaryOp::RShift) {
+            return self.shift(lhs, lhs_ty, rhs, rhs_ty, span);
+        }
+        if is_comparison(op) {
+            self.comparison(op, lhs, lhs_ty, rhs, rhs_ty, span);
+            return Ty::Untyped(UntypedKind::Bool);
+        }
+        let ty = match self.unify_operands(lhs, lhs_ty, rhs, rhs_ty, &op.to_string()) {
+            Some(ty) => ty,
+            None => return Ty::Invalid,
+        };
+        let ok = match op {
+            BinaryOp::LogicAnd | BinaryOp::LogicOr => self.is_boolean(&ty),
+            BinaryOp::Add => self.is_numeric(&ty) || self.is_string(&ty),
+            BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => self.is_numeric(&ty),
+            _ => self.is_integer(&ty), // % & | ^ &^
+        };
+        if !ok {
+            self.error(format!("invalid operation: operator {} not defined on {} (type {})", op, describe(lhs), ty), span);
+            return Ty::Invalid;
+        }
+        // like Go, a constant zero divisor is an error for integers, and for floats when the result is constant.
+        let divides_by_zero = self.info.constants.get(&rhs.span).is_some_and(Value::is_zero);
+        if matches!(op, BinaryOp::Div | BinaryOp::Mod) && divides_by_zero && (self.is_integer(&ty) || self.is_constant(lhs)) {
+            self.error("invalid operation: division by zero", rhs.span);
+        }
+        ty
+    }
+
+    /// The common type of two operands, converting an untyped operand to the type of the other.
+    fn unify_operands(&mut self, lhs: &Expr, lhs_ty: Ty, rhs: &Expr, rhs_ty: Ty, op: &str) -> Option<Ty> {
+        let mismatch = |checker: &mut Self| {
+            checker.error(
+                format!("invalid operation: {} {} {} (mismatched types {} and {})", describe(lhs), op, describe(rhs), lhs_ty, rhs_ty),
+                lhs.span.to(rhs.span),
+            );
+            None
+        };
+        match (&lhs_ty, &rhs_ty) {
+            (Ty::Invalid, _) | (_, Ty::Invalid) => Some(Ty::Invalid),
+            (Ty::Untyped(a), Ty::Untyped(b)) => {
+                let numeric = |kind: &UntypedKind| matches!(kind, UntypedKind::Int | UntypedKind::Rune | UntypedKind::Float);
+                if a == b || (numeric(a) && numeric(b)) {
+                    Some(Ty::Untyped(*a.max(b)))
+                } else {
+                    mismatch(self)
+                }
+            }
+            (Ty::Untyped(kind), _) => {
+                if self.representable(Some(lhs), *kind, &rhs_ty) && *kind != UntypedKind::Nil {
+                    self.set_untyped(lhs, &rhs_ty);
+                    Some(rhs_ty)
+                } else {
+                    mismatch(self)
+                }
+            }
+            (_, Ty::Untyped(kind)) => {
+                if self.representable(Some(rhs), *kind, &lhs_ty) && *kind != UntypedKind::Nil {
+                    self.set_untyped(rhs, &lhs_ty);
+                    Some(lhs_ty)
+                } else {
+                    mismatch(self)
+                }
+            }
+            _ if lhs_ty == rhs_ty => Some(lhs_ty),
+            _ => mismatch(self),
+        }
+    }
+
+    fn comparison(&mut self, op: BinaryOp, lhs: &Expr, lhs_ty: Ty, rhs: &Expr, rhs_ty: Ty, span: Span) {
+        let nil = Ty::Untyped(UntypedKind::Nil);
+        // comparing to nil only needs the other operand to be nillable.
+        if lhs_ty == nil || rhs_ty == nil {
+            let (other, other_ty) = if lhs_ty == nil { (rhs, &rhs_ty) } else { (lhs, &lhs_ty) };
+            if *other_ty == nil {
+                self.error(format!("invalid operation: {} {} {} (operator {} not defined on nil)", describe(lhs), op, describe(rhs), op), span);
+            } else if !self.is_nillable(other_ty) {
+                self.error(format!("invalid operation: mismatched types {} and untyped nil", other_ty), span);
+            } else if !matches!(op, BinaryOp::Eq | BinaryOp::NotEq) {
+                self.error(format!("invalid operation: operator {} not defined on nil", op), span);
+            } else if other_ty.is_untyped() {
+                self.set_untyped(other, &other_ty.default_type());
+            }
+            return;
+        }
+        let ty = if lhs_ty.is_untyped() || rhs_ty.is_untyped() {
+            match self.unify_operands(lhs, lhs_ty, rhs, rhs_ty, &op.to_string()) {
+                Some(ty) => ty,
+                None => return,
+            }
+        } else if self.assignable(None, &lhs_ty, &rhs_ty) || self.assignable(None, &rhs_ty, &lhs_ty) {
+            lhs_ty
+        } else {
+            self.error(
+                format!("invalid operation: {} {} {} (mismatched types {} and {})", describe(lhs), op, describe(rhs), lhs_ty, rhs_ty),
+                span,
+            );
+            return;
+        };
+        let ok = match op {
+            BinaryOp::Eq | BinaryOp::NotEq => self.is_comparable(&ty),
+            _ => self.is_ordered(&ty),
+        };
+        if !ok {
+            let reason = match self.underlying(&ty) {
+                Ty::Slice(_) => "slice can only be compared to nil".to_string(),
+                Ty::Map(..) => "map can only be compared to nil".to_string(),
+                Ty::Func(_) => "func can only be compared to nil".to_string(),
+                _ => format!("operator {} not defined on {}", op, ty),
+            };
+            self.error(format!("invalid operation: {} {} {} ({})", describe(lhs), op, describe(rhs), reason), span);
+        }
+    }
+
+    fn shift(&mut self, lhs: &Expr, lhs_ty: Ty, rhs: &Expr, rhs_ty: Ty, span: Span) -> Ty {
+        let count_ok = self.is_integer(&rhs_ty)
+            || (rhs_ty == Ty::Untyped(UntypedKind::Float) && self.assignable(Some(rhs), &rhs_ty, &Ty::Basic(BasicKind::Uint)));
+        if !count_ok {
+            self.error(format!("invalid operation: shift count {} (type {}) must be integer", describe(rhs), rhs_ty), rhs.span);
+            return Ty::Invalid;
+        }
+        if rhs_ty.is_untyped() {
+            self.set_untyped(rhs, &Ty::Basic(BasicKind::Uint));
+        }
+        if let Some(count) = self.const_int(rhs) {
+            if count < 0 {
+                self.error(format!("invalid shift count {} (negative)", count), rhs.span);
+            }
+        }
+        match lhs_ty {
+            Ty::Untyped(UntypedKind::Int | UntypedKind::Rune) => lhs_ty,
+            Ty::Untyped(UntypedKind::Float) if self.assignable(Some(lhs), &lhs_ty, &int_ty()) => Ty::Untyped(UntypedKind::Int),
+            _ if self.is_integer(&lhs_ty) => lhs_ty,
+            _ => {
+                self.error(
+                    format!("invalid operation: shifted operand {} (type {}) must be integer", describe(lhs), lhs_ty),
+                    span,
+                );
+                Ty::Invalid
+            }
+        }
+    }
+
+    // ---- constants ----
+
+    /// Whether an expression is a compile-time constant.
+    fn is_constant(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::IntLit(..) | ExprKind::FloatLit(..) | ExprKind::CharLit(..) | ExprKind::StringLit(..) => true,
+            ExprKind::Ident(_) => self
+                    .symbol_id(expr.span)
+                    .map(|id| self.table.symbol(id).kind == SymbolKind::Const)
+                    .unwrap_or(false),
+            ExprKind::Paren(inner) => self.is_constant(inner),
+            ExprKind::Unary(op, operand) => {
+                !matches!(op, UnaryOp::Deref | UnaryOp::AddrOf | UnaryOp::Receive) && self.is_constant(operand)
+            }
+            ExprKind::Binary(_, lhs, rhs) => self.is_constant(lhs) && self.is_constant(rhs),
+            ExprKind::Call(callee, args, _) => {
+                let basic_conversion = matches!(self.info.types.get(&callee.span).map(|ty| self.underlying(ty)), Some(Ty::Basic(_)))
+                    && matches!(&callee.kind, ExprKind::Ident(_) | ExprKind::Paren(_));
+                let is_len = matches!(&callee.kind, ExprKind::Ident(name) if name == "len")
+                    && args.len() == 1
+                    && self.is_string(&self.type_of(&args[0]));
+                (basic_conversion || is_len) && args.len() == 1 && self.is_constant(&args[0])
+            }
+            _ => false,
+        }
+    }
+
+    /// The value of a constant integer expression that fits in an i64, if it can be worked out.
+    fn const_int(&self, expr: &Expr) -> Option<i64> {
+        self.info.constants.get(&expr.span)?.to_i64()
+    }
+
+    /// Works out the value of a constant expression from the values of its operands, which have already been
+    /// checked. Typed constants must fit their type.
+    fn fold(&mut self, expr: &Expr, ty: &Ty) {
+        let value = match self.constant_value(expr, ty) {
+            Some(value) => value,
+            None => return,
+        };
+        let value = match self.underlying(ty) {
+            Ty::Untyped(_) => value,
+            Ty::Basic(kind) => match constant::convert(&value, kind) {
+                Ok(value) => value,
+                Err(inexact) => return self.inexact(&value, inexact, ty, expr.span),
+            },
+            _ => return,
+        };
+        self.info.constants.insert(expr.span, value);
+    }
+
+    fn inexact(&mut self, value: &Value, inexact: Inexact, ty: &Ty, span: Span) {
+        match inexact {
+            Inexact::Overflows => self.error(format!("constant {} overflows {}", value, ty), span),
+            Inexact::Truncated => self.error(format!("constant {} truncated to integer", value), span),
+        }
+    }
+
+    fn constant_value(&self, expr: &Expr, ty: &Ty) -> Option<Value> {
+        let operand = |expr: &Expr| self.info.constants.get(&expr.span);
+        match &expr.kind {
+            ExprKind::IntLit(_, value) => Some(Value::Int(value.clone())),
+            ExprKind::FloatLit(_, value) => Some(Value::Float(value.clone())),
+            ExprKind::CharLit(_, value) => Some(Value::Int(BigInt::from(*value as u32))),
+            ExprKind::StringLit(_, value) => Some(Value::String(value.clone())),
+            ExprKind::Ident(name) => {
+                let id = self.symbol_id(expr.span)?;
+                let symbol = self.table.symbol(id);
+                match (&symbol.kind, symbol.span) {
+                    (SymbolKind::Const, None) => match name.as_str() {
+                        "iota" => Some(Value::Int(self.iota?.into())),
+                        "true" => Some(Value::Bool(true)),
+                        "false" => Some(Value::Bool(false)),
+                        _ => None,
+                    },
+                    (SymbolKind::Const, Some(_)) => self.const_values.get(&id).cloned(),
+                    _ => None,
+                }
+            }
+            ExprKind::Paren(inner) => operand(inner).cloned(),
+            ExprKind::Unary(op, inner) => {
+                let kind = match self.underlying(ty) {
+                    Ty::Basic(kind) => Some(kind),
+                    _ => None,
+                };
+                constant::unary(*op, operand(inner)?, kind)
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (operand(lhs)?, operand(rhs)?);
+                match op {
+                    _ if is_comparison(*op) => constant::compare(*op, lhs, rhs).map(Value::Bool),
+                    BinaryOp::LShift | BinaryOp::RShift => constant::shift(*op, lhs, rhs),
+                    // a constant division by zero has been reported when checking the operator.
+                    _ => constant::binary(*op, lhs, rhs).ok(),
+                }
+            }
+            ExprKind::Call(callee, args, _) if self.is_constant(expr) => match (operand(&args[0])?, &callee.kind) {
+                (Value::String(value), ExprKind::Ident(name)) if name == "len" => Some(Value::Int(value.len().into())),
+                (Value::Int(code), _) if self.is_string(ty) => {
+                    let char = code.to_u32().and_then(char::from_u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+                    Some(Value::String(char.to_string().into_bytes()))
+                }
+                (value, _) => Some(value.clone()),
+            },
+            _ => None,
+        }
+    }
+
+    // ---- statements ----
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Decl(Decl::Const(specs, _)) => {
+                for (spec, ty, values, iota) in const_group(specs) {
+                    for (i, name) in spec.names.iter().enumerate() {
+                        self.const_decl(name, ty, values.get(i), iota);
+                    }
+                    if values.len() > spec.names.len() {
+                        self.error("extra init expr", values[spec.names.len()].span);
+                    }
+                }
+            }
+            StmtKind::Decl(Decl::Var(specs, _)) => {
+                for spec in specs {
+                    self.var_spec(&spec.names, spec.ty.as_ref(), &spec.values);
+                }
+            }
+            StmtKind::Decl(Decl::Type(specs, _)) => {
+                for spec in specs {
+                    self.declare_type(spec);
+                    self.define_type(spec);
+                }
+            }
+            StmtKind::Labeled(_, inner) => self.stmt(inner),
+            StmtKind::Expr(expr) => self.expr_stmt(expr),
+            StmtKind::Send(channel, value) => {
+                let ty = self.single(channel);
+                match self.underlying(&ty) {
+                    Ty::Chan(ChanDir::Recv, _) => {
+                        self.error(format!("invalid operation: cannot send to receive-only channel {}", describe(channel)), stmt.span);
+                        self.expr(value);
+                    }
+                    Ty::Chan(_, elem) => self.assign(value, &elem, "send"),
+                    Ty::Invalid => {
+                        self.expr(value);
+                    }
+                    _ => {
+                        self.error(format!("invalid operation: cannot send to non-channel {}", describe(channel)), stmt.span);
+                        self.expr(value);
+                    }
+                }
+            }
+            StmtKind::IncDec(target, increment) => {
+                let ty = self.assign_target(target);
+                if let Some(ty) = ty {
+                    if !self.is_numeric(&ty) {
+                        let op = if *increment { "++" } else { "--" };
+                        self.error(format!("invalid operation: {}{} (non-numeric type {})", describe(target), op, ty), stmt.span);
+                    }
+                }
+            }
+            StmtKind::Assign(lhs, None, rhs) => {
+                let types = self.values(lhs.len(), rhs, "assignment");
+                for (i, target) in lhs.iter().enumerate() {
+                    let ty = match types.get(i) {
+                        Some(ty) => ty.clone(),
+                        None => continue,
+                    };
+                    let value = rhs.get(i).filter(|_| rhs.len() == lhs.len());
+                    match self.assign_target(target) {
+                        Some(target_ty) => match value {
+                            Some(value) => self.assign_value(value, ty, &target_ty, "assignment"),
+                            None if !self.assignable(None, &ty, &target_ty) => self.error(
+                                format!("cannot use value of type {} as {} value in assignment", ty, target_ty),
+                                target.span,
+                            ),
+                            None => {}
+                        },
+                        None => {
+                            self.infer_var_type(value, &ty, target.span);
+                        }
+                    }
+                }
+            }
+            StmtKind::Assign(lhs, Some(op), rhs) => {
+                if lhs.len() != 1 || rhs.len() != 1 {
+                    self.error(format!("assignment operation {}= requires single-valued expressions", op), stmt.span);
+                    return;
+                }
+                if let Some(target_ty) = self.assign_target(&lhs[0]) {
+                    let ty = self.binary(*op, &lhs[0], &rhs[0], stmt.span);
+                    if ty != Ty::Invalid && !ty.is_untyped() && ty != target_ty {
+                        self.error(format!("cannot assign {} to {} (type {})", ty, describe(&lhs[0]), target_ty), stmt.span);
+                    }
+                }
+            }
+            StmtKind::ShortVarDecl(names, values) => {
+                let types = self.values(names.len(), values, "assignment");
+                for (i, name) in names.iter().enumerate() {
+                    let ty = types.get(i).cloned().unwrap_or(Ty::Invalid);
+                    let value = values.get(i).filter(|_| values.len() == names.len());
+                    let is_new = self.table.defs.contains_key(&name.span);
+                    if is_new {
+                        let ty = self.infer_var_type(value, &ty, name.span);
+                        self.set_symbol_type(name, ty);
+                    } else if let Some(id) = self.symbol_id(name.span) {
+                        let target = self.info.symbols.get(&id).cloned().unwrap_or(Ty::Invalid);
+                        match value {
+                            Some(value) => self.assign_value(value, ty, &target, "assignment"),
+                            None if !self.assignable(None, &ty, &target) => self.error(
+                                format!("cannot use value of type {} as {} value in assignment", ty, target),
+                                name.span,
+                            ),
+                            None => {}
+                        }
+                    }
+                }
+            }
+            StmtKind::Go(expr) | StmtKind::Defer(expr) => {
+                let keyword = if matches!(stmt.kind, StmtKind::Go(_)) { "go" } else { "defer" };
+                if !matches!(expr.kind, ExprKind::Call(..)) {
+                    self.error(format!("expression in {} must be function call", keyword), expr.span);
+                }
+                self.expr(expr);
+            }
+            StmtKind::Return(values) => self.return_stmt(values, stmt.span),
+            StmtKind::Break(_) | StmtKind::Continue(_) | StmtKind::Goto(_) | StmtKind::Fallthrough | StmtKind::Empty | StmtKind::Bad => {}
+            StmtKind::Block(block) => self.stmts(&block.stmts),
+            StmtKind::If(if_stmt) => {
+                if let Some(init) = &if_stmt.init {
+                    self.stmt(init);
+                }
+                self.condition(&if_stmt.cond, "if statement");
+                self.stmts(&if_stmt.then.stmts);
+                if let Some(otherwise) = &if_stmt.otherwise {
+                    self.stmt(otherwise);
+                }
+            }
+            StmtKind::Switch(switch) => self.switch(switch),
+            StmtKind::TypeSwitch(switch) => self.type_switch(switch),
+            StmtKind::Select(clauses) => {
+                for clause in clauses {
+                    if let Some(comm) = &clause.comm {
+                        self.stmt(comm);
+                    }
+                    self.stmts(&clause.body);
+                }
+            }
+            StmtKind::For(for_stmt) => {
+                if let Some(init) = &for_stmt.init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = &for_stmt.cond {
+                    self.condition(cond, "for loop");
+                }
+                if let Some(post) = &for_stmt.post {
+                    self.stmt(post);
+                }
+                self.stmts(&for_stmt.body.stmts);
+            }
+            StmtKind::Range(range) => self.range(range),
+        }
+    }
+
+    fn expr_stmt(&mut self, expr: &Expr) {
+        let ty = self.expr(expr);
+        let used = match &expr.kind {
+            ExprKind::Call(callee, _, _) => match &callee.kind {
+                ExprKind::Ident(name) if NOT_USED_BUILTINS.contains(&name.as_str()) => {
+                    self.symbol_id(callee.span).map(|id| self.table.symbol(id).kind != SymbolKind::Builtin).unwrap_or(true)
+                }
+                _ => !matches!(self.table.lookup(callee.span).map(|id| &self.table.symbol(id).kind), Some(SymbolKind::Type))
+                    && !matches!(callee.kind, ExprKind::Type(_)),
+            },
+            ExprKind::Unary(UnaryOp::Receive, _) => true,
+            _ => ty == Ty::Invalid,
+        };
+        if !used {
+            self.error(format!("{} (value of type {}) is not used", describe(expr), ty), expr.span);
+        }
+    }
+
+    /// The type of an assignment target, or None for the blank identifier.
+    fn assign_target(&mut self, target: &Expr) -> Option<Ty> {
+        match &target.kind {
+            ExprKind::Ident(name) if name == "_" => {
+                self.record(target, &Ty::Invalid);
+                return None;
+            }
+            ExprKind::Paren(inner) if matches!(&inner.kind, ExprKind::Ident(name) if name == "_") => return None,
+            _ => {}
+        }
+        let ty = self.single(target);
+        if ty == Ty::Invalid {
+            return Some(ty);
+        }
+        let is_map_index = match &target.kind {
+            ExprKind::Index(base, _) => matches!(self.underlying(&self.type_of(base)), Ty::Map(..)),
+            _ => false,
+        };
+        if !is_map_index && !self.addressable(target) {
+            self.error(format!("cannot assign to {} (neither addressable nor a map index expression)", describe(target)), target.span);
+            return Some(Ty::Invalid);
+        }
+        Some(ty)
+    }
+
+    fn condition(&mut self, cond: &Expr, context: &str) {
+        let ty = self.single(cond);
+        if !self.is_boolean(&ty) {
+            self.error(format!("non-boolean condition in {}", context), cond.span);
+        } else if ty.is_untyped() {
+            self.set_untyped(cond, &bool_ty());
+        }
+    }
+
+    fn return_stmt(&mut self, values: &[Expr], span: Span) {
+        let (results, named_results) = match self.funcs.last() {
+            Some(func) => (func.results.clone(), func.named_results),
+            None => return,
+        };
+        if values.is_empty() {
+            if !results.is_empty() && !named_results {
+                self.error(format!("not enough return values (have (), want ({}))", join(&results)), span);
+            }
+            return;
+        }
+        let types = if values.len() == 1 && results.len() != 1 {
+            match self.expr(&values[0]) {
+                Ty::Tuple(types) if !types.is_empty() => types,
+                Ty::Tuple(_) => {
+                    self.error(format!("{} (no value) used as value", describe(&values[0])), values[0].span);
+                    return;
+                }
+                ty => vec![ty],
+            }
+        } else {
+            values.iter().map(|value| self.single(value)).collect()
+        };
+        if types.len() != results.len() {
+            let problem = if types.len() < results.len() { "not enough" } else { "too many" };
+            self.error(format!("{} return values (have ({}), want ({}))", problem, join(&types), join(&results)), values[0].span);
+            return;
+        }
+        for (i, (ty, result)) in types.into_iter().zip(&results).enumerate() {
+            match values.get(i).filter(|_| values.len() == results.len()) {
+                Some(value) => self.assign_value(value, ty, result, "return statement"),
+                None if !self.assignable(None, &ty, result) => {
+                    self.error(format!("cannot use value of type {} as {} value in return statement", ty, result), span);
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn switch(&mut self, switch: &SwitchStmt) {
+        if let Some(init) = &switch.init {
+            self.stmt(init);
+        }
+        let tag = switch.tag.as_ref().map(|tag| {
+            let ty = self.single(tag);
+            let ty = self.infer_var_type(Some(tag), &ty, tag.span);
+            (tag, ty)
+        });
+        for clause in &switch.clauses {
+            for expr in &clause.exprs {
+                match &tag {
+                    Some((tag, tag_ty)) => {
+                        let ty = self.single(expr);
+                        self.comparison(BinaryOp::Eq, tag, tag_ty.clone(), expr, ty, expr.span);
+                    }
+                    None => self.condition(expr, "case clause"),
+                }
+            }
+            self.stmts(&clause.body);
+        }
+    }
+
+    fn type_switch(&mut self, switch: &TypeSwitchStmt) {
+        if let Some(init) = &switch.init {
+            self.stmt(init);
+        }
+        let operand = match &switch.expr.kind {
+            ExprKind::TypeAssert(operand, None) => operand,
+            _ => return,
+        };
+        let operand_ty = self.single(operand);
+        let iface = match self.underlying(&operand_ty) {
+            Ty::Interface(iface) if !matches!(operand_ty, Ty::TypeParam { .. }) => Some(iface),
+            Ty::Invalid => None,
+            _ => {
+                self.error(format!("{} (type {}) is not an interface", describe(operand), operand_ty), operand.span);
+                None
+            }
+        };
+        self.record(&switch.expr, &operand_ty);
+        for clause in &switch.clauses {
+            let mut types = vec![];
+            for ty in &clause.types {
+                let is_nil = matches!(&ty.kind, TypeKind::Name(name, args)
+                    if args.is_empty() && self.symbol_id(name.span).map(|id| self.table.symbol(id).kind == SymbolKind::Nil) == Some(true));
+                if is_nil {
+                    types.push(Ty::Untyped(UntypedKind::Nil));
+                    continue;
+                }
+                let case_ty = self.resolve_type(ty);
+                if let Some(iface) = &iface {
+                    if !matches!(self.underlying(&case_ty), Ty::Interface(_) | Ty::Invalid) {
+                        if let Some(method) = self.missing_method(&case_ty, iface) {
+                            self.error(
+                                format!("impossible type switch case: {} cannot have dynamic type {} (missing method {})", describe(operand), case_ty, method),
+                                ty.span,
+                            );
+                        }
+                    }
+                }
+                types.push(case_ty);
+            }
+            if let Some(binding) = &switch.binding {
+                // in a clause with exactly one type the binding has that type, otherwise the operand's type.
+                let ty = match types.as_slice() {
+                    [ty] if *ty != Ty::Untyped(UntypedKind::Nil) => ty.clone(),
+                    _ => operand_ty.clone(),
+                };
+                self.set_symbol_type(binding, ty);
+            }
+            self.stmts(&clause.body);
+        }
+    }
+
+    fn range(&mut self, range: &RangeStmt) {
+        let ty = self.single(&range.expr);
+        let container = match self.underlying(&ty) {
+            Ty::Pointer(inner) => match self.underlying(&inner) {
+                array @ Ty::Array(..) => array,
+                _ => Ty::Pointer(inner),
+            },
+            container => container,
+        };
+        let (key, value) = match container {
+            Ty::Basic(BasicKind::String) | Ty::Untyped(UntypedKind::String) => (int_ty(), Some(Ty::Basic(BasicKind::Int32))),
+            Ty::Array(_, elem) | Ty::Slice(elem) => (int_ty(), Some(*elem)),
+            Ty::Map(key, value) => (*key, Some(*value)),
+            Ty::Chan(ChanDir::Send, _) => {
+                self.error(format!("invalid operation: range {} receive from send-only channel", describe(&range.expr)), range.expr.span);
+                (Ty::Invalid, Some(Ty::Invalid))
+            }
+            Ty::Chan(_, elem) => (*elem, None),
+            Ty::Invalid => (Ty::Invalid, Some(Ty::Invalid)),
+            _ if self.is_integer(&ty) => {
+                let ty = ty.default_type();
+                self.set_untyped(&range.expr, &ty);
+                (ty, None)
+            }
+            _ => {
+                self.error(format!("cannot range over {} (type {})", describe(&range.expr), ty), range.expr.span);
+                (Ty::Invalid, Some(Ty::Invalid))
+            }
+        };
+        let value = match (&range.value, value) {
+            (Some(target), None) => {
+                self.error(format!("range over {} permits only one iteration variable", describe(&range.expr)), target.span);
+                Ty::Invalid
+            }
+            (_, value) => value.unwrap_or(Ty::Invalid),
+        };
+        for (target, ty) in range.key.iter().zip([key]).chain(range.value.iter().zip([value])) {
+            match &target.kind {
+                ExprKind::Ident(name) if range.define => {
+                    let ident = Ident {
+                        name: name.clone(),
+                        span: target.span,
+                    };
+                    self.record(target, &ty);
+                    self.set_symbol_type(&ident, ty);
+                }
+                _ => {
+                    if let Some(target_ty) = self.assign_target(target) {
+                        if !self.assignable(None, &ty, &target_ty) {
+                            self.error(format!("cannot use value of type {} as {} value in range", ty, target_ty), target.span);
+                        }
+                    }
+                }
+            }
+        }
+        self.stmts(&range.body.stmts);
+    }
+}
+
+/// The specs of a const group with omitted values filled in from the previous spec, along with their iota.
+fn const_group(specs: &[ConstSpec]) -> Vec<(&ConstSpec, Option<&ast::Type>, &[Expr], i64)> {
+    let mut result = vec![];
+    let mut previous: Option<(Option<&ast::Type>, &[Expr])> = None;
+    for (iota, spec) in specs.iter().enumerate() {
+        let (ty, values) = match previous {
+            Some(previous) if spec.values.is_empty() && spec.ty.is_none() => previous,
+            _ => (spec.ty.as_ref(), spec.values.as_slice()),
+        };
+        previous = Some((ty, values));
+        result.push((spec, ty, values, iota as i64));
+    }
+    result
+}
+
+/// Binds type parameters in `param` to the matching parts of `arg`.
+fn unify(param: &Ty, arg: &Ty, bindings: &mut HashMap<SymbolId, Ty>) {
+    match (param, arg) {
+        (Ty::TypeParam { id, .. }, _) => {
+            bindings.entry(*id).or_insert_with(|| arg.clone());
+        }
+        (Ty::Slice(a), Ty::Slice(b)) | (Ty::Pointer(a), Ty::Pointer(b)) | (Ty::Array(_, a), Ty::Array(_, b)) | (Ty::Chan(_, a), Ty::Chan(_, b)) => {
+            unify(a, b, bindings)
+        }
+        (Ty::Map(k1, v1), Ty::Map(k2, v2)) => {
+            unify(k1, k2, bindings);
+            unify(v1, v2, bindings);
+        }
+        (Ty::Named { id: a, args: a_args, .. }, Ty::Named { id: b, args: b_args, .. }) if a == b => {
+            for (a, b) in a_args.iter().zip(b_args) {
+                unify(a, b, bindings);
+            }
+        }
+        (Ty::Func(a), Ty::Func(b)) => {
+            for (a, b) in a.params.iter().zip(&b.params).chain(a.results.iter().zip(&b.results)) {
+                unify(a, b, bindings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The field name of an embedded field, which is its type name.
+fn embedded_name(ty: &Ty) -> String {
+    match ty {
+        Ty::Pointer(inner) => embedded_name(inner),
+        Ty::Named { name, .. } | Ty::TypeParam { name, .. } => name.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn untyped_name(kind: UntypedKind) -> &'static str {
+    match kind {
+        UntypedKind::Bool => "bool",
+        UntypedKind::Int => "int",
+        UntypedKind::Rune => "rune",
+        UntypedKind::Float => "float",
+        UntypedKind::String => "string",
+        UntypedKind::Nil => "nil",
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+fn is_comparison(op: BinaryOp) -> bool {
+    op.precedence() == 3
+}
+
+/// A short description of an expression for error messages, e.g. `x`, `p.name` or `f()`.
+fn describe(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Ident(name) => name.clone(),
+        ExprKind::IntLit(text, _) | ExprKind::FloatLit(text, _) => text.clone(),
+        ExprKind::CharLit(raw, _) | ExprKind::StringLit(raw, _) => raw.clone(),
+        ExprKind::Paren(inner) => format!("({})", describe(inner)),
+        ExprKind::Selector(base, name) => format!("{}.{}", describe(base), name.name),
+        ExprKind::Index(base, index) => format!("{}[{}]", describe(base), describe(index)),
+        ExprKind::Call(callee, _, _) => format!("{}()", describe(callee)),
+        ExprKind::Unary(op, operand) => format!("{}{}", op, describe(operand)),
+        ExprKind::Binary(op, lhs, rhs) => format!("{} {} {}", describe(lhs), op, describe(rhs)),
+        ExprKind::CompositeLit(..) => "composite literal".to_string(),
+        ExprKind::FuncLit(..) => "func literal".to_string(),
+        ExprKind::Slice(base, ..) => format!("{}[:]", describe(base)),
+        ExprKind::TypeAssert(base, _) => format!("{}.(...)", describe(base)),
+        ExprKind::Instantiation(base, _) => format!("{}[...]", describe(base)),
+        ExprKind::Type(_) => "type".to_string(),
+    }
+}
+
+/// Whether a statement list ends in a terminating statement, so a function can't fall off its end.
+fn is_terminating_list(stmts: &[Stmt]) -> bool {
+    stmts.iter().rev().find(|stmt| stmt.kind != StmtKind::Empty).is_some_and(is_terminating)
+}
+
+fn is_terminating(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::Return(_) | StmtKind::Goto(_) => true,
+        StmtKind::Expr(Expr {
+            kind: ExprKind::Call(callee, _, _),
+            ..
+        }) => matches!(&callee.kind, ExprKind::Ident(name) if name == "panic"),
+        StmtKind::Block(block) => is_terminating_list(&block.stmts),
+        StmtKind::If(if_stmt) => {
+            is_terminating_list(&if_stmt.then.stmts) && if_stmt.otherwise.as_ref().is_some_and(|otherwise| is_terminating(otherwise))
+        }
+        StmtKind::For(for_stmt) => for_stmt.cond.is_none() && !has_break(&for_stmt.body.stmts, None, true),
+        StmtKind::Labeled(label, inner) => match &inner.kind {
+            StmtKind::For(for_stmt) => for_stmt.cond.is_none() && !has_break(&for_stmt.body.stmts, Some(&label.name), true),
+            StmtKind::Switch(_) | StmtKind::TypeSwitch(_) | StmtKind::Select(_) => {
+                is_terminating(inner) && !clause_bodies(inner).iter().any(|body| has_break(body, Some(&label.name), true))
+            }
+            _ => is_terminating(inner),
+        },
+        StmtKind::Switch(switch) => {
+            switch.clauses.iter().any(|clause| clause.is_default)
+                && switch.clauses.iter().all(|clause| {
+                    !has_break(&clause.body, None, true)
+                        && (is_terminating_list(&clause.body) || matches!(clause.body.last(), Some(Stmt { kind: StmtKind::Fallthrough, .. })))
+                })
+        }
+        StmtKind::TypeSwitch(switch) => {
+            switch.clauses.iter().any(|clause| clause.is_default)
+                && switch.clauses.iter().all(|clause| !has_break(&clause.body, None, true) && is_terminating_list(&clause.body))
+        }
+        StmtKind::Select(clauses) => clauses
+                .iter()
+                .all(|clause| !has_break(&clause.body, None, true) && is_terminating_list(&clause.body)),
+        _ => false,
+    }
+}
+
+fn clause_bodies(stmt: &Stmt) -> Vec<&[Stmt]> {
+    match &stmt.kind {
+        StmtKind::Switch(switch) => switch.clauses.iter().map(|clause| clause.body.as_slice()).collect(),
+        StmtKind::TypeSwitch(switch) => switch.clauses.iter().map(|clause| clause.body.as_slice()).collect(),
+        StmtKind::Select(clauses) => clauses.iter().map(|clause| clause.body.as_slice()).collect(),
+        _ => vec![],
+    }
+}
+
+/// Whether `stmts` contain a break out of the enclosing statement: an unlabeled one (if `unlabeled`) that
+/// isn't inside a nested loop, switch or select, or one naming `label`.
+fn has_break(stmts: &[Stmt], label: Option<&str>, unlabeled: bool) -> bool {
+    stmts.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Break(None) => unlabeled,
+        StmtKind::Break(Some(name)) => Some(name.name.as_str()) == label,
+        StmtKind::Block(block) => has_break(&block.stmts, label, unlabeled),
+        StmtKind::Labeled(_, inner) => has_break(std::slice::from_ref(&**inner), label, unlabeled),
+        StmtKind::If(if_stmt) => {
+            has_break(&if_stmt.then.stmts, label, unlabeled)
+                || if_stmt.otherwise.as_ref().is_some_and(|otherwise| has_break(std::slice::from_ref(&**otherwise), label, unlabeled))
+        }
+        StmtKind::For(for_stmt) => has_break(&for_stmt.body.stmts, label, false),
+        StmtKind::Range(range) => has_break(&range.body.stmts, label, false),
+        StmtKind::Switch(_) | StmtKind::TypeSwitch(_) | StmtKind::Select(_) => {
+            clause_bodies(stmt).iter().any(|body| has_break(body, label, false))
+        }
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+pub mod typechecker_test {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::sema;
+    use super::{TypeError, TypeInfo};
+
+    fn check(file_name: &str) -> Result<(TypeInfo, sema::SymbolTable), Vec<TypeError>> {
+        let program = Parser::new(Lexer::new(file_name.to_string()).unwrap()).parse().unwrap();
+        let table = sema::analyze(&program).unwrap();
+        super::check(&program, &table).map(|info| (info, table))
+    }
+
+    /// The type of the first declared variable or constant called `name`.
+    fn symbol_type(info: &TypeInfo, table: &sema::SymbolTable, name: &str) -> String {
+        let id = table.symbols.iter().position(|symbol| symbol.name == name && symbol.span.is_some()).unwrap();
+        info.symbols[&id].to_string()
+    }
+
+    /// The type of the expression starting at `line`:`column`.
+    fn expr_type(info: &TypeInfo, line: u32, column: u32) -> String {
+        let (_, ty) = info.types.iter().find(|(span, _)| span.start.line == line && span.start.column == column).unwrap();
+        ty.to_string()
+    }
+
+    #[test]
+    pub fn given_valid_program_should_type_every_expression() {
+        let (info, table) = check("testfiles/types.test").unwrap();
+        assert_eq!(symbol_type(&info, &table, "mx"), "float64");
+        assert_eq!(symbol_type(&info, &table, "strs"), "[]string");
+        assert_eq!(symbol_type(&info, &table, "top"), "int");
+        assert_eq!(symbol_type(&info, &table, "bs"), "[]uint8");
+        assert_eq!(symbol_type(&info, &table, "days"), "[]Weekday");
+        assert_eq!(symbol_type(&info, &table, "Pop"), "func() (T, bool)");
+        assert_eq!(symbol_type(&info, &table, "KB"), "untyped int");
+        // untyped constants take the type they're used as, or their default type.
+        assert_eq!(symbol_type(&info, &table, "r"), "int32");
+        assert_eq!(expr_type(&info, 155, 10), "float64");
+        assert_eq!(expr_type(&info, 155, 10), expr_type(&info, 155, 15));
+        // constants converted to types constants can't have.
+        assert_eq!(expr_type(&info, 170, 12), "[]uint8");
+        assert_eq!(expr_type(&info, 170, 26), "[]int32");
+        assert_eq!(expr_type(&info, 170, 40), "[]uint8");
+        assert_eq!(expr_type(&info, 170, 47), "string");
+    }
+
+    #[test]
+    pub fn given_type_errors_should_report_all_of_them() {
+        let errors = check("testfiles/illegal_types.test").unwrap_err();
+        let messages = errors.iter().map(|error| error.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "ERROR: invalid operation: operator % not defined on x (type float64) on line 4",
+                "ERROR: invalid operation: operator &^ not defined on a (type float64) on line 8",
+                "ERROR: invalid operation: shifted operand a (type float64) must be integer on line 9",
+                "ERROR: invalid operation: b + \"s\" (mismatched types int and untyped string) on line 10",
+                "ERROR: non-boolean condition in if statement on line 14",
+                "ERROR: not enough return values (have (int), want (int, bool)) on line 15",
+                "ERROR: too many return values (have (int, untyped bool, untyped bool), want (int, bool)) on line 17",
+                "ERROR: missing return on line 24",
+                "ERROR: too many arguments in call to half (have (untyped int, untyped int), want (float64)) on line 27",
+                "ERROR: cannot use 2.5 (type untyped float) as int value in variable declaration (truncated) on line 28",
+                "ERROR: not enough arguments in call to mask (have (float64), want (float64, int)) on line 29",
+                "ERROR: cannot use mask() (type int) as string value in variable declaration on line 29",
+                "ERROR: multiple-value pick() in single-value context on line 30",
+                "ERROR: cannot use s (type string) as int value in assignment on line 32",
+                "ERROR: too many arguments in call to main (have (float64, int, string, invalid type, bool), want ()) on line 33",
+                "ERROR: undefined: undefinedKey on line 37",
+                "ERROR: index n must be integer constant on line 39",
+                "ERROR: duplicate index 0 in array or slice literal on line 40",
+                "ERROR: duplicate index 1 in array or slice literal on line 41",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn given_constants_that_dont_fit_their_type_should_report_overflow() {
+        let errors = check("testfiles/illegal_constants.test").unwrap_err();
+        let messages = errors.iter().map(|error| error.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "ERROR: constant 300 overflows uint8 on line 8",
+                "ERROR: constant 200 overflows int8 on line 9",
+                "ERROR: constant 18446744073709551616 overflows int64 on line 10",
+                "ERROR: constant -1 overflows uint on line 11",
+                "ERROR: constant 1267650600228229401496703205376 overflows int on line 12",
+                "ERROR: invalid operation: division by zero on line 14",
+                "ERROR: constant 1e+400 overflows float64 on line 18",
+                "ERROR: invalid operation: division by zero on line 22",
+                "ERROR: invalid operation: division by zero on line 23",
+                "ERROR: cannot evaluate constant 1 << 20000 on line 24",
+            ]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Lexes every `testfiles/*.test` with `--tokens` and compares the output, token by token, with the
+//! expected stream in the matching `.tokens` file. Lexical errors are reported on stderr as JSON diagnostics and
+//! follow the tokens in the stream. The compiler must also fail exactly when the stream has errors.
+//!
+//! After an intended change to the lexer, regenerate the expected streams with
+//! `cargo test --test lexer_conformance -- --bless` and review the diff.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+/// Runs the compiler on `test` and returns the tokens it printed for `--tokens` followed by any errors, and whether
+/// it exited successfully.
+fn dump_tokens(test: &Path) -> (String, bool) {
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+            .arg("-i")
+            .arg(test)
+            .arg("--tokens")
+            .arg("--error-format=json")
+            .output()
+            .expect("could not run the compiler");
+    let mut stream = String::from_utf8_lossy(&output.stdout).into_owned();
+    stream.push_str(&String::from_utf8_lossy(&output.stderr));
+    (stream, output.status.success())
+}
+
+/// Describes a mismatch between the exit status and the token stream, which should fail exactly when it has errors.
+fn check_status(tokens: &str, success: bool) -> Option<String> {
+    let has_errors = tokens.lines().any(|line| line.starts_with("{\"severity\":\"error\""));
+    match (has_errors, success) {
+        (false, false) => Some("the compiler failed on a token stream without errors".to_string()),
+        (true, true) => Some("the compiler succeeded on a token stream with errors".to_string()),
+        _ => None,
+    }
+}
+
+/// Describes the first line where the token streams differ, or None if they're the same.
+fn compare(expected: &str, actual: &str) -> Option<String> {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return None,
+            (Some(expected), Some(actual)) if expected == actual => {}
+            (expected, actual) => {
+                return Some(format!(
+                    "line {}: expected `{}`, found `{}`",
+                    line,
+                    expected.unwrap_or("<end of tokens>"),
+                    actual.unwrap_or("<end of tokens>")
+                ))
+            }
+        }
+    }
+    unreachable!()
+}
+
+fn main() {
+    let bless = env::args().any(|arg| arg == "--bless");
+    let mut tests = fs::read_dir("testfiles")
+            .expect("could not read testfiles")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "test"))
+            .collect::<Vec<PathBuf>>();
+    tests.sort();
+
+    let mut failures = vec![];
+    for test in &tests {
+        let (actual, success) = dump_tokens(test);
+        let expected_file = test.with_extension("tokens");
+        if bless {
+            fs::write(&expected_file, actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&expected_file) {
+            Ok(expected) => {
+                if let Some(difference) = compare(&expected, &actual).or_else(|| check_status(&actual, success)) {
+                    failures.push(format!("{}: {}", test.display(), difference));
+                }
+            }
+            Err(_) => failures.push(format!("{}: missing {}", test.display(), expected_file.display())),
+        }
+    }
+
+    if bless {
+        println!("blessed {} token streams", tests.len());
+    } else if failures.is_empty() {
+        println!("{} token streams match", tests.len());
+    } else {
+        for failure in &failures {
+            eprintln!("{}", failure);
+        }
+        eprintln!("{} of {} token streams differ (rerun with --bless if the change is intended)", failures.len(), tests.len());
+        process::exit(1);
+    }
+}